@@ -39,6 +39,22 @@ impl DictionaryExt for lopdf::Dictionary {
     }
 }
 
+pub trait StreamExt {
+    /// Returns the stream content with its filters applied, falling back to the raw
+    /// content if the filters aren't supported.
+    fn decoded_content(&self) -> Vec<u8>;
+}
+
+impl StreamExt for lopdf::Stream {
+    fn decoded_content(&self) -> Vec<u8> {
+        if let Some(content) = self.decompressed_content() {
+            content
+        } else {
+            self.content.clone()
+        }
+    }
+}
+
 pub trait ObjectExt {
     fn try_as_stream(&self) -> Fallible<&lopdf::Stream>;
 
@@ -1,9 +1,10 @@
 use std::io;
+use std::rc::Rc;
 use std::sync::Arc;
 
 use failure::Fallible;
 
-pub use rpdf_graphics::font::{Font, FontMap};
+pub use rpdf_graphics::font::{default_font_directories, set_font_directories, Font, FontMap};
 pub use rpdf_graphics::text::{TextFragment, TextObject};
 use rpdf_graphics::*;
 
@@ -29,18 +30,21 @@ impl Document {
                     .ok_or_else(|| failure::format_err!("page is missing dictionary"))?;
                 let media_box = document.deserialize_object(page_dict.try_get(b"MediaBox")?)?;
                 let content = document.get_page_content(*object_id)?;
-                let font_map =
-                    FontMap::try_from_page_fonts(&document, document.get_page_fonts(*object_id))?;
-                let graphics_objects =
-                    GraphicsObjectDecoder::decode(document.clone(), &font_map, &content)?
-                        .collect::<Fallible<_>>()?;
+                let resources = resources::Resources::from_page(document.clone(), *object_id);
+                let font_map = FontMap::try_from_resources(&resources)?;
+                let graphics_objects = GraphicsObjectDecoder::decode(
+                    document.clone(),
+                    Rc::new(resources),
+                    Rc::new(font_map),
+                    &content,
+                )?
+                .collect::<Fallible<_>>()?;
 
                 Ok(Page {
                     document: document.clone(),
                     object_id: *object_id,
                     media_box,
                     graphics_objects,
-                    font_map,
                 })
             })
             .collect::<Fallible<Vec<Page>>>()?;
@@ -60,7 +64,6 @@ pub struct Page {
     object_id: lopdf::ObjectId,
     media_box: data::Rectangle,
     graphics_objects: Vec<GraphicsObject>,
-    font_map: FontMap,
 }
 
 impl Page {
@@ -75,8 +78,4 @@ impl Page {
     pub fn graphics_objects(&self) -> &[GraphicsObject] {
         &self.graphics_objects
     }
-
-    pub fn font(&self, name: &[u8]) -> Option<&Font> {
-        self.font_map.get(name)
-    }
 }
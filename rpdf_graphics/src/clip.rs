@@ -0,0 +1,32 @@
+use std::sync::Arc;
+
+use crate::path::{FillRule, Path};
+
+/// A clipping path, together with the transform that was in effect when it was set.
+#[derive(Debug)]
+pub struct ClipPath {
+    pub transform: euclid::Transform2D<f32>,
+    pub path: Path,
+    pub fill_rule: FillRule,
+}
+
+/// The current clipping region, which is the intersection of all clipping paths set
+/// since the graphics state was initialised.
+#[derive(Clone, Debug, Default)]
+pub struct Clip {
+    paths: Vec<Arc<ClipPath>>,
+}
+
+impl Clip {
+    pub fn intersect(&mut self, clip_path: ClipPath) {
+        self.paths.push(Arc::new(clip_path));
+    }
+
+    pub fn paths(&self) -> &[Arc<ClipPath>] {
+        &self.paths
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+}
@@ -0,0 +1,186 @@
+use std::sync::Arc;
+
+use failure::Fallible;
+
+use rpdf_lopdf_extra::DocumentExt;
+
+use crate::colorspace::ColorSpace;
+use crate::data::Name;
+use crate::resources::Resources;
+
+/// A colour in the sRGB colour space, with components in the range `0.0..=1.0`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rgb {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+}
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb {
+        red: 0.0,
+        green: 0.0,
+        blue: 0.0,
+    };
+
+    pub fn new(red: f32, green: f32, blue: f32) -> Self {
+        Rgb {
+            red: clamp(red),
+            green: clamp(green),
+            blue: clamp(blue),
+        }
+    }
+}
+
+fn clamp(value: f32) -> f32 {
+    value.clamp(0.0, 1.0)
+}
+
+#[derive(Clone, Debug)]
+pub struct Color {
+    space: Arc<ColorSpace>,
+    components: Vec<f32>,
+    /// Name of the pattern resource, for colours in a `Pattern` colour space.
+    pattern: Option<Vec<u8>>,
+}
+
+impl Color {
+    pub fn new(space: Arc<ColorSpace>) -> Self {
+        Color {
+            components: space.initial_color(),
+            space,
+            pattern: None,
+        }
+    }
+
+    pub fn from_components(space: Arc<ColorSpace>, components: Vec<f32>) -> Self {
+        Color {
+            space,
+            components,
+            pattern: None,
+        }
+    }
+
+    pub fn space(&self) -> &Arc<ColorSpace> {
+        &self.space
+    }
+
+    pub fn components(&self) -> &[f32] {
+        &self.components
+    }
+
+    pub fn pattern(&self) -> Option<&[u8]> {
+        self.pattern.as_deref()
+    }
+
+    pub fn to_rgb(&self) -> Rgb {
+        self.space.to_rgb(&self.components)
+    }
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Color::new(Arc::new(ColorSpace::DeviceGray))
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ColorState {
+    pub stroke: Color,
+    pub fill: Color,
+}
+
+impl ColorState {
+    fn read_components(
+        document: &lopdf::Document,
+        op: &lopdf::content::Operation,
+    ) -> Fallible<Vec<f32>> {
+        // `scn` and `SCN` may carry a trailing pattern name, which isn't a colour component
+        op.operands
+            .iter()
+            .filter(|operand| matches!(operand, lopdf::Object::Integer(_) | lopdf::Object::Real(_)))
+            .map(|operand| document.deserialize_object(operand))
+            .collect()
+    }
+
+    fn read_pattern(op: &lopdf::content::Operation) -> Option<Vec<u8>> {
+        op.operands
+            .last()
+            .and_then(lopdf::Object::as_name)
+            .map(|name| name.to_owned())
+    }
+
+    fn read_color_space(
+        resources: &Resources,
+        op: &lopdf::content::Operation,
+    ) -> Fallible<Arc<ColorSpace>> {
+        let Name(name) = resources.document().deserialize_object(&op.operands[0])?;
+        Ok(resources.color_space(&name).unwrap_or_else(|err| {
+            log::warn!("unsupported colour space in {:?}: {}", op, err);
+            Arc::new(ColorSpace::DeviceGray)
+        }))
+    }
+
+    pub fn handle_operation(
+        &mut self,
+        resources: &Resources,
+        op: &lopdf::content::Operation,
+    ) -> Fallible<()> {
+        let document = resources.document();
+        match op.operator.as_str() {
+            "G" | "RG" | "K" => {
+                let space = match op.operator.as_str() {
+                    "G" => ColorSpace::DeviceGray,
+                    "RG" => ColorSpace::DeviceRGB,
+                    _ => ColorSpace::DeviceCMYK,
+                };
+                self.stroke = Color {
+                    space: Arc::new(space),
+                    components: Self::read_components(document, op)?,
+                    pattern: None,
+                };
+            }
+            "g" | "rg" | "k" => {
+                let space = match op.operator.as_str() {
+                    "g" => ColorSpace::DeviceGray,
+                    "rg" => ColorSpace::DeviceRGB,
+                    _ => ColorSpace::DeviceCMYK,
+                };
+                self.fill = Color {
+                    space: Arc::new(space),
+                    components: Self::read_components(document, op)?,
+                    pattern: None,
+                };
+            }
+            "CS" => {
+                self.stroke = Color::new(Self::read_color_space(resources, op)?);
+            }
+            "cs" => {
+                self.fill = Color::new(Self::read_color_space(resources, op)?);
+            }
+            "SC" | "SCN" => {
+                self.stroke.components = Self::read_components(document, op)?;
+                self.stroke.pattern = Self::read_pattern(op);
+            }
+            "sc" | "scn" => {
+                self.fill.components = Self::read_components(document, op)?;
+                self.fill.pattern = Self::read_pattern(op);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn initial_cmyk_color_is_black() {
+        assert_eq!(
+            Rgb::BLACK,
+            Color::new(Arc::new(ColorSpace::DeviceCMYK)).to_rgb()
+        );
+    }
+}
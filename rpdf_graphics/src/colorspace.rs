@@ -0,0 +1,469 @@
+use std::sync::Arc;
+
+use failure::Fallible;
+use serde_derive::Deserialize;
+
+use rpdf_lopdf_extra::*;
+
+use crate::color::Rgb;
+use crate::function::Function;
+
+const D65_WHITE_POINT: [f32; 3] = [0.9505, 1.0, 1.089];
+
+/// Limits how deeply colour spaces can refer to base and alternate spaces, so that one
+/// that refers to itself can't exhaust the stack.
+const MAX_DEPTH: u32 = 8;
+
+#[derive(Deserialize)]
+struct CalGrayDictionary {
+    #[serde(rename = "WhitePoint")]
+    white_point: [f32; 3],
+    #[serde(rename = "Gamma")]
+    gamma: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct CalRgbDictionary {
+    #[serde(rename = "WhitePoint")]
+    white_point: [f32; 3],
+    #[serde(rename = "Gamma")]
+    gamma: Option<[f32; 3]>,
+    #[serde(rename = "Matrix")]
+    matrix: Option<[f32; 9]>,
+}
+
+#[derive(Deserialize)]
+struct LabDictionary {
+    #[serde(rename = "WhitePoint")]
+    white_point: [f32; 3],
+    #[serde(rename = "Range")]
+    range: Option<[f32; 4]>,
+}
+
+#[derive(Deserialize)]
+struct IccStreamDictionary {
+    #[serde(rename = "N")]
+    components: usize,
+    #[serde(rename = "Range")]
+    range: Option<Vec<f32>>,
+}
+
+/// Converts CIE XYZ coordinates relative to the given white point to sRGB.
+fn xyz_to_rgb(xyz: [f32; 3], white_point: [f32; 3]) -> Rgb {
+    // simple von Kries-style adaptation from the source white point to D65
+    let x = xyz[0] * D65_WHITE_POINT[0] / white_point[0];
+    let y = xyz[1] * D65_WHITE_POINT[1] / white_point[1];
+    let z = xyz[2] * D65_WHITE_POINT[2] / white_point[2];
+
+    let gamma = |linear: f32| {
+        if linear <= 0.003_130_8 {
+            12.92 * linear
+        } else {
+            1.055 * linear.powf(1.0 / 2.4) - 0.055
+        }
+    };
+
+    Rgb::new(
+        gamma(3.2406 * x - 1.5372 * y - 0.4986 * z),
+        gamma(-0.9689 * x + 1.8758 * y + 0.0415 * z),
+        gamma(0.0557 * x - 0.2040 * y + 1.0570 * z),
+    )
+}
+
+#[derive(Debug)]
+pub enum ColorSpace {
+    DeviceGray,
+    DeviceRGB,
+    DeviceCMYK,
+    CalGray {
+        white_point: [f32; 3],
+        gamma: f32,
+    },
+    CalRGB {
+        white_point: [f32; 3],
+        gamma: [f32; 3],
+        matrix: [f32; 9],
+    },
+    Lab {
+        white_point: [f32; 3],
+        range: [f32; 4],
+    },
+    ICCBased {
+        components: usize,
+        range: Vec<f32>,
+        alternate: Arc<ColorSpace>,
+    },
+    Indexed {
+        base: Arc<ColorSpace>,
+        high_value: u32,
+        lookup: Vec<u8>,
+    },
+    Separation {
+        alternate: Arc<ColorSpace>,
+        tint_transform: Arc<Function>,
+    },
+    DeviceN {
+        components: usize,
+        alternate: Arc<ColorSpace>,
+        tint_transform: Arc<Function>,
+    },
+    Pattern {
+        underlying: Option<Arc<ColorSpace>>,
+    },
+}
+
+impl ColorSpace {
+    /// Returns the colour space for one of the family names that can be used without
+    /// parameters, including the abbreviations allowed in inline images.
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        match name {
+            b"DeviceGray" | b"G" => Some(ColorSpace::DeviceGray),
+            b"DeviceRGB" | b"RGB" => Some(ColorSpace::DeviceRGB),
+            b"DeviceCMYK" | b"CMYK" => Some(ColorSpace::DeviceCMYK),
+            b"Pattern" => Some(ColorSpace::Pattern { underlying: None }),
+            _ => None,
+        }
+    }
+
+    pub fn try_from_object(doc: &lopdf::Document, obj: &lopdf::Object) -> Fallible<Self> {
+        Self::try_from_nested_object(doc, obj, 0)
+    }
+
+    fn try_from_nested_object(
+        doc: &lopdf::Document,
+        obj: &lopdf::Object,
+        depth: u32,
+    ) -> Fallible<Self> {
+        if depth > MAX_DEPTH {
+            failure::bail!("colour spaces are nested too deeply");
+        }
+        match doc.resolve_object(obj)? {
+            lopdf::Object::Name(ref name) => Self::from_name(name).ok_or_else(|| {
+                failure::format_err!("unsupported colour space {}", String::from_utf8_lossy(name))
+            }),
+            lopdf::Object::Array(ref items) => Self::try_from_array(doc, items, depth),
+            _ => failure::bail!("unexpected colour space {:?}", obj),
+        }
+    }
+
+    fn try_from_array(
+        doc: &lopdf::Document,
+        items: &[lopdf::Object],
+        depth: u32,
+    ) -> Fallible<Self> {
+        let nested = |obj: &lopdf::Object| Self::try_from_nested_object(doc, obj, depth + 1);
+        let family = items
+            .first()
+            .and_then(|item| doc.resolve_object(item).ok())
+            .and_then(lopdf::Object::as_name)
+            .ok_or_else(|| failure::format_err!("colour space is missing a family name"))?;
+        let operand = |index: usize| {
+            items
+                .get(index)
+                .ok_or_else(|| failure::format_err!("colour space is missing operands"))
+        };
+
+        match family {
+            b"CalGray" => {
+                let dict: CalGrayDictionary = doc.deserialize_object(operand(1)?)?;
+                Ok(ColorSpace::CalGray {
+                    white_point: dict.white_point,
+                    gamma: dict.gamma.unwrap_or(1.0),
+                })
+            }
+            b"CalRGB" => {
+                let dict: CalRgbDictionary = doc.deserialize_object(operand(1)?)?;
+                Ok(ColorSpace::CalRGB {
+                    white_point: dict.white_point,
+                    gamma: dict.gamma.unwrap_or([1.0, 1.0, 1.0]),
+                    matrix: dict
+                        .matrix
+                        .unwrap_or([1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]),
+                })
+            }
+            b"Lab" => {
+                let dict: LabDictionary = doc.deserialize_object(operand(1)?)?;
+                Ok(ColorSpace::Lab {
+                    white_point: dict.white_point,
+                    range: dict.range.unwrap_or([-100.0, 100.0, -100.0, 100.0]),
+                })
+            }
+            b"ICCBased" => {
+                let stream_obj = operand(1)?;
+                let dict: IccStreamDictionary = doc.deserialize_object(stream_obj)?;
+                let stream = doc.resolve_object(stream_obj)?.try_as_stream()?;
+                let alternate = match stream.dict.get(b"Alternate") {
+                    Some(alternate_obj) => nested(alternate_obj)?,
+                    None => match dict.components {
+                        1 => ColorSpace::DeviceGray,
+                        3 => ColorSpace::DeviceRGB,
+                        4 => ColorSpace::DeviceCMYK,
+                        n => failure::bail!("unsupported number of ICC components {}", n),
+                    },
+                };
+                let components = dict.components;
+                Ok(ColorSpace::ICCBased {
+                    components,
+                    range: dict
+                        .range
+                        .unwrap_or_else(|| (0..components).flat_map(|_| vec![0.0, 1.0]).collect()),
+                    alternate: Arc::new(alternate),
+                })
+            }
+            b"Indexed" | b"I" => {
+                let base = nested(operand(1)?)?;
+                let high_value = doc.deserialize_object(operand(2)?)?;
+                let lookup = match doc.resolve_object(operand(3)?)? {
+                    lopdf::Object::String(ref bytes, _) => bytes.clone(),
+                    lopdf::Object::Stream(ref stream) => stream.decoded_content(),
+                    obj => failure::bail!("unexpected indexed colour lookup {:?}", obj),
+                };
+                Ok(ColorSpace::Indexed {
+                    base: Arc::new(base),
+                    high_value,
+                    lookup,
+                })
+            }
+            b"Separation" => Ok(ColorSpace::Separation {
+                alternate: Arc::new(nested(operand(2)?)?),
+                tint_transform: Arc::new(Function::try_from_object(doc, operand(3)?)?),
+            }),
+            b"DeviceN" => {
+                let names = doc
+                    .resolve_object(operand(1)?)?
+                    .as_array()
+                    .ok_or_else(|| failure::format_err!("DeviceN is missing colorant names"))?;
+                Ok(ColorSpace::DeviceN {
+                    components: names.len(),
+                    alternate: Arc::new(nested(operand(2)?)?),
+                    tint_transform: Arc::new(Function::try_from_object(doc, operand(3)?)?),
+                })
+            }
+            b"Pattern" => {
+                let underlying = match items.get(1) {
+                    Some(underlying_obj) => Some(Arc::new(nested(underlying_obj)?)),
+                    None => None,
+                };
+                Ok(ColorSpace::Pattern { underlying })
+            }
+            name => match Self::from_name(name) {
+                Some(space) => Ok(space),
+                None => {
+                    failure::bail!("unsupported colour space {}", String::from_utf8_lossy(name))
+                }
+            },
+        }
+    }
+
+    pub fn components(&self) -> usize {
+        match self {
+            ColorSpace::DeviceGray | ColorSpace::CalGray { .. } => 1,
+            ColorSpace::DeviceRGB | ColorSpace::CalRGB { .. } | ColorSpace::Lab { .. } => 3,
+            ColorSpace::DeviceCMYK => 4,
+            ColorSpace::ICCBased { components, .. } => *components,
+            ColorSpace::Indexed { .. } | ColorSpace::Separation { .. } => 1,
+            ColorSpace::DeviceN { components, .. } => *components,
+            ColorSpace::Pattern { .. } => 0,
+        }
+    }
+
+    /// Returns the range of valid values for the component with the given index.
+    pub fn component_range(&self, index: usize) -> (f32, f32) {
+        match self {
+            ColorSpace::Lab { range, .. } => match index {
+                0 => (0.0, 100.0),
+                1 => (range[0], range[1]),
+                _ => (range[2], range[3]),
+            },
+            ColorSpace::ICCBased { range, .. } if range.len() >= index * 2 + 2 => {
+                (range[index * 2], range[index * 2 + 1])
+            }
+            ColorSpace::Indexed { high_value, .. } => (0.0, *high_value as f32),
+            _ => (0.0, 1.0),
+        }
+    }
+
+    pub fn initial_color(&self) -> Vec<f32> {
+        match self {
+            ColorSpace::DeviceCMYK => vec![0.0, 0.0, 0.0, 1.0],
+            ColorSpace::Separation { .. } | ColorSpace::DeviceN { .. } => {
+                vec![1.0; self.components()]
+            }
+            _ => (0..self.components())
+                .map(|index| {
+                    let (min, max) = self.component_range(index);
+                    0.0f32.max(min).min(max)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn to_rgb(&self, components: &[f32]) -> Rgb {
+        let component = |index: usize| {
+            let (min, max) = self.component_range(index);
+            components
+                .get(index)
+                .cloned()
+                .unwrap_or(0.0)
+                .max(min)
+                .min(max)
+        };
+        match self {
+            ColorSpace::DeviceGray => {
+                let gray = component(0);
+                Rgb::new(gray, gray, gray)
+            }
+            ColorSpace::DeviceRGB => Rgb::new(component(0), component(1), component(2)),
+            ColorSpace::DeviceCMYK => {
+                let black = component(3);
+                Rgb::new(
+                    (1.0 - component(0)) * (1.0 - black),
+                    (1.0 - component(1)) * (1.0 - black),
+                    (1.0 - component(2)) * (1.0 - black),
+                )
+            }
+            ColorSpace::CalGray { white_point, gamma } => {
+                let luminance = component(0).powf(*gamma);
+                xyz_to_rgb(
+                    [
+                        white_point[0] * luminance,
+                        white_point[1] * luminance,
+                        white_point[2] * luminance,
+                    ],
+                    *white_point,
+                )
+            }
+            ColorSpace::CalRGB {
+                white_point,
+                gamma,
+                matrix,
+            } => {
+                let a = component(0).powf(gamma[0]);
+                let b = component(1).powf(gamma[1]);
+                let c = component(2).powf(gamma[2]);
+                xyz_to_rgb(
+                    [
+                        matrix[0] * a + matrix[3] * b + matrix[6] * c,
+                        matrix[1] * a + matrix[4] * b + matrix[7] * c,
+                        matrix[2] * a + matrix[5] * b + matrix[8] * c,
+                    ],
+                    *white_point,
+                )
+            }
+            ColorSpace::Lab { white_point, .. } => {
+                let g = |t: f32| {
+                    if t >= 6.0 / 29.0 {
+                        t * t * t
+                    } else {
+                        108.0 / 841.0 * (t - 4.0 / 29.0)
+                    }
+                };
+                let l = (component(0) + 16.0) / 116.0;
+                let m = l + component(1) / 500.0;
+                let n = l - component(2) / 200.0;
+                xyz_to_rgb(
+                    [
+                        white_point[0] * g(m),
+                        white_point[1] * g(l),
+                        white_point[2] * g(n),
+                    ],
+                    *white_point,
+                )
+            }
+            ColorSpace::ICCBased { alternate, .. } => alternate.to_rgb(components),
+            ColorSpace::Indexed {
+                base,
+                high_value,
+                lookup,
+            } => {
+                let index = component(0).round().max(0.0).min(*high_value as f32) as usize;
+                let base_components = (0..base.components())
+                    .map(|i| {
+                        let byte = lookup
+                            .get(index * base.components() + i)
+                            .cloned()
+                            .unwrap_or(0);
+                        let (min, max) = base.component_range(i);
+                        min + f32::from(byte) * (max - min) / 255.0
+                    })
+                    .collect::<Vec<_>>();
+                base.to_rgb(&base_components)
+            }
+            ColorSpace::Separation {
+                alternate,
+                tint_transform,
+            }
+            | ColorSpace::DeviceN {
+                alternate,
+                tint_transform,
+                ..
+            } => alternate.to_rgb(&tint_transform.evaluate(components)),
+            ColorSpace::Pattern { .. } => Rgb::BLACK,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_color_to_rgb() {
+        assert_eq!(
+            Rgb::new(0.5, 0.5, 0.5),
+            ColorSpace::DeviceGray.to_rgb(&[0.5])
+        );
+        assert_eq!(
+            Rgb::new(1.0, 0.0, 0.25),
+            ColorSpace::DeviceRGB.to_rgb(&[1.0, 0.0, 0.25])
+        );
+        assert_eq!(
+            Rgb::new(0.0, 1.0, 1.0),
+            ColorSpace::DeviceCMYK.to_rgb(&[1.0, 0.0, 0.0, 0.0])
+        );
+    }
+
+    #[test]
+    fn indexed_lookup() {
+        let doc = lopdf::Document::new();
+        let space = ColorSpace::try_from_object(
+            &doc,
+            &lopdf::Object::Array(vec![
+                lopdf::Object::from("Indexed"),
+                lopdf::Object::from("DeviceRGB"),
+                lopdf::Object::Integer(1),
+                lopdf::Object::String(vec![255, 0, 0, 0, 0, 255], lopdf::StringFormat::Literal),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(Rgb::new(1.0, 0.0, 0.0), space.to_rgb(&[0.0]));
+        assert_eq!(Rgb::new(0.0, 0.0, 1.0), space.to_rgb(&[1.0]));
+        assert_eq!(Rgb::new(0.0, 0.0, 1.0), space.to_rgb(&[5.0]));
+    }
+
+    #[test]
+    fn self_referencing_space() {
+        let mut doc = lopdf::Document::new();
+        let id = doc.add_object(lopdf::Object::Null);
+        let space = lopdf::Object::Array(vec![
+            lopdf::Object::from("Indexed"),
+            id.into(),
+            lopdf::Object::Integer(0),
+            lopdf::Object::String(vec![0], lopdf::StringFormat::Literal),
+        ]);
+        doc.objects.insert(id, space);
+        assert!(ColorSpace::try_from_object(&doc, &id.into()).is_err());
+    }
+
+    #[test]
+    fn lab_white_is_white() {
+        let space = ColorSpace::Lab {
+            white_point: D65_WHITE_POINT,
+            range: [-100.0, 100.0, -100.0, 100.0],
+        };
+        let white = space.to_rgb(&[100.0, 0.0, 0.0]);
+        assert!((white.red - 1.0).abs() < 0.01);
+        assert!((white.green - 1.0).abs() < 0.01);
+        assert!((white.blue - 1.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,548 @@
+use std::convert::TryFrom;
+
+use failure::Fallible;
+
+use lopdf::content::Operation;
+use lopdf::Object;
+
+/// Operator of the synthetic operation produced for an inline image, whose only operand
+/// is a stream holding the (expanded) image dictionary and the image data.
+pub const OP_INLINE_IMAGE: &str = "BI";
+
+/// Limits how deeply arrays and dictionaries can nest, so that crafted content streams
+/// can't exhaust the stack.
+const MAX_NESTING: usize = 32;
+
+fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, b'\0' | b'\t' | b'\n' | b'\x0c' | b'\r' | b' ')
+}
+
+fn is_delimiter(byte: u8) -> bool {
+    matches!(
+        byte,
+        b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%'
+    )
+}
+
+fn is_regular(byte: u8) -> bool {
+    !is_whitespace(byte) && !is_delimiter(byte)
+}
+
+/// Expands the abbreviated keys that may be used in inline image dictionaries.
+fn expand_inline_image_key(key: Vec<u8>) -> Vec<u8> {
+    let expanded: &[u8] = match key.as_slice() {
+        b"BPC" => b"BitsPerComponent",
+        b"CS" => b"ColorSpace",
+        b"D" => b"Decode",
+        b"DP" => b"DecodeParms",
+        b"F" => b"Filter",
+        b"H" => b"Height",
+        b"IM" => b"ImageMask",
+        b"I" => b"Interpolate",
+        b"L" => b"Length",
+        b"W" => b"Width",
+        _ => return key,
+    };
+    expanded.to_vec()
+}
+
+enum Token {
+    Object(Object),
+    Operator(String),
+    ArrayStart,
+    ArrayEnd,
+    DictionaryStart,
+    DictionaryEnd,
+}
+
+struct Lexer<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.position).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(byte) = self.peek() {
+            if is_whitespace(byte) {
+                self.position += 1;
+            } else if byte == b'%' {
+                while let Some(byte) = self.peek() {
+                    if byte == b'\r' || byte == b'\n' {
+                        break;
+                    }
+                    self.position += 1;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn read_regular(&mut self) -> &'a [u8] {
+        let start = self.position;
+        while self.peek().is_some_and(is_regular) {
+            self.position += 1;
+        }
+        &self.data[start..self.position]
+    }
+
+    fn read_name(&mut self) -> Vec<u8> {
+        let raw = self.read_regular();
+        let mut name = Vec::with_capacity(raw.len());
+        let mut index = 0;
+        while index < raw.len() {
+            let escaped = raw
+                .get(index + 1..index + 3)
+                .filter(|_| raw[index] == b'#')
+                .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+            if let Some(byte) = escaped {
+                name.push(byte);
+                index += 3;
+            } else {
+                name.push(raw[index]);
+                index += 1;
+            }
+        }
+        name
+    }
+
+    fn read_literal_string(&mut self) -> Fallible<Vec<u8>> {
+        let mut string = Vec::new();
+        let mut depth = 0;
+        loop {
+            let byte = match self.peek() {
+                Some(byte) => byte,
+                None => failure::bail!("unterminated string"),
+            };
+            self.position += 1;
+            match byte {
+                b'(' => {
+                    depth += 1;
+                    string.push(byte);
+                }
+                b')' if depth == 0 => return Ok(string),
+                b')' => {
+                    depth -= 1;
+                    string.push(byte);
+                }
+                b'\\' => {
+                    let escaped = match self.peek() {
+                        Some(escaped) => escaped,
+                        None => continue,
+                    };
+                    self.position += 1;
+                    match escaped {
+                        b'n' => string.push(b'\n'),
+                        b'r' => string.push(b'\r'),
+                        b't' => string.push(b'\t'),
+                        b'b' => string.push(b'\x08'),
+                        b'f' => string.push(b'\x0c'),
+                        b'0'..=b'7' => {
+                            let mut value = u32::from(escaped - b'0');
+                            for _ in 0..2 {
+                                match self.peek() {
+                                    Some(digit @ b'0'..=b'7') => {
+                                        value = value * 8 + u32::from(digit - b'0');
+                                        self.position += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            string.push(value as u8);
+                        }
+                        b'\r' => {
+                            // a backslash at the end of a line continues the string
+                            if self.peek() == Some(b'\n') {
+                                self.position += 1;
+                            }
+                        }
+                        b'\n' => {}
+                        _ => string.push(escaped),
+                    }
+                }
+                _ => string.push(byte),
+            }
+        }
+    }
+
+    fn read_hex_string(&mut self) -> Fallible<Vec<u8>> {
+        let mut string = Vec::new();
+        let mut high = None;
+        loop {
+            let byte = match self.peek() {
+                Some(byte) => byte,
+                None => failure::bail!("unterminated hex string"),
+            };
+            self.position += 1;
+            if byte == b'>' {
+                break;
+            }
+            let digit = match (byte as char).to_digit(16) {
+                Some(digit) => digit as u8,
+                None if is_whitespace(byte) => continue,
+                None => failure::bail!("invalid character in hex string"),
+            };
+            match high.take() {
+                Some(high) => string.push(high << 4 | digit),
+                None => high = Some(digit),
+            }
+        }
+        if let Some(high) = high {
+            string.push(high << 4);
+        }
+        Ok(string)
+    }
+
+    fn read_number_or_operator(&mut self) -> Token {
+        let raw = self.read_regular();
+        let text = String::from_utf8_lossy(raw);
+        let is_number = raw
+            .iter()
+            .all(|byte| matches!(byte, b'0'..=b'9' | b'.' | b'+' | b'-'))
+            && raw.iter().any(u8::is_ascii_digit);
+        if is_number {
+            if let Ok(value) = text.parse::<i64>() {
+                return Token::Object(Object::Integer(value));
+            }
+            // some producers emit numbers such as `--1` or `1.2.3`, so be lenient
+            let cleaned = text.trim_start_matches('+').replace("--", "-");
+            if let Ok(value) = cleaned.parse::<f64>() {
+                return Token::Object(Object::Real(value));
+            }
+        }
+        match raw {
+            b"true" => Token::Object(Object::Boolean(true)),
+            b"false" => Token::Object(Object::Boolean(false)),
+            b"null" => Token::Object(Object::Null),
+            _ => Token::Operator(text.into_owned()),
+        }
+    }
+
+    fn next_token(&mut self) -> Fallible<Option<Token>> {
+        self.skip_whitespace();
+        let byte = match self.peek() {
+            Some(byte) => byte,
+            None => return Ok(None),
+        };
+        let token = match byte {
+            b'/' => {
+                self.position += 1;
+                Token::Object(Object::Name(self.read_name()))
+            }
+            b'(' => {
+                self.position += 1;
+                Token::Object(Object::String(
+                    self.read_literal_string()?,
+                    lopdf::StringFormat::Literal,
+                ))
+            }
+            b'<' if self.data.get(self.position + 1) == Some(&b'<') => {
+                self.position += 2;
+                Token::DictionaryStart
+            }
+            b'<' => {
+                self.position += 1;
+                Token::Object(Object::String(
+                    self.read_hex_string()?,
+                    lopdf::StringFormat::Hexadecimal,
+                ))
+            }
+            b'>' if self.data.get(self.position + 1) == Some(&b'>') => {
+                self.position += 2;
+                Token::DictionaryEnd
+            }
+            b'[' => {
+                self.position += 1;
+                Token::ArrayStart
+            }
+            b']' => {
+                self.position += 1;
+                Token::ArrayEnd
+            }
+            b')' | b'>' | b'{' | b'}' => {
+                log::warn!("unexpected {:?} in content stream", byte as char);
+                self.position += 1;
+                return self.next_token();
+            }
+            _ => self.read_number_or_operator(),
+        };
+        Ok(Some(token))
+    }
+
+    /// Reads the complete object starting with `token`, which is nested in `depth`
+    /// arrays and dictionaries.
+    fn read_object(&mut self, token: Token, depth: usize) -> Fallible<Object> {
+        if depth > MAX_NESTING {
+            failure::bail!("arrays and dictionaries are nested too deeply");
+        }
+        match token {
+            Token::Object(object) => Ok(object),
+            Token::ArrayStart => {
+                let mut items = Vec::new();
+                loop {
+                    match self.next_token()? {
+                        Some(Token::ArrayEnd) => return Ok(Object::Array(items)),
+                        Some(Token::DictionaryEnd) => log::warn!("unexpected >> in array"),
+                        Some(token) => items.push(self.read_object(token, depth + 1)?),
+                        None => failure::bail!("unterminated array"),
+                    }
+                }
+            }
+            Token::DictionaryStart => {
+                let mut dict = lopdf::Dictionary::new();
+                loop {
+                    match self.next_token()? {
+                        Some(Token::DictionaryEnd) => return Ok(Object::Dictionary(dict)),
+                        Some(Token::ArrayEnd) => log::warn!("unexpected ] in dictionary"),
+                        Some(Token::Object(Object::Name(key))) => {
+                            let value = match self.next_token()? {
+                                Some(token) => self.read_object(token, depth + 1)?,
+                                None => failure::bail!("unterminated dictionary"),
+                            };
+                            dict.set(key, value);
+                        }
+                        _ => failure::bail!("invalid dictionary key"),
+                    }
+                }
+            }
+            Token::Operator(operator) => failure::bail!("unexpected operator {}", operator),
+            Token::ArrayEnd | Token::DictionaryEnd => failure::bail!("unbalanced delimiter"),
+        }
+    }
+
+    /// Reads the image data following the `ID` operator of an inline image.
+    fn read_inline_image_data(&mut self, dict: &lopdf::Dictionary) -> Fallible<Vec<u8>> {
+        // a single whitespace character separates `ID` from the data
+        if self.peek().is_some_and(is_whitespace) {
+            self.position += 1;
+        }
+        let start = self.position;
+
+        let is_end = |lexer: &Self, position: usize| {
+            lexer.data.get(position..position + 2) == Some(b"EI")
+                && lexer
+                    .data
+                    .get(position + 2)
+                    .is_none_or(|byte| !is_regular(*byte))
+        };
+
+        // unfiltered data has a known length, which avoids mistaking samples for `EI`
+        let known_end = inline_image_length(dict)
+            .and_then(|length| start.checked_add(length))
+            .filter(|end| *end <= self.data.len());
+        if let Some(mut end) = known_end {
+            let length = end - start;
+            while self.data.get(end).is_some_and(|byte| is_whitespace(*byte)) {
+                end += 1;
+            }
+            if is_end(self, end) {
+                self.position = end + 2;
+                return Ok(self.data[start..start + length].to_vec());
+            }
+        }
+
+        let mut position = start;
+        while position < self.data.len() {
+            if is_end(self, position) && position > start && is_whitespace(self.data[position - 1])
+            {
+                self.position = position + 2;
+                return Ok(self.data[start..position - 1].to_vec());
+            }
+            position += 1;
+        }
+        failure::bail!("inline image is missing EI")
+    }
+
+    fn read_inline_image(&mut self) -> Fallible<Object> {
+        let mut dict = lopdf::Dictionary::new();
+        loop {
+            match self.next_token()? {
+                Some(Token::Operator(ref operator)) if operator == "ID" => break,
+                Some(Token::Object(Object::Name(key))) => {
+                    let value = match self.next_token()? {
+                        Some(token) => self.read_object(token, 1)?,
+                        None => failure::bail!("unterminated inline image"),
+                    };
+                    dict.set(expand_inline_image_key(key), value);
+                }
+                _ => failure::bail!("invalid inline image dictionary"),
+            }
+        }
+        let data = self.read_inline_image_data(&dict)?;
+        Ok(Object::Stream(lopdf::Stream::new(dict, data)))
+    }
+}
+
+/// Computes the length of unfiltered inline image data from its dictionary, if possible.
+fn inline_image_length(dict: &lopdf::Dictionary) -> Option<usize> {
+    if dict.get(b"Filter").is_some() {
+        return None;
+    }
+    let integer = |key: &[u8]| {
+        dict.get(key)
+            .and_then(Object::as_i64)
+            .and_then(|value| usize::try_from(value).ok())
+    };
+    let width = integer(b"Width")?;
+    let height = integer(b"Height")?;
+    let is_mask = matches!(dict.get(b"ImageMask"), Some(Object::Boolean(true)));
+    let (components, bits) = if is_mask {
+        (1, 1)
+    } else {
+        let components = match dict.get(b"ColorSpace")? {
+            Object::Name(ref name) => match name.as_slice() {
+                b"G" | b"DeviceGray" | b"I" | b"Indexed" => 1,
+                b"RGB" | b"DeviceRGB" => 3,
+                b"CMYK" | b"DeviceCMYK" => 4,
+                _ => return None,
+            },
+            Object::Array(ref items) => match items.first().and_then(Object::as_name) {
+                Some(b"I") | Some(b"Indexed") => 1,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        (components, integer(b"BitsPerComponent")?)
+    };
+    // lengths that overflow are left to the search for `EI`
+    width
+        .checked_mul(components)?
+        .checked_mul(bits)?
+        .div_ceil(8)
+        .checked_mul(height)
+}
+
+/// Splits a content stream into operations.
+///
+/// Unlike `lopdf::content::Content::decode`, this understands inline images, which are
+/// returned as a single `BI` operation (see `OP_INLINE_IMAGE`).
+pub fn decode(data: &[u8]) -> Fallible<Vec<Operation>> {
+    let mut lexer = Lexer { data, position: 0 };
+    let mut operations = Vec::new();
+    let mut operands = Vec::new();
+    while let Some(token) = lexer.next_token()? {
+        match token {
+            Token::Operator(ref operator) if operator == OP_INLINE_IMAGE => {
+                let image = lexer.read_inline_image()?;
+                operations.push(Operation::new(OP_INLINE_IMAGE, vec![image]));
+                operands.clear();
+            }
+            Token::Operator(operator) => {
+                operations.push(Operation {
+                    operator,
+                    operands: std::mem::take(&mut operands),
+                });
+            }
+            Token::ArrayEnd | Token::DictionaryEnd => {
+                log::warn!("skipping unmatched closing delimiter in content stream");
+            }
+            token => operands.push(lexer.read_object(token, 0)?),
+        }
+    }
+    Ok(operations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operands() {
+        let operations = decode(b"/F1 12 Tf [(a\\(b\\)) -2.5 <41 42>] TJ % comment\nQ").unwrap();
+        assert_eq!(3, operations.len());
+        assert_eq!("Tf", operations[0].operator);
+        match operations[0].operands.as_slice() {
+            [Object::Name(name), Object::Integer(12)] => assert_eq!(b"F1", name.as_slice()),
+            operands => panic!("unexpected operands {:?}", operands),
+        }
+        match operations[1].operands.as_slice() {
+            [Object::Array(items)] => match items.as_slice() {
+                [Object::String(a, lopdf::StringFormat::Literal), Object::Real(b), Object::String(c, lopdf::StringFormat::Hexadecimal)] =>
+                {
+                    assert_eq!(b"a(b)", a.as_slice());
+                    assert_eq!(-2.5, *b);
+                    assert_eq!(b"AB", c.as_slice());
+                }
+                items => panic!("unexpected items {:?}", items),
+            },
+            operands => panic!("unexpected operands {:?}", operands),
+        }
+        assert_eq!("Q", operations[2].operator);
+    }
+
+    #[test]
+    fn unmatched_and_nested_delimiters() {
+        let operations = decode(b"] 1 >> 2 [3 >> 4] << /A 5 ] >> x").unwrap();
+        assert_eq!(1, operations.len());
+        match operations[0].operands.as_slice() {
+            [Object::Integer(1), Object::Integer(2), Object::Array(items), Object::Dictionary(dict)] =>
+            {
+                assert_eq!(2, items.len());
+                assert_eq!(Some(5), dict.get(b"A").and_then(Object::as_i64));
+            }
+            operands => panic!("unexpected operands {:?}", operands),
+        }
+
+        let nested = [vec![b'['; 10_000], vec![b']'; 10_000]].concat();
+        assert!(decode(&nested).is_err());
+        let nested = [vec![b'['; MAX_NESTING], vec![b']'; MAX_NESTING]].concat();
+        assert!(decode(&nested).is_ok());
+    }
+
+    #[test]
+    fn inline_image_with_known_length() {
+        // the samples contain "EI" followed by a space, which must not end the image
+        let operations = decode(b"q BI /W 4 /H 1 /BPC 8 /CS /G ID EI 1 EI Q").unwrap();
+        assert_eq!(3, operations.len());
+        match operations[1].operands[0] {
+            Object::Stream(ref stream) => {
+                assert_eq!(b"EI 1", stream.content.as_slice());
+                assert_eq!(Some(4), stream.dict.get(b"Width").and_then(Object::as_i64));
+                assert_eq!(
+                    Some(&b"G"[..]),
+                    stream.dict.get(b"ColorSpace").and_then(Object::as_name)
+                );
+            }
+            _ => panic!("expected an inline image stream"),
+        }
+        assert_eq!("Q", operations[2].operator);
+    }
+
+    #[test]
+    fn inline_image_with_filter() {
+        let operations =
+            decode(b"BI /W 2 /H 1 /CS /RGB /BPC 8 /F /AHx ID 0a0b0c0d0e0f> EI").unwrap();
+        assert_eq!(1, operations.len());
+        match operations[0].operands[0] {
+            Object::Stream(ref stream) => {
+                assert_eq!(b"0a0b0c0d0e0f>", stream.content.as_slice());
+                assert_eq!(
+                    Some(&b"AHx"[..]),
+                    stream.dict.get(b"Filter").and_then(Object::as_name)
+                );
+            }
+            _ => panic!("expected an inline image stream"),
+        }
+    }
+
+    #[test]
+    fn inline_image_with_invalid_size() {
+        // lengths that are negative, overflow or run past the data are found by `EI`
+        for content in &[
+            &b"BI /W -4 /H -1 /BPC 8 /CS /G ID abc EI"[..],
+            b"BI /W 4611686018427387904 /H 4 /BPC 8 /CS /RGB ID abc EI",
+            b"BI /W 1000 /H 1000 /BPC 8 /CS /G ID abc EI",
+        ] {
+            let operations = decode(content).unwrap();
+            assert_eq!(1, operations.len());
+            match operations[0].operands[0] {
+                Object::Stream(ref stream) => assert_eq!(b"abc", stream.content.as_slice()),
+                _ => panic!("expected an inline image stream"),
+            }
+        }
+    }
+}
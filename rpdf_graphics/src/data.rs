@@ -60,3 +60,35 @@ impl<'de> serde::Deserialize<'de> for Name {
         deserializer.deserialize_bytes(Visitor)
     }
 }
+
+/// Reads big-endian unsigned integers of up to 32 bits from a packed byte sequence.
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        BitReader { data, position: 0 }
+    }
+
+    pub fn read(&mut self, bits: u32) -> Option<u32> {
+        let mut value = 0u64;
+        let mut remaining = bits as usize;
+        while remaining > 0 {
+            let byte = u64::from(*self.data.get(self.position / 8)?);
+            let available = 8 - self.position % 8;
+            let take = available.min(remaining);
+            let chunk = (byte >> (available - take)) & ((1 << take) - 1);
+            value = (value << take) | chunk;
+            remaining -= take;
+            self.position += take;
+        }
+        Some(value as u32)
+    }
+
+    /// Skips ahead to the next byte boundary.
+    pub fn align(&mut self) {
+        self.position = self.position.div_ceil(8) * 8;
+    }
+}
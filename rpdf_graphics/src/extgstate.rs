@@ -0,0 +1,205 @@
+use failure::Fallible;
+
+use rpdf_lopdf_extra::*;
+
+use crate::line::DashPattern;
+use crate::resources::Resources;
+use crate::transparency::BlendMode;
+use crate::GraphicsState;
+
+/// Keys that only matter for printing or are overridden by the viewer, which we ignore.
+const DEVICE_DEPENDENT_KEYS: &[&[u8]] = &[
+    b"RI", b"OP", b"op", b"OPM", b"BG", b"BG2", b"UCR", b"UCR2", b"TR", b"TR2", b"HT", b"FL",
+    b"SM", b"SA",
+];
+
+fn read_blend_mode(document: &lopdf::Document, obj: &lopdf::Object) -> Fallible<BlendMode> {
+    // an array lists blend modes in order of preference
+    let names = match document.resolve_object(obj)? {
+        lopdf::Object::Array(ref items) => items.iter().collect(),
+        obj => vec![obj],
+    };
+    for name in names {
+        if let Some(blend_mode) = document
+            .resolve_object(name)?
+            .as_name()
+            .and_then(BlendMode::from_name)
+        {
+            return Ok(blend_mode);
+        }
+    }
+    log::warn!("unsupported blend mode {:?}", obj);
+    Ok(BlendMode::Normal)
+}
+
+/// Looks up the resource name of the font an ExtGState refers to, since fonts are
+/// identified by name in the text state.
+fn font_name(resources: &Resources, obj: &lopdf::Object) -> Option<Vec<u8>> {
+    let id = match obj {
+        lopdf::Object::Reference(id) => *id,
+        _ => return None,
+    };
+    resources
+        .entries(b"Font")
+        .keys()
+        .find(|name| resources.get_id(b"Font", name) == Some(id))
+        .cloned()
+}
+
+/// Applies the entries of an ExtGState parameter dictionary (set with the `gs`
+/// operator) to the graphics state. Invalid entries are skipped with a warning.
+pub fn apply(resources: &Resources, dict: &lopdf::Dictionary, state: &mut GraphicsState) {
+    for (key, value) in dict.iter() {
+        if let Err(err) = apply_entry(resources, key, value, state) {
+            log::warn!(
+                "ignoring invalid ExtGState key {}: {}",
+                String::from_utf8_lossy(key),
+                err
+            );
+        }
+    }
+}
+
+fn apply_entry(
+    resources: &Resources,
+    key: &[u8],
+    value: &lopdf::Object,
+    state: &mut GraphicsState,
+) -> Fallible<()> {
+    let document = resources.document();
+    match key {
+        b"Type" => {}
+        b"LW" => {
+            let width: f32 = document.deserialize_object(value)?;
+            state.line_style.width = width.abs();
+        }
+        b"LC" => {
+            state
+                .line_style
+                .set_cap(document.deserialize_object(value)?);
+        }
+        b"LJ" => {
+            state
+                .line_style
+                .set_join(document.deserialize_object(value)?);
+        }
+        b"ML" => {
+            state.line_style.miter_limit = document.deserialize_object(value)?;
+        }
+        b"D" => match document.resolve_object(value)? {
+            lopdf::Object::Array(ref items) if items.len() == 2 => {
+                state.line_style.dash = DashPattern {
+                    array: document.deserialize_object(&items[0])?,
+                    phase: document.deserialize_object(&items[1])?,
+                };
+            }
+            obj => failure::bail!("invalid dash pattern {:?}", obj),
+        },
+        b"Font" => match document.resolve_object(value)? {
+            lopdf::Object::Array(ref items) if items.len() == 2 => {
+                let size = document.deserialize_object(&items[1])?;
+                match font_name(resources, &items[0]) {
+                    Some(name) => state.text_state.set_font(name, size),
+                    None => {
+                        log::warn!("font {:?} of ExtGState is not a font resource", items[0])
+                    }
+                }
+            }
+            obj => failure::bail!("invalid font {:?}", obj),
+        },
+        b"CA" => {
+            state.transparency.stroke_alpha = document.deserialize_object(value)?;
+        }
+        b"ca" => {
+            state.transparency.fill_alpha = document.deserialize_object(value)?;
+        }
+        b"BM" => {
+            state.transparency.blend_mode = read_blend_mode(document, value)?;
+        }
+        // soft masks are decoded by the caller, which can execute their groups
+        b"SMask" => {}
+        b"AIS" => {
+            state.transparency.alpha_is_shape = document.deserialize_object(value)?;
+        }
+        key if DEVICE_DEPENDENT_KEYS.contains(&key) => {
+            log::debug!("ignoring ExtGState key {}", String::from_utf8_lossy(key));
+        }
+        key => {
+            log::warn!("unsupported ExtGState key {}", String::from_utf8_lossy(key));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use lopdf::Object;
+
+    use super::*;
+    use crate::line::LineJoin;
+
+    #[test]
+    fn apply_parameters() {
+        let mut document = lopdf::Document::new();
+        let font_id = document.add_object(lopdf::Dictionary::new());
+        let mut fonts = lopdf::Dictionary::new();
+        fonts.set("F1", font_id);
+        let mut resource_dict = lopdf::Dictionary::new();
+        resource_dict.set("Font", fonts);
+        let resources = Resources::new(Arc::new(document), vec![resource_dict]);
+
+        let mut dict = lopdf::Dictionary::new();
+        dict.set("Type", Object::Name(b"ExtGState".to_vec()));
+        dict.set("LW", Object::Real(0.5));
+        dict.set("LJ", Object::Integer(2));
+        dict.set(
+            "D",
+            Object::Array(vec![
+                Object::Array(vec![Object::Integer(2)]),
+                Object::Integer(1),
+            ]),
+        );
+        dict.set(
+            "Font",
+            Object::Array(vec![Object::Reference(font_id), Object::Integer(12)]),
+        );
+        dict.set("ca", Object::Real(0.25));
+        dict.set(
+            "BM",
+            Object::Array(vec![
+                Object::Name(b"Unknown".to_vec()),
+                Object::Name(b"Multiply".to_vec()),
+            ]),
+        );
+        dict.set("OP", Object::Boolean(true));
+
+        let mut state = GraphicsState::default();
+        apply(&resources, &dict, &mut state);
+        assert_eq!(0.5, state.line_style.width);
+        assert_eq!(LineJoin::Bevel, state.line_style.join);
+        assert_eq!(vec![2.0], state.line_style.dash.array);
+        assert_eq!(1.0, state.line_style.dash.phase);
+        assert_eq!(b"F1", state.text_state.font_name());
+        assert_eq!(1.0, state.transparency.stroke_alpha);
+        assert_eq!(0.25, state.transparency.fill_alpha);
+        assert_eq!(BlendMode::Multiply, state.transparency.blend_mode);
+    }
+
+    #[test]
+    fn invalid_parameters_are_skipped() {
+        let resources = Resources::new(Arc::new(lopdf::Document::new()), vec![]);
+
+        let mut dict = lopdf::Dictionary::new();
+        dict.set("LW", Object::Name(b"Wide".to_vec()));
+        dict.set("D", Object::Integer(3));
+        dict.set("Font", Object::Null);
+        dict.set("ca", Object::Real(0.5));
+
+        let mut state = GraphicsState::default();
+        apply(&resources, &dict, &mut state);
+        assert_eq!(GraphicsState::default().line_style, state.line_style);
+        assert_eq!(0.5, state.transparency.fill_alpha);
+    }
+}
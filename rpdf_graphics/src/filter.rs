@@ -0,0 +1,338 @@
+use std::io::Read;
+
+use failure::Fallible;
+use serde_derive::Deserialize;
+
+use rpdf_lopdf_extra::*;
+
+#[derive(Deserialize)]
+struct PredictorParameters {
+    #[serde(rename = "Predictor")]
+    predictor: Option<u32>,
+    #[serde(rename = "Colors")]
+    colors: Option<usize>,
+    #[serde(rename = "BitsPerComponent")]
+    bits_per_component: Option<usize>,
+    #[serde(rename = "Columns")]
+    columns: Option<usize>,
+}
+
+/// Returns the filters of a stream together with their (optional) parameters.
+fn filters<'a>(
+    doc: &'a lopdf::Document,
+    dict: &'a lopdf::Dictionary,
+) -> Fallible<Vec<(&'a [u8], Option<&'a lopdf::Object>)>> {
+    let names = match dict.get(b"Filter") {
+        Some(obj) => match doc.resolve_object(obj)? {
+            lopdf::Object::Name(ref name) => vec![name.as_slice()],
+            lopdf::Object::Array(ref items) => items
+                .iter()
+                .map(|item| {
+                    doc.resolve_object(item)?
+                        .as_name()
+                        .ok_or_else(|| failure::format_err!("invalid filter {:?}", item))
+                })
+                .collect::<Fallible<_>>()?,
+            obj => failure::bail!("invalid filter {:?}", obj),
+        },
+        None => vec![],
+    };
+
+    let parameters = match dict.get(b"DecodeParms") {
+        Some(obj) => match doc.resolve_object(obj)? {
+            lopdf::Object::Array(ref items) => items.iter().map(Some).collect(),
+            obj => vec![Some(obj)],
+        },
+        None => vec![],
+    };
+
+    Ok(names
+        .into_iter()
+        .enumerate()
+        .map(|(index, name)| {
+            let parameter = parameters
+                .get(index)
+                .cloned()
+                .and_then(|obj| obj)
+                .filter(|obj| !matches!(obj, lopdf::Object::Null));
+            (name, parameter)
+        })
+        .collect())
+}
+
+/// Applies the filters listed in the stream dictionary to its data.
+///
+/// `DCTDecode` is decoded all the way to samples, so the result of an image stream is
+/// always its raw sample data.
+pub fn decode(doc: &lopdf::Document, dict: &lopdf::Dictionary, data: &[u8]) -> Fallible<Vec<u8>> {
+    let mut data = data.to_owned();
+    for (name, parameters) in filters(doc, dict)? {
+        data = match name {
+            b"FlateDecode" | b"Fl" => {
+                let data = flate_decode(&data)?;
+                match parameters {
+                    Some(obj) => apply_predictor(data, &doc.deserialize_object(obj)?)?,
+                    None => data,
+                }
+            }
+            b"ASCIIHexDecode" | b"AHx" => ascii_hex_decode(&data),
+            b"ASCII85Decode" | b"A85" => ascii85_decode(&data)?,
+            b"RunLengthDecode" | b"RL" => run_length_decode(&data),
+            b"DCTDecode" | b"DCT" => dct_decode(&data)?,
+            _ => failure::bail!("unsupported filter {}", String::from_utf8_lossy(name)),
+        };
+    }
+    Ok(data)
+}
+
+fn flate_decode(data: &[u8]) -> Fallible<Vec<u8>> {
+    let mut output = Vec::new();
+    if let Err(err) = flate2::read::ZlibDecoder::new(data).read_to_end(&mut output) {
+        // truncated streams are common, so keep whatever could be decompressed
+        if output.is_empty() {
+            return Err(err.into());
+        }
+        log::warn!(
+            "flate stream is corrupt after {} bytes: {}",
+            output.len(),
+            err
+        );
+    }
+    Ok(output)
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let p = i16::from(left) + i16::from(up) - i16::from(up_left);
+    let pa = (p - i16::from(left)).abs();
+    let pb = (p - i16::from(up)).abs();
+    let pc = (p - i16::from(up_left)).abs();
+    if pa <= pb && pa <= pc {
+        left
+    } else if pb <= pc {
+        up
+    } else {
+        up_left
+    }
+}
+
+fn apply_predictor(data: Vec<u8>, parameters: &PredictorParameters) -> Fallible<Vec<u8>> {
+    let predictor = parameters.predictor.unwrap_or(1);
+    let colors = parameters.colors.unwrap_or(1);
+    let bits_per_component = parameters.bits_per_component.unwrap_or(8);
+    let columns = parameters.columns.unwrap_or(1);
+    if predictor == 1 {
+        return Ok(data);
+    }
+    // the sizes come straight from the file, so they are checked before rows are split
+    let row_bits = colors
+        .checked_mul(bits_per_component)
+        .and_then(|bits_per_pixel| bits_per_pixel.checked_mul(columns))
+        .filter(|row_bits| *row_bits > 0)
+        .ok_or_else(|| {
+            failure::format_err!(
+                "invalid predictor parameters: {} colours, {} bits per component, {} columns",
+                colors,
+                bits_per_component,
+                columns
+            )
+        })?;
+    let bytes_per_pixel = (colors * bits_per_component).div_ceil(8);
+    let row_length = row_bits.div_ceil(8);
+
+    match predictor {
+        2 => {
+            if bits_per_component != 8 {
+                log::warn!(
+                    "unsupported TIFF predictor with {} bits",
+                    bits_per_component
+                );
+                return Ok(data);
+            }
+            let mut data = data;
+            for row in data.chunks_mut(row_length) {
+                for index in bytes_per_pixel..row.len() {
+                    row[index] = row[index].wrapping_add(row[index - bytes_per_pixel]);
+                }
+            }
+            Ok(data)
+        }
+        10..=15 => {
+            let mut output = Vec::with_capacity(data.len());
+            let mut previous = vec![0; row_length];
+            for chunk in data.chunks(row_length + 1) {
+                let (filter_type, input) = (chunk[0], &chunk[1..]);
+                let mut row = vec![0; row_length];
+                for (index, byte) in input.iter().enumerate() {
+                    let left = if index >= bytes_per_pixel {
+                        row[index - bytes_per_pixel]
+                    } else {
+                        0
+                    };
+                    let up = previous[index];
+                    let up_left = if index >= bytes_per_pixel {
+                        previous[index - bytes_per_pixel]
+                    } else {
+                        0
+                    };
+                    row[index] = match filter_type {
+                        0 => *byte,
+                        1 => byte.wrapping_add(left),
+                        2 => byte.wrapping_add(up),
+                        3 => byte.wrapping_add(((u16::from(left) + u16::from(up)) / 2) as u8),
+                        4 => byte.wrapping_add(paeth(left, up, up_left)),
+                        _ => failure::bail!("invalid PNG filter type {}", filter_type),
+                    };
+                }
+                output.extend_from_slice(&row[..input.len()]);
+                previous = row;
+            }
+            Ok(output)
+        }
+        _ => failure::bail!("unsupported predictor {}", predictor),
+    }
+}
+
+fn ascii_hex_decode(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len() / 2);
+    let mut high = None;
+    for byte in data.iter() {
+        if *byte == b'>' {
+            break;
+        }
+        let digit = match (*byte as char).to_digit(16) {
+            Some(digit) => digit as u8,
+            None => continue,
+        };
+        match high.take() {
+            Some(high) => output.push(high << 4 | digit),
+            None => high = Some(digit),
+        }
+    }
+    if let Some(high) = high {
+        output.push(high << 4);
+    }
+    output
+}
+
+fn ascii85_decode(data: &[u8]) -> Fallible<Vec<u8>> {
+    let mut output = Vec::with_capacity(data.len() * 4 / 5);
+    let mut group = Vec::with_capacity(5);
+    let mut bytes = data.iter().cloned();
+    if data.starts_with(b"<~") {
+        bytes.nth(1);
+    }
+    for byte in bytes {
+        match byte {
+            b'~' => break,
+            b'z' if group.is_empty() => output.extend_from_slice(&[0; 4]),
+            b'!'..=b'u' => {
+                group.push(u32::from(byte - b'!'));
+                if group.len() == 5 {
+                    let value = group.iter().fold(0u32, |value, digit| {
+                        value.wrapping_mul(85).wrapping_add(*digit)
+                    });
+                    output.extend_from_slice(&value.to_be_bytes());
+                    group.clear();
+                }
+            }
+            byte if byte.is_ascii_whitespace() => {}
+            _ => failure::bail!("invalid character in ASCII85 stream"),
+        }
+    }
+    if !group.is_empty() {
+        // a final partial group is padded with the highest digit
+        let length = group.len() - 1;
+        group.resize(5, 84);
+        let value = group.iter().fold(0u32, |value, digit| {
+            value.wrapping_mul(85).wrapping_add(*digit)
+        });
+        output.extend_from_slice(&value.to_be_bytes()[..length]);
+    }
+    Ok(output)
+}
+
+fn run_length_decode(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut index = 0;
+    while index < data.len() {
+        let length = data[index] as usize;
+        index += 1;
+        if length < 128 {
+            let end = (index + length + 1).min(data.len());
+            output.extend_from_slice(&data[index..end]);
+            index = end;
+        } else if length > 128 {
+            if let Some(byte) = data.get(index) {
+                output.extend(std::iter::repeat_n(*byte, 257 - length));
+            }
+            index += 1;
+        } else {
+            break;
+        }
+    }
+    output
+}
+
+fn dct_decode(data: &[u8]) -> Fallible<Vec<u8>> {
+    jpeg_decoder::Decoder::new(data)
+        .decode()
+        .map_err(|err| failure::format_err!("failed to decode JPEG data: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_predictor() {
+        let parameters = PredictorParameters {
+            predictor: Some(15),
+            colors: Some(1),
+            bits_per_component: Some(8),
+            columns: Some(3),
+        };
+        let data = vec![1, 10, 5, 5, 2, 1, 1, 1, 4, 1, 1, 1];
+        assert_eq!(
+            vec![10, 15, 20, 11, 16, 21, 12, 17, 22],
+            apply_predictor(data, &parameters).unwrap()
+        );
+    }
+
+    #[test]
+    fn tiff_predictor() {
+        let parameters = PredictorParameters {
+            predictor: Some(2),
+            colors: Some(2),
+            bits_per_component: Some(8),
+            columns: Some(2),
+        };
+        let data = vec![10, 20, 1, 2, 30, 40, 3, 4];
+        assert_eq!(
+            vec![10, 20, 11, 22, 30, 40, 33, 44],
+            apply_predictor(data, &parameters).unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_predictor_parameters() {
+        for &(colors, bits_per_component, columns) in &[(1, 8, 0), (0, 8, 1), (1, 0, 1)] {
+            let parameters = PredictorParameters {
+                predictor: Some(12),
+                colors: Some(colors),
+                bits_per_component: Some(bits_per_component),
+                columns: Some(columns),
+            };
+            assert!(apply_predictor(vec![2, 1, 1], &parameters).is_err());
+        }
+    }
+
+    #[test]
+    fn ascii_filters() {
+        assert_eq!(b"Man ".to_vec(), ascii_hex_decode(b"4d 616E2>"));
+        assert_eq!(b"Man".to_vec(), ascii85_decode(b"<~9jqo~>").unwrap());
+        assert_eq!(
+            vec![1, 2, 3, 7, 7, 7, 7],
+            run_length_decode(&[2, 1, 2, 3, 253, 7, 128])
+        );
+    }
+}
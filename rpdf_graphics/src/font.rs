@@ -1,4 +1,5 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use failure::Fallible;
@@ -6,113 +7,698 @@ use serde_derive::Deserialize;
 
 use rpdf_lopdf_extra::*;
 
+use crate::data::Name;
+use crate::resources::Resources;
+
+mod cff;
+mod cmap;
+pub use self::cmap::CharCode;
+mod composite;
+pub use self::composite::VerticalMetrics;
 mod encoding;
 pub use self::encoding::GlyphName;
 mod loaded;
 pub use self::loaded::LoadedFont;
+mod standard;
+mod substitute;
+mod system;
+pub use self::system::{default_font_directories, set_font_directories};
+mod truetype;
+mod type1;
+mod type3;
+pub use self::type3::Type3Glyphs;
+
+const FLAG_FIXED_PITCH: u32 = 1;
+const FLAG_SERIF: u32 = 1 << 1;
+const FLAG_SYMBOLIC: u32 = 1 << 2;
+/// Font descriptor flag for fonts whose glyphs are a subset of the standard Latin set.
+const FLAG_NONSYMBOLIC: u32 = 1 << 5;
+const FLAG_ITALIC: u32 = 1 << 6;
+const FLAG_FORCE_BOLD: u32 = 1 << 18;
 
 #[derive(Clone, Copy, Debug, Deserialize)]
 #[serde(variant_identifier)]
 pub enum Subtype {
+    Type0,
     Type1,
+    TrueType,
+    Type3,
 }
 
+static NEXT_FONT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Identifies a loaded font, so that renderers can cache per-font data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FontId(usize);
+
 pub struct Font {
+    id: FontId,
+    data: Arc<Vec<u8>>,
+    subtype: Subtype,
+    kind: FontKind,
+}
+
+enum FontKind {
+    Simple(SimpleFont),
+    Composite(composite::CompositeFont),
+    Type3(SimpleFont, Type3Glyphs),
+}
+
+/// A font with single-byte character codes.
+struct SimpleFont {
     first_char: i64,
     last_char: i64,
     widths: Vec<f64>,
-    data: Arc<Vec<u8>>,
-    subtype: Subtype,
-    encoding: Option<encoding::Encoding>,
+    encoding: encoding::EncodingTable,
+    /// The character to glyph mappings of a TrueType font program.
+    cmap: Option<truetype::Cmap>,
+    /// Whether glyphs are selected by character code rather than by glyph name.
+    symbolic: bool,
+    /// The horizontal scale of each glyph of a substitute font program that makes it as
+    /// wide as the font's widths say, or empty if the program is the font's own.
+    glyph_scales: Vec<f64>,
+}
+
+fn font_descriptor<'a>(
+    doc: &'a lopdf::Document,
+    dict: &'a lopdf::Dictionary,
+) -> Fallible<&'a lopdf::Dictionary> {
+    doc.resolve_object(dict.try_get(b"FontDescriptor")?)?
+        .try_as_dict()
+}
+
+/// A font program, embedded through a font descriptor or installed on the system.
+enum FontProgram {
+    Type1(Vec<u8>),
+    TrueType(Vec<u8>),
+    /// A CFF program, in an OpenType container whether or not it was embedded in one.
+    Cff(Vec<u8>, cff::Cff),
+}
+
+impl FontProgram {
+    fn try_from_descriptor(
+        doc: &lopdf::Document,
+        descriptor: &lopdf::Dictionary,
+    ) -> Fallible<Self> {
+        if let Some(file_obj) = descriptor.get(b"FontFile") {
+            let file = doc.resolve_object(file_obj)?.try_as_stream()?;
+            return Ok(FontProgram::Type1(file.decoded_content()));
+        }
+        if let Some(file_obj) = descriptor.get(b"FontFile2") {
+            let file = doc.resolve_object(file_obj)?.try_as_stream()?;
+            return Ok(FontProgram::TrueType(file.decoded_content()));
+        }
+        if let Some(file_obj) = descriptor.get(b"FontFile3") {
+            let file = doc.resolve_object(file_obj)?.try_as_stream()?;
+            let data = file.decoded_content();
+            let Name(subtype) = doc.deserialize_object(file.dict.try_get(b"Subtype")?)?;
+            return match subtype.as_slice() {
+                b"Type1C" | b"CIDFontType0C" => {
+                    let cff = cff::Cff::parse(&data)?;
+                    let font = cff
+                        .to_opentype(&data)
+                        .ok_or_else(|| failure::format_err!("CFF font program is too large"))?;
+                    Ok(FontProgram::Cff(font, cff))
+                }
+                b"OpenType" => match truetype::find_table(&data, b"CFF ") {
+                    Some(table) => {
+                        let cff = cff::Cff::parse(table)?;
+                        Ok(FontProgram::Cff(data, cff))
+                    }
+                    None => Ok(FontProgram::TrueType(data)),
+                },
+                _ => failure::bail!(
+                    "unsupported font program {}",
+                    String::from_utf8_lossy(&subtype)
+                ),
+            };
+        }
+        failure::bail!("font is missing glyph data")
+    }
+
+    /// Reads a font program installed on the system, which may be a TrueType or
+    /// OpenType font, the face at `index` of a collection of them, or a Type 1 font in
+    /// either PFA or PFB format.
+    fn from_file(path: &std::path::Path, index: u32) -> Fallible<Self> {
+        let mut data = std::fs::read(path)?;
+        if data.starts_with(b"ttcf") {
+            data = truetype::collection_face(&data, index)
+                .ok_or_else(|| failure::format_err!("{} has no face {}", path.display(), index))?;
+        }
+        match data.get(..4) {
+            Some(b"\x00\x01\x00\x00") | Some(b"true") => Ok(FontProgram::TrueType(data)),
+            Some(b"OTTO") => {
+                let table = truetype::find_table(&data, b"CFF ")
+                    .ok_or_else(|| failure::format_err!("OpenType font is missing a CFF table"))?;
+                let cff = cff::Cff::parse(table)?;
+                Ok(FontProgram::Cff(data, cff))
+            }
+            Some([0x80, 0x01, ..]) | Some([b'%', b'!', ..]) => Ok(FontProgram::Type1(data)),
+            _ => failure::bail!("unsupported font file {}", path.display()),
+        }
+    }
 }
 
 impl Font {
     pub fn try_from_dictionary(doc: &lopdf::Document, dict: &lopdf::Dictionary) -> Fallible<Self> {
         let subtype = doc.deserialize_object(dict.try_get(b"Subtype")?)?;
+        let (data, kind) = match subtype {
+            Subtype::Type0 => Self::load_composite(doc, dict)?,
+            Subtype::Type1 | Subtype::TrueType => Self::load_simple(doc, dict)?,
+            Subtype::Type3 => Self::load_type3(doc, dict)?,
+        };
+        Ok(Font {
+            id: FontId(NEXT_FONT_ID.fetch_add(1, Ordering::Relaxed)),
+            data,
+            subtype,
+            kind,
+        })
+    }
 
-        let mut encoding = None;
-        if let Some(encoding_obj) = dict.get(b"Encoding") {
-            encoding = Some(doc.deserialize_object(encoding_obj)?);
-            log::debug!("font has encoding {:?}", encoding);
-        }
-
-        let data;
-        match subtype {
-            Subtype::Type1 => {
-                let descriptor = doc
-                    .resolve_object(dict.try_get(b"FontDescriptor")?)?
-                    .try_as_dict()?;
-
-                if let Some(file_obj) = descriptor.get(b"FontFile") {
-                    let file = doc.resolve_object(file_obj)?.try_as_stream()?;
-                    if let Some(content) = file.decompressed_content() {
-                        data = Arc::new(content);
-                    } else {
-                        data = Arc::new(file.content.clone());
+    fn load_composite(
+        doc: &lopdf::Document,
+        dict: &lopdf::Dictionary,
+    ) -> Fallible<(Arc<Vec<u8>>, FontKind)> {
+        let descendant_obj = doc
+            .resolve_object(dict.try_get(b"DescendantFonts")?)?
+            .as_array()
+            .and_then(|descendants| descendants.first())
+            .ok_or_else(|| failure::format_err!("font is missing a descendant font"))?;
+        let descendant = doc.resolve_object(descendant_obj)?.try_as_dict()?;
+        let program = font_descriptor(doc, descendant)
+            .and_then(|descriptor| FontProgram::try_from_descriptor(doc, descriptor));
+        let mut font = composite::CompositeFont::try_from_dictionary(doc, dict, descendant_obj)?;
+        let data = match program {
+            Ok(FontProgram::Cff(data, cff)) => {
+                // glyphs of CID-keyed CFF programs are found through their charset
+                if let Some(cid_to_gid) = cff.cid_to_gid() {
+                    font.set_cid_to_gid(cid_to_gid);
+                }
+                data
+            }
+            Ok(FontProgram::Type1(data)) | Ok(FontProgram::TrueType(data)) => data,
+            // fonts that aren't embedded are drawn with an installed face instead
+            Err(err) => {
+                log::debug!("substituting composite font: {}", err);
+                Self::substitute_composite(doc, dict, descendant, &mut font)?
+            }
+        };
+        Ok((Arc::new(data), FontKind::Composite(font)))
+    }
+
+    /// Finds an installed face to draw a composite font that isn't embedded with. Its
+    /// glyphs are selected through the Unicode values of the font's ToUnicode CMap,
+    /// so without one the font is positioned but not drawn.
+    fn substitute_composite(
+        doc: &lopdf::Document,
+        dict: &lopdf::Dictionary,
+        descendant: &lopdf::Dictionary,
+        font: &mut composite::CompositeFont,
+    ) -> Fallible<Vec<u8>> {
+        let base_font = match dict.get(b"BaseFont") {
+            Some(obj) => {
+                let Name(name) = doc.deserialize_object(obj)?;
+                name
+            }
+            None => Vec::new(),
+        };
+        let name = String::from_utf8_lossy(&base_font);
+
+        let to_unicode = match dict.get(b"ToUnicode") {
+            Some(obj) => {
+                let stream = doc.resolve_object(obj)?.try_as_stream()?;
+                cmap::parse_to_unicode(&stream.decoded_content())
+            }
+            None => {
+                log::warn!(
+                    "not drawing {}, which has neither a program nor a ToUnicode CMap",
+                    name
+                );
+                return Ok(Vec::new());
+            }
+        };
+        let description = substitute::FontDescription::from_descriptor(
+            doc,
+            &base_font,
+            descendant.get(b"FontDescriptor"),
+            0,
+        )?;
+        let program = match description.find_substitute() {
+            Some((path, index)) => FontProgram::from_file(&path, index)
+                .map_err(|err| log::warn!("failed to read substitute font: {}", err))
+                .ok(),
+            None => None,
+        };
+        let data = match program {
+            Some(FontProgram::TrueType(data)) | Some(FontProgram::Cff(data, _)) => data,
+            Some(FontProgram::Type1(_)) | None => {
+                log::warn!("no substitute font installed for {}", name);
+                return Ok(Vec::new());
+            }
+        };
+        match truetype::Cmap::parse(&data) {
+            Ok(cmap) => font.select_glyphs_by_unicode(&to_unicode, |unicode| {
+                cmap.lookup(3, 10, unicode)
+                    .or_else(|| cmap.lookup(3, 1, unicode))
+            }),
+            Err(err) => {
+                log::warn!("not drawing substitute for {}: {}", name, err);
+                return Ok(Vec::new());
+            }
+        }
+        Ok(data)
+    }
+
+    fn load_type3(
+        doc: &lopdf::Document,
+        dict: &lopdf::Dictionary,
+    ) -> Fallible<(Arc<Vec<u8>>, FontKind)> {
+        let glyphs = Type3Glyphs::try_from_dictionary(doc, dict)?;
+        // glyph names are only defined through the differences of the encoding
+        let encoding = match dict.get(b"Encoding") {
+            Some(encoding_obj) => {
+                let font_encoding: encoding::Encoding = doc.deserialize_object(encoding_obj)?;
+                font_encoding.to_table(&encoding::EncodingTable::empty())
+            }
+            None => encoding::EncodingTable::empty(),
+        };
+        let font = SimpleFont {
+            first_char: doc.deserialize_object(dict.try_get(b"FirstChar")?)?,
+            last_char: doc.deserialize_object(dict.try_get(b"LastChar")?)?,
+            widths: doc.deserialize_object(dict.try_get(b"Widths")?)?,
+            encoding,
+            cmap: None,
+            symbolic: false,
+            glyph_scales: Vec::new(),
+        };
+        Ok((Arc::new(Vec::new()), FontKind::Type3(font, glyphs)))
+    }
+
+    fn load_simple(
+        doc: &lopdf::Document,
+        dict: &lopdf::Dictionary,
+    ) -> Fallible<(Arc<Vec<u8>>, FontKind)> {
+        let base_font = match dict.get(b"BaseFont") {
+            Some(obj) => {
+                let Name(name) = doc.deserialize_object(obj)?;
+                name
+            }
+            None => Vec::new(),
+        };
+        let standard_font = standard::StandardFont::find(&base_font);
+        let descriptor = match dict.get(b"FontDescriptor") {
+            Some(obj) => Some(doc.resolve_object(obj)?.try_as_dict()?),
+            None => None,
+        };
+        let flags: u32 = match descriptor.and_then(|descriptor| descriptor.get(b"Flags")) {
+            Some(obj) => doc.deserialize_object(obj)?,
+            None => 0,
+        };
+
+        // fonts that aren't embedded are drawn with an installed face instead
+        let program = match descriptor {
+            Some(descriptor) => FontProgram::try_from_descriptor(doc, descriptor),
+            None => Err(failure::format_err!("font is missing a font descriptor")),
+        };
+        let mut substituted = false;
+        let program = match program {
+            Ok(program) => Some(program),
+            Err(err) => {
+                let name = String::from_utf8_lossy(&base_font);
+                log::debug!("substituting {}: {}", name, err);
+                substituted = true;
+                match Self::find_substitute(doc, dict, &base_font, standard_font)? {
+                    Some((path, index)) => FontProgram::from_file(&path, index)
+                        .map_err(|err| log::warn!("failed to read substitute font: {}", err))
+                        .ok(),
+                    None => {
+                        log::warn!("no substitute font installed for {}", name);
+                        None
                     }
-                } else {
-                    failure::bail!("font is missing glyph data");
                 }
             }
         };
 
-        let first_char = doc.deserialize_object(dict.try_get(b"FirstChar")?)?;
-        let last_char = doc.deserialize_object(dict.try_get(b"LastChar")?)?;
-        let widths = doc.deserialize_object(dict.try_get(b"Widths")?)?;
+        let mut cmap = None;
+        let (data, built_in_encoding) = match program {
+            Some(FontProgram::Type1(data)) => {
+                let built_in_encoding = type1::built_in_encoding(&data);
+                (data, built_in_encoding)
+            }
+            Some(FontProgram::TrueType(data)) => {
+                match truetype::Cmap::parse(&data) {
+                    Ok(font_cmap) => cmap = Some(font_cmap),
+                    Err(err) => log::warn!("selecting TrueType glyphs by name: {}", err),
+                }
+                (data, None)
+            }
+            Some(FontProgram::Cff(data, cff)) => {
+                // installed OpenType fonts have a cmap, unlike embedded CFF programs
+                if substituted {
+                    cmap = truetype::Cmap::parse(&data).ok();
+                }
+                let built_in_encoding = cff.built_in_encoding();
+                (data, built_in_encoding)
+            }
+            None => (Vec::new(), None),
+        };
+
+        // the font program's own encoding applies unless the font dictionary overrides it,
+        // but a substitute is read with the encoding of the font it stands in for
+        let built_in_encoding = match built_in_encoding {
+            Some(built_in_encoding) if !substituted => built_in_encoding,
+            _ => encoding::EncodingTable::for_base_font(
+                standard_font.map_or(&base_font[..], |font| font.name().as_bytes()),
+            ),
+        };
+        let mut named_encoding = false;
+        let encoding = match dict.get(b"Encoding") {
+            Some(encoding_obj) => {
+                let font_encoding: encoding::Encoding = doc.deserialize_object(encoding_obj)?;
+                log::debug!("font has encoding {:?}", font_encoding);
+                named_encoding = matches!(
+                    font_encoding,
+                    encoding::Encoding::Predefined(
+                        encoding::PredefinedEncoding::MacRomanEncoding
+                            | encoding::PredefinedEncoding::WinAnsiEncoding
+                    )
+                );
+                font_encoding.to_table(&built_in_encoding)
+            }
+            None => built_in_encoding,
+        };
+        // TrueType glyphs are looked up by name only for fonts with a standard Latin
+        // character set, which are expected to have a Unicode or Mac OS Roman cmap
+        let symbolic = match standard_font {
+            Some(standard_font) if substituted => standard_font.is_symbolic(),
+            _ => !named_encoding && flags & FLAG_NONSYMBOLIC == 0,
+        };
+
+        // widths of the standard fonts may be omitted, in which case they come from the
+        // font metrics of the glyph that each code is encoded as
+        let (first_char, last_char, widths) = match (dict.get(b"Widths"), standard_font) {
+            (None, Some(standard_font)) => {
+                let widths = (0..=255)
+                    .map(|c| {
+                        encoding
+                            .lookup(c)
+                            .and_then(|glyph_name| standard_font.width(glyph_name.as_bytes()))
+                            .map_or(0.0, f64::from)
+                    })
+                    .collect();
+                (0, 255, widths)
+            }
+            _ => (
+                doc.deserialize_object(dict.try_get(b"FirstChar")?)?,
+                doc.deserialize_object(dict.try_get(b"LastChar")?)?,
+                doc.deserialize_object(dict.try_get(b"Widths")?)?,
+            ),
+        };
 
-        Ok(Font {
+        let mut font = SimpleFont {
             first_char,
             last_char,
             widths,
-            data,
-            subtype,
             encoding,
-        })
+            cmap,
+            symbolic,
+            glyph_scales: Vec::new(),
+        };
+        if substituted {
+            font.glyph_scales = font.substitute_glyph_scales(&data);
+        }
+        Ok((Arc::new(data), FontKind::Simple(font)))
+    }
+
+    /// Finds an installed face to draw a font that isn't embedded with: one with the
+    /// same metrics for the standard 14 fonts, or else the face that most closely
+    /// resembles the font's description.
+    fn find_substitute(
+        doc: &lopdf::Document,
+        dict: &lopdf::Dictionary,
+        base_font: &[u8],
+        standard_font: Option<&standard::StandardFont>,
+    ) -> Fallible<Option<(std::path::PathBuf, u32)>> {
+        if let Some(path) = standard_font.and_then(standard::StandardFont::substitute) {
+            return Ok(Some((path, 0)));
+        }
+        let description = substitute::FontDescription::from_descriptor(
+            doc,
+            base_font,
+            dict.get(b"FontDescriptor"),
+            standard_font.map_or(0, standard::StandardFont::flags),
+        )?;
+        Ok(description.find_substitute())
+    }
+
+    pub fn id(&self) -> FontId {
+        self.id
     }
 
     pub fn data(&self) -> &[u8] {
         self.data.as_slice()
     }
 
+    /// Whether the font has a program to draw its glyphs with. Type 3 fonts have none,
+    /// and neither do standard fonts that no substitute is installed for.
+    pub fn has_program(&self) -> bool {
+        !self.data.is_empty()
+    }
+
+    /// Splits a string shown with the font into character codes.
+    pub fn char_codes<'a>(&'a self, bytes: &'a [u8]) -> Box<dyn Iterator<Item = CharCode> + 'a> {
+        match self.kind {
+            FontKind::Simple(_) | FontKind::Type3(..) => {
+                Box::new(bytes.iter().map(|byte| CharCode {
+                    code: u32::from(*byte),
+                    length: 1,
+                }))
+            }
+            FontKind::Composite(ref font) => Box::new(font.char_codes(bytes)),
+        }
+    }
+
     pub fn decode_char(&self, c: u8) -> Option<&GlyphName> {
-        if let Some(ref encoding) = self.encoding {
-            encoding.lookup(c)
-        } else {
-            None
+        match self.kind {
+            FontKind::Simple(ref font) | FontKind::Type3(ref font, _) => font.encoding.lookup(c),
+            FontKind::Composite(_) => None,
         }
     }
 
-    pub fn width_for_char(&self, c: u8) -> f64 {
-        if i64::from(c) < self.first_char || i64::from(c) > self.last_char {
-            return 0.0;
+    /// Returns the glyph of the font program that a character code selects, or 0 if the
+    /// program couldn't be loaded. Glyphs of Type 3 fonts, which have no program, are
+    /// identified by their character code.
+    pub fn glyph_index(&self, loaded_font: Option<&LoadedFont>, code: CharCode) -> u32 {
+        let font = match self.kind {
+            FontKind::Simple(ref font) => font,
+            FontKind::Composite(ref font) => return font.glyph_index(code),
+            FontKind::Type3(..) => return code.code,
+        };
+        let c = code.code as u8;
+        if let Some(index) = font
+            .cmap
+            .as_ref()
+            .and_then(|cmap| font.truetype_glyph_index(cmap, c))
+        {
+            return index;
+        }
+        let loaded_font = match loaded_font {
+            Some(loaded_font) => loaded_font,
+            None => return 0,
+        };
+        match font.encoding.lookup(c) {
+            Some(glyph_name) => loaded_font.glyph_index_for_name(glyph_name.as_bytes()),
+            None => loaded_font.glyph_index_for_char(c as char),
+        }
+    }
+
+    /// Returns the horizontal scale to draw a glyph with, which differs from 1 for fonts
+    /// that are drawn with a substitute font program.
+    pub fn glyph_scale(&self, code: CharCode) -> f64 {
+        match self.kind {
+            FontKind::Simple(ref font) => font
+                .glyph_scales
+                .get(code.code as usize)
+                .copied()
+                .unwrap_or(1.0),
+            FontKind::Composite(_) | FontKind::Type3(..) => 1.0,
+        }
+    }
+
+    /// Returns the horizontal displacement of a glyph, per unit of font size.
+    pub fn width(&self, code: CharCode) -> f64 {
+        match self.kind {
+            FontKind::Simple(ref font) => font.width(code.code as u8) / 1000.0,
+            FontKind::Composite(ref font) => font.width(code),
+            // widths of Type 3 glyphs are given in glyph space
+            FontKind::Type3(ref font, ref glyphs) => {
+                font.width(code.code as u8) * f64::from(glyphs.matrix().m11)
+            }
+        }
+    }
+
+    pub fn is_vertical(&self) -> bool {
+        match self.kind {
+            FontKind::Simple(_) | FontKind::Type3(..) => false,
+            FontKind::Composite(ref font) => font.is_vertical(),
+        }
+    }
+
+    /// Returns the metrics of a glyph if the font is used in vertical writing mode.
+    pub fn vertical_metrics(&self, code: CharCode) -> Option<VerticalMetrics> {
+        match self.kind {
+            FontKind::Simple(_) | FontKind::Type3(..) => None,
+            FontKind::Composite(ref font) => font.vertical_metrics(code),
+        }
+    }
+
+    /// Returns the glyph descriptions of a Type 3 font, which has no font program.
+    pub fn type3_glyphs(&self) -> Option<&Type3Glyphs> {
+        match self.kind {
+            FontKind::Type3(_, ref glyphs) => Some(glyphs),
+            _ => None,
         }
-        let index = i64::from(c) - self.first_char;
-        let width = self.widths[index as usize];
-        width / 1000.0
     }
 
     pub fn load(&self) -> Fallible<LoadedFont> {
+        if !self.has_program() {
+            failure::bail!("font has no font program");
+        }
         LoadedFont::from_bytes(self.data.clone())
     }
 }
 
+impl SimpleFont {
+    /// Selects a glyph through the cmap of a TrueType font, using the (3, 1) Unicode or
+    /// (1, 0) Mac OS Roman subtables for named glyphs, and the (3, 0) symbol subtable
+    /// for codes of symbolic fonts.
+    fn truetype_glyph_index(&self, cmap: &truetype::Cmap, c: u8) -> Option<u32> {
+        let code = u32::from(c);
+        if self.symbolic {
+            // symbol subtables commonly map single-byte codes into the private use area
+            return [0x0000, 0xf000, 0xf100, 0xf200]
+                .iter()
+                .find_map(|base| cmap.lookup(3, 0, base + code))
+                .or_else(|| cmap.lookup(1, 0, code))
+                .or_else(|| cmap.lookup(3, 1, code));
+        }
+
+        let glyph_name = self.encoding.lookup(c)?;
+        if cmap.has_subtable(3, 1) {
+            match glyph_name.to_char() {
+                '\u{fffd}' => None,
+                unicode => cmap.lookup(3, 1, unicode as u32),
+            }
+        } else {
+            let mac_code = encoding::tables::MAC_ROMAN
+                .iter()
+                .position(|name| name.map(str::as_bytes) == Some(glyph_name.as_bytes()))?;
+            cmap.lookup(1, 0, mac_code as u32)
+        }
+    }
+
+    /// Computes the horizontal scale of each glyph of a substitute font program, which
+    /// makes its advance width match the width of the code it is selected by.
+    fn substitute_glyph_scales(&self, data: &[u8]) -> Vec<f64> {
+        let cmap = match self.cmap {
+            Some(ref cmap) => cmap,
+            None => return Vec::new(),
+        };
+        let metrics = match truetype::HorizontalMetrics::parse(data) {
+            Ok(metrics) => metrics,
+            Err(err) => {
+                log::warn!("not scaling substitute glyphs: {}", err);
+                return Vec::new();
+            }
+        };
+        (0..=255)
+            .map(|c| {
+                let width = self.width(c) / 1000.0;
+                let advance = self
+                    .truetype_glyph_index(cmap, c)
+                    .map_or(0.0, |glyph| metrics.advance(glyph));
+                if width > 0.0 && advance > 0.0 {
+                    width / advance
+                } else {
+                    1.0
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the width of a glyph in glyph space units.
+    fn width(&self, c: u8) -> f64 {
+        if i64::from(c) < self.first_char || i64::from(c) > self.last_char {
+            return 0.0;
+        }
+        let index = i64::from(c) - self.first_char;
+        // the Widths array may be shorter than its character range says
+        self.widths.get(index as usize).copied().unwrap_or(0.0)
+    }
+}
+
 pub struct FontMap {
-    map: HashMap<Vec<u8>, Font>,
+    map: HashMap<Vec<u8>, Arc<Font>>,
 }
 
 impl FontMap {
-    pub fn try_from_page_fonts(
-        doc: &lopdf::Document,
-        page_fonts: BTreeMap<Vec<u8>, &lopdf::Dictionary>,
-    ) -> Fallible<Self> {
-        let map = page_fonts
+    pub fn try_from_resources(resources: &Resources) -> Fallible<Self> {
+        let doc = resources.document();
+        let map = resources
+            .entries(b"Font")
             .into_iter()
-            .flat_map(|(name, dict)| Some((name, Font::try_from_dictionary(doc, dict).ok()?)))
+            .flat_map(|(name, obj)| {
+                let font = Font::try_from_dictionary(doc, obj.as_dict()?)
+                    .map_err(|err| {
+                        log::warn!("skipping font /{}: {}", String::from_utf8_lossy(&name), err)
+                    })
+                    .ok()?;
+                Some((name, Arc::new(font)))
+            })
             .collect::<HashMap<_, _>>();
         Ok(FontMap { map })
     }
 
-    pub fn get(&self, name: &[u8]) -> Option<&Font> {
+    pub fn get(&self, name: &[u8]) -> Option<&Arc<Font>> {
         self.map.get(name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standard_font_without_widths() {
+        let doc = lopdf::Document::new();
+        let mut dict = lopdf::Dictionary::new();
+        dict.set("Type", lopdf::Object::Name(b"Font".to_vec()));
+        dict.set("Subtype", lopdf::Object::Name(b"Type1".to_vec()));
+        dict.set("BaseFont", lopdf::Object::Name(b"Helvetica".to_vec()));
+        dict.set("Encoding", lopdf::Object::Name(b"WinAnsiEncoding".to_vec()));
+        let font = Font::try_from_dictionary(&doc, &dict).unwrap();
+        let width = |code| font.width(CharCode { code, length: 1 });
+        assert_eq!(0.667, width(u32::from(b'A')));
+        assert_eq!(0.333, width(0x93));
+        assert_eq!(0.0, width(0x10));
+    }
+
+    #[test]
+    fn widths_shorter_than_character_range() {
+        let doc = lopdf::Document::new();
+        let mut dict = lopdf::Dictionary::new();
+        dict.set("Type", lopdf::Object::Name(b"Font".to_vec()));
+        dict.set("Subtype", lopdf::Object::Name(b"Type1".to_vec()));
+        dict.set("BaseFont", lopdf::Object::Name(b"Helvetica".to_vec()));
+        dict.set("FirstChar", lopdf::Object::Integer(32));
+        dict.set("LastChar", lopdf::Object::Integer(126));
+        dict.set(
+            "Widths",
+            lopdf::Object::Array(vec![lopdf::Object::Integer(278)]),
+        );
+        let font = Font::try_from_dictionary(&doc, &dict).unwrap();
+        let width = |code| font.width(CharCode { code, length: 1 });
+        assert_eq!(0.278, width(32));
+        assert_eq!(0.0, width(u32::from(b'A')));
+    }
+}
@@ -0,0 +1,507 @@
+use std::collections::HashMap;
+
+use failure::Fallible;
+
+use super::encoding::{EncodingTable, GlyphName};
+use super::truetype::{self, read_u16};
+
+mod strings;
+use self::strings::{STANDARD_STRINGS, STANDARD_STRING_COUNT};
+
+const OP_FONT_BBOX: u16 = 5;
+const OP_CHARSET: u16 = 15;
+const OP_ENCODING: u16 = 16;
+const OP_CHAR_STRINGS: u16 = 17;
+const OP_FONT_MATRIX: u16 = 0x0c07;
+const OP_ROS: u16 = 0x0c1e;
+
+fn read_offset(data: &[u8], offset: usize, size: usize) -> Option<usize> {
+    let bytes = data.get(offset..offset + size)?;
+    Some(
+        bytes
+            .iter()
+            .fold(0, |value, byte| value << 8 | usize::from(*byte)),
+    )
+}
+
+/// Reads an INDEX structure, returning its items and the offset following it.
+fn parse_index(data: &[u8], offset: usize) -> Option<(Vec<&[u8]>, usize)> {
+    let count = usize::from(read_u16(data, offset)?);
+    if count == 0 {
+        return Some((Vec::new(), offset + 2));
+    }
+    let offset_size = usize::from(*data.get(offset + 2)?);
+    if offset_size == 0 || offset_size > 4 {
+        return None;
+    }
+    let offsets = (0..=count)
+        .map(|index| read_offset(data, offset + 3 + index * offset_size, offset_size))
+        .collect::<Option<Vec<_>>>()?;
+    // item offsets are relative to the byte preceding the item data
+    let base = offset + 2 + (count + 1) * offset_size;
+    let items = offsets
+        .windows(2)
+        .map(|pair| data.get(base + pair[0]..base + pair[1]))
+        .collect::<Option<Vec<_>>>()?;
+    Some((items, base + offsets[count]))
+}
+
+fn parse_real(data: &[u8], pos: &mut usize) -> Option<f64> {
+    let mut text = String::new();
+    loop {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        for nibble in [byte >> 4, byte & 0x0f].iter() {
+            match nibble {
+                0..=9 => text.push((b'0' + nibble) as char),
+                0xa => text.push('.'),
+                0xb => text.push('E'),
+                0xc => text.push_str("E-"),
+                0xe => text.push('-'),
+                0xf => return text.parse().ok(),
+                _ => return None,
+            }
+        }
+    }
+}
+
+/// Reads a DICT structure into its operands by operator.
+fn parse_dict(data: &[u8]) -> Option<HashMap<u16, Vec<f64>>> {
+    let mut dict = HashMap::new();
+    let mut operands = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let b0 = data[pos];
+        pos += 1;
+        match b0 {
+            12 => {
+                dict.insert(0x0c00 | u16::from(*data.get(pos)?), operands.split_off(0));
+                pos += 1;
+            }
+            0..=21 => {
+                dict.insert(u16::from(b0), operands.split_off(0));
+            }
+            28 => {
+                operands.push(f64::from(read_u16(data, pos)? as i16));
+                pos += 2;
+            }
+            29 => {
+                let value = truetype::read_u32(data, pos)? as i32;
+                operands.push(f64::from(value));
+                pos += 4;
+            }
+            30 => operands.push(parse_real(data, &mut pos)?),
+            32..=246 => operands.push(f64::from(i32::from(b0) - 139)),
+            247..=250 => {
+                let b1 = *data.get(pos)?;
+                pos += 1;
+                operands.push(f64::from((i32::from(b0) - 247) * 256 + i32::from(b1) + 108));
+            }
+            251..=254 => {
+                let b1 = *data.get(pos)?;
+                pos += 1;
+                operands.push(f64::from(
+                    -(i32::from(b0) - 251) * 256 - i32::from(b1) - 108,
+                ));
+            }
+            _ => return None,
+        }
+    }
+    Some(dict)
+}
+
+/// Reads a charset, which gives the string id (or CID) of each glyph but the first.
+fn parse_charset(data: &[u8], offset: usize, glyph_count: usize) -> Option<Vec<u16>> {
+    let mut charset = vec![0];
+    let format = *data.get(offset)?;
+    let mut pos = offset + 1;
+    while charset.len() < glyph_count {
+        match format {
+            0 => {
+                charset.push(read_u16(data, pos)?);
+                pos += 2;
+            }
+            1 | 2 => {
+                let first = read_u16(data, pos)?;
+                let left = if format == 1 {
+                    u16::from(*data.get(pos + 2)?)
+                } else {
+                    read_u16(data, pos + 2)?
+                };
+                pos += if format == 1 { 3 } else { 4 };
+                charset.extend((0..=left).map(|index| first.wrapping_add(index)));
+            }
+            _ => return None,
+        }
+    }
+    charset.truncate(glyph_count);
+    Some(charset)
+}
+
+#[derive(Debug)]
+enum BuiltInEncoding {
+    Standard,
+    Expert,
+    /// The glyph of each character code, as a string id.
+    Custom(Vec<(u8, u16)>),
+}
+
+fn parse_encoding(data: &[u8], offset: usize, charset: &[u16]) -> Option<BuiltInEncoding> {
+    let format = *data.get(offset)?;
+    let mut codes = Vec::new();
+    let mut pos = offset + 1;
+    let mut glyph = 1;
+    match format & 0x7f {
+        0 => {
+            let count = usize::from(*data.get(pos)?);
+            for code in data.get(pos + 1..pos + 1 + count)? {
+                codes.push((*code, *charset.get(glyph)?));
+                glyph += 1;
+            }
+            pos += 1 + count;
+        }
+        1 => {
+            let ranges = usize::from(*data.get(pos)?);
+            for range in 0..ranges {
+                let first = *data.get(pos + 1 + range * 2)?;
+                let left = *data.get(pos + 2 + range * 2)?;
+                for code in first..=first.saturating_add(left) {
+                    codes.push((code, *charset.get(glyph)?));
+                    glyph += 1;
+                }
+            }
+            pos += 1 + ranges * 2;
+        }
+        _ => return None,
+    }
+    // supplements map additional codes to glyphs that already have one
+    if format & 0x80 != 0 {
+        let count = usize::from(*data.get(pos)?);
+        for supplement in 0..count {
+            let record = pos + 1 + supplement * 3;
+            codes.push((*data.get(record)?, read_u16(data, record + 1)?));
+        }
+    }
+    Some(BuiltInEncoding::Custom(codes))
+}
+
+/// A font program in the Compact Font Format, as embedded with `FontFile3`.
+#[derive(Debug)]
+pub struct Cff {
+    name: Vec<u8>,
+    strings: Vec<Vec<u8>>,
+    glyph_count: u16,
+    /// The string id of each glyph, or its CID if the font is CID-keyed.
+    charset: Vec<u16>,
+    encoding: Option<BuiltInEncoding>,
+    cid_keyed: bool,
+    bbox: [i16; 4],
+    units_per_em: u16,
+}
+
+impl Cff {
+    pub fn parse(data: &[u8]) -> Fallible<Self> {
+        Self::parse_tables(data).ok_or_else(|| failure::format_err!("invalid CFF font program"))
+    }
+
+    fn parse_tables(data: &[u8]) -> Option<Self> {
+        if *data.first()? != 1 {
+            return None;
+        }
+        let header_size = usize::from(*data.get(2)?);
+        let (names, offset) = parse_index(data, header_size)?;
+        let (top_dicts, offset) = parse_index(data, offset)?;
+        let (strings, _) = parse_index(data, offset)?;
+        let top_dict = parse_dict(top_dicts.first()?)?;
+        let operand = |op| top_dict.get(&op).and_then(|operands| operands.first());
+
+        let char_strings = *operand(OP_CHAR_STRINGS)? as usize;
+        let glyph_count = read_u16(data, char_strings)?;
+        let cid_keyed = top_dict.contains_key(&OP_ROS);
+        let charset = match operand(OP_CHARSET).map_or(0, |offset| *offset as usize) {
+            // the predefined ISOAdobe charset numbers glyphs in string id order
+            0 => (0..glyph_count).collect(),
+            1 | 2 => Vec::new(),
+            offset => parse_charset(data, offset, usize::from(glyph_count))?,
+        };
+        let encoding = match operand(OP_ENCODING).map_or(0, |offset| *offset as usize) {
+            _ if cid_keyed => None,
+            0 => Some(BuiltInEncoding::Standard),
+            1 => Some(BuiltInEncoding::Expert),
+            offset => parse_encoding(data, offset, &charset),
+        };
+        let bbox = match top_dict.get(&OP_FONT_BBOX) {
+            Some(bbox) if bbox.len() == 4 => [
+                bbox[0] as i16,
+                bbox[1] as i16,
+                bbox[2] as i16,
+                bbox[3] as i16,
+            ],
+            _ => [0; 4],
+        };
+        let units_per_em = match operand(OP_FONT_MATRIX) {
+            Some(scale) if *scale > 0.0 => (1.0 / scale).round().clamp(16.0, 16384.0) as u16,
+            _ => 1000,
+        };
+
+        Some(Cff {
+            name: names.first().map_or_else(Vec::new, |name| name.to_vec()),
+            strings: strings.into_iter().map(<[u8]>::to_vec).collect(),
+            glyph_count,
+            charset,
+            encoding,
+            cid_keyed,
+            bbox,
+            units_per_em,
+        })
+    }
+
+    fn string(&self, sid: u16) -> Option<&[u8]> {
+        let sid = usize::from(sid);
+        if sid < STANDARD_STRING_COUNT {
+            Some(STANDARD_STRINGS[sid].as_bytes())
+        } else {
+            self.strings
+                .get(sid - STANDARD_STRING_COUNT)
+                .map(Vec::as_slice)
+        }
+    }
+
+    /// Returns the encoding of a simple font program, which maps character codes to
+    /// the names of its glyphs.
+    pub fn built_in_encoding(&self) -> Option<EncodingTable> {
+        match self.encoding.as_ref()? {
+            BuiltInEncoding::Standard => Some(EncodingTable::standard()),
+            BuiltInEncoding::Expert => None,
+            BuiltInEncoding::Custom(codes) => {
+                let mut table = EncodingTable::empty();
+                for &(code, sid) in codes {
+                    if let Some(name) = self.string(sid) {
+                        table.set(code, GlyphName::new(name));
+                    }
+                }
+                Some(table)
+            }
+        }
+    }
+
+    /// Returns the glyph of each CID of a CID-keyed font program.
+    pub fn cid_to_gid(&self) -> Option<Vec<u16>> {
+        if !self.cid_keyed {
+            return None;
+        }
+        let max_cid = self.charset.iter().max().copied().unwrap_or(0);
+        let mut cid_to_gid = vec![0; usize::from(max_cid) + 1];
+        for (gid, cid) in self.charset.iter().enumerate() {
+            cid_to_gid[usize::from(*cid)] = gid as u16;
+        }
+        Some(cid_to_gid)
+    }
+
+    /// Wraps the font program in an OpenType container, which font loaders accept
+    /// more widely than a bare CFF program. The added tables only carry the metrics
+    /// a loader requires, since glyphs are positioned using the PDF's own widths.
+    /// Returns `None` if the program is too large for an OpenType table.
+    pub fn to_opentype(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let [x_min, y_min, x_max, y_max] = self.bbox;
+        let advance = self.units_per_em;
+
+        let mut head = Vec::new();
+        push_u32(&mut head, &[0x0001_0000, 0x0001_0000, 0, 0x5f0f_3cf5]);
+        push_u16(&mut head, &[0x0003, self.units_per_em]);
+        head.extend_from_slice(&[0; 16]);
+        push_i16(&mut head, &[x_min, y_min, x_max, y_max]);
+        push_u16(&mut head, &[0, 8, 2, 0, 0]);
+
+        let mut hhea = Vec::new();
+        push_u32(&mut hhea, &[0x0001_0000]);
+        push_i16(&mut hhea, &[y_max, y_min, 0]);
+        push_u16(&mut hhea, &[advance]);
+        push_i16(&mut hhea, &[x_min, 0, x_max, 1, 0, 0, 0, 0, 0, 0, 0]);
+        push_u16(&mut hhea, &[1]);
+
+        let mut hmtx = Vec::new();
+        push_u16(&mut hmtx, &[advance, 0]);
+        hmtx.resize(
+            hmtx.len() + 2 * usize::from(self.glyph_count.saturating_sub(1)),
+            0,
+        );
+
+        let mut maxp = Vec::new();
+        push_u32(&mut maxp, &[0x0000_5000]);
+        push_u16(&mut maxp, &[self.glyph_count]);
+
+        // a Unicode cmap with only the final segment, mapping nothing
+        let mut cmap = Vec::new();
+        push_u16(&mut cmap, &[0, 1, 3, 1, 0, 12]);
+        push_u16(&mut cmap, &[4, 24, 0, 2, 2, 0, 0, 0xffff, 0, 0xffff, 1, 0]);
+
+        let mut os2 = Vec::new();
+        push_u16(&mut os2, &[3, advance / 2, 400, 5, 0]);
+        os2.resize(os2.len() + 52, 0);
+        push_u16(&mut os2, &[0x0040, 0xffff, 0xffff]);
+        push_i16(&mut os2, &[y_max, y_min, 0]);
+        push_u16(&mut os2, &[y_max.max(0) as u16, (-y_min).max(0) as u16]);
+        os2.resize(os2.len() + 18, 0);
+
+        let mut post = Vec::new();
+        push_u32(&mut post, &[0x0003_0000, 0]);
+        push_i16(&mut post, &[-100, 50]);
+        post.resize(post.len() + 20, 0);
+
+        let name = self.name_table();
+
+        truetype::build_sfnt(
+            b"OTTO",
+            vec![
+                (*b"CFF ", data.to_vec()),
+                (*b"OS/2", os2),
+                (*b"cmap", cmap),
+                (*b"head", head),
+                (*b"hhea", hhea),
+                (*b"hmtx", hmtx),
+                (*b"maxp", maxp),
+                (*b"name", name),
+                (*b"post", post),
+            ],
+        )
+    }
+
+    /// Builds a naming table with the family and PostScript names of the font.
+    fn name_table(&self) -> Vec<u8> {
+        let font_name = String::from_utf8_lossy(&self.name);
+        // subset fonts are named with a tag like ABCDEF+Name
+        let family = font_name.splitn(2, '+').last().unwrap_or_default();
+        let records: [(u16, &str); 4] = [(1, family), (2, "Regular"), (4, family), (6, &font_name)];
+        let mut table = Vec::new();
+        let mut strings = Vec::new();
+        push_u16(
+            &mut table,
+            &[0, records.len() as u16, 6 + 12 * records.len() as u16],
+        );
+        for (name_id, value) in records.iter() {
+            let encoded = value
+                .encode_utf16()
+                .flat_map(|unit| unit.to_be_bytes().to_vec())
+                .collect::<Vec<_>>();
+            push_u16(
+                &mut table,
+                &[
+                    3,
+                    1,
+                    0x0409,
+                    *name_id,
+                    encoded.len() as u16,
+                    strings.len() as u16,
+                ],
+            );
+            strings.extend(encoded);
+        }
+        table.extend(strings);
+        table
+    }
+}
+
+fn push_u16(data: &mut Vec<u8>, values: &[u16]) {
+    for value in values {
+        data.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+fn push_i16(data: &mut Vec<u8>, values: &[i16]) {
+    for value in values {
+        data.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+fn push_u32(data: &mut Vec<u8>, values: &[u32]) {
+    for value in values {
+        data.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a CFF program with three glyphs and a custom charset and encoding, or a
+    /// CID-keyed program whose charset maps the glyphs to CIDs.
+    fn cff_program(cid_keyed: bool) -> Vec<u8> {
+        let mut data = vec![1, 0, 4, 1];
+        // Name INDEX with "ABCDEF+Test"
+        data.extend_from_slice(&[0, 1, 1, 1, 12]);
+        data.extend_from_slice(b"ABCDEF+Test");
+        // Top DICT INDEX with a fixed-size DICT pointing to the tables below
+        let top_dict_start = data.len();
+        let mut top_dict = Vec::new();
+        if cid_keyed {
+            // ROS: Adobe (391) Identity (392) 0
+            top_dict.extend_from_slice(&[28, 1, 135, 28, 1, 136, 139, 12, 30]);
+        }
+        let top_dict_size = top_dict.len() + 3 * 6;
+        let index_size = 5 + top_dict_size;
+        let strings = b"Adobe".len() + b"Identity".len() + b"custom".len();
+        let strings_start = top_dict_start + index_size;
+        let charset_offset = strings_start + 3 + 4 + strings;
+        let encoding_offset = charset_offset + 5;
+        let char_strings_offset = encoding_offset + 4;
+        for (offset, op) in [
+            (charset_offset, OP_CHARSET),
+            (encoding_offset, OP_ENCODING),
+            (char_strings_offset, OP_CHAR_STRINGS),
+        ]
+        .iter()
+        {
+            top_dict.push(29);
+            top_dict.extend_from_slice(&(*offset as u32).to_be_bytes());
+            top_dict.push(*op as u8);
+        }
+        data.extend_from_slice(&[0, 1, 1, 1, 1 + top_dict_size as u8]);
+        data.extend(top_dict);
+        // String INDEX
+        data.extend_from_slice(&[0, 3, 1, 1, 6, 14, 20]);
+        data.extend_from_slice(b"AdobeIdentitycustom");
+        // charset format 0: glyph 1 is "A" (34) or CID 34, glyph 2 is "custom" (393)
+        data.extend_from_slice(&[0, 0, 34, 1, 137]);
+        // encoding format 0: code 0x41 for glyph 1, code 0x61 for glyph 2
+        data.extend_from_slice(&[0, 2, 0x41, 0x61]);
+        // CharStrings INDEX with three endchar charstrings
+        data.extend_from_slice(&[0, 3, 1, 1, 2, 3, 4, 14, 14, 14]);
+        data
+    }
+
+    #[test]
+    fn built_in_encoding() {
+        let cff = Cff::parse(&cff_program(false)).unwrap();
+        let table = cff.built_in_encoding().unwrap();
+        assert_eq!(b"A", table.lookup(0x41).unwrap().as_bytes());
+        assert_eq!(b"custom", table.lookup(0x61).unwrap().as_bytes());
+        assert_eq!(None, table.lookup(0x42));
+        assert_eq!(None, cff.cid_to_gid());
+    }
+
+    #[test]
+    fn cid_keyed_charset() {
+        let cff = Cff::parse(&cff_program(true)).unwrap();
+        assert!(cff.built_in_encoding().is_none());
+        let cid_to_gid = cff.cid_to_gid().unwrap();
+        assert_eq!(394, cid_to_gid.len());
+        assert_eq!(1, cid_to_gid[34]);
+        assert_eq!(2, cid_to_gid[393]);
+        assert_eq!(0, cid_to_gid[35]);
+    }
+
+    #[test]
+    fn opentype_wrapper() {
+        let program = cff_program(false);
+        let font = Cff::parse(&program).unwrap().to_opentype(&program).unwrap();
+        assert_eq!(b"OTTO", &font[..4]);
+        assert_eq!(Some(&program[..]), truetype::find_table(&font, b"CFF "));
+        let maxp = truetype::find_table(&font, b"maxp").unwrap();
+        assert_eq!(Some(3), read_u16(maxp, 4));
+        let hmtx = truetype::find_table(&font, b"hmtx").unwrap();
+        assert_eq!(4 + 2 * 2, hmtx.len());
+        let name = truetype::find_table(&font, b"name").unwrap();
+        assert_eq!(Some(4), read_u16(name, 2));
+    }
+}
@@ -0,0 +1,548 @@
+use failure::Fallible;
+
+use rpdf_lopdf_extra::*;
+
+/// Maximum length of a character code in bytes.
+const MAX_CODE_LENGTH: usize = 4;
+
+/// Limits how long a chain of `UseCMap` references can be, so that a CMap that uses
+/// itself can't exhaust the stack.
+const MAX_DEPTH: u32 = 8;
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    HexString(Vec<u8>),
+    Name(&'a [u8]),
+    Word(&'a [u8]),
+    ArrayStart,
+    ArrayEnd,
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+fn is_separator(byte: u8) -> bool {
+    matches!(
+        byte,
+        b' ' | b'\t' | b'\r' | b'\n' | b'\x0c' | b'\0' | b'<' | b'>' | b'[' | b']' | b'/' | b'%'
+    ) || matches!(byte, b'(' | b')' | b'{' | b'}')
+}
+
+/// Splits a CMap program into the tokens relevant to its mappings. Strings and
+/// dictionaries are not interpreted, and their delimiters are skipped.
+fn tokenize(data: &[u8]) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        match data[pos] {
+            b'%' => {
+                while pos < data.len() && data[pos] != b'\n' && data[pos] != b'\r' {
+                    pos += 1;
+                }
+            }
+            b'<' if data.get(pos + 1) == Some(&b'<') => pos += 2,
+            b'<' => {
+                pos += 1;
+                let mut digits = Vec::new();
+                while pos < data.len() && data[pos] != b'>' {
+                    digits.extend(hex_value(data[pos]));
+                    pos += 1;
+                }
+                pos += 1;
+                if digits.len() % 2 == 1 {
+                    digits.push(0);
+                }
+                let bytes = digits
+                    .chunks(2)
+                    .map(|pair| pair[0] << 4 | pair[1])
+                    .collect();
+                tokens.push(Token::HexString(bytes));
+            }
+            b'/' => {
+                let start = pos + 1;
+                pos = start;
+                while pos < data.len() && !is_separator(data[pos]) {
+                    pos += 1;
+                }
+                tokens.push(Token::Name(&data[start..pos]));
+            }
+            b'[' => {
+                tokens.push(Token::ArrayStart);
+                pos += 1;
+            }
+            b']' => {
+                tokens.push(Token::ArrayEnd);
+                pos += 1;
+            }
+            byte if is_separator(byte) => pos += 1,
+            _ => {
+                let start = pos;
+                while pos < data.len() && !is_separator(data[pos]) {
+                    pos += 1;
+                }
+                tokens.push(Token::Word(&data[start..pos]));
+            }
+        }
+    }
+    tokens
+}
+
+fn code_value(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |value, byte| value << 8 | u32::from(*byte))
+}
+
+/// A range of character codes of a given length, bounded byte by byte.
+#[derive(Debug)]
+struct CodespaceRange {
+    low: Vec<u8>,
+    high: Vec<u8>,
+}
+
+impl CodespaceRange {
+    fn matches(&self, bytes: &[u8]) -> bool {
+        bytes.len() == self.low.len()
+            && bytes
+                .iter()
+                .zip(self.low.iter().zip(self.high.iter()))
+                .all(|(byte, (low, high))| byte >= low && byte <= high)
+    }
+}
+
+/// A range of character codes of the same length, mapped to consecutive CIDs.
+#[derive(Debug)]
+struct CidRange {
+    length: usize,
+    start: u32,
+    end: u32,
+    cid: u32,
+}
+
+/// A character code read from a string shown with a font.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CharCode {
+    pub code: u32,
+    /// The number of bytes the code occupies in the string.
+    pub length: usize,
+}
+
+/// Maps the multi-byte character codes of a composite font to CIDs.
+#[derive(Debug)]
+pub struct CMap {
+    codespace_ranges: Vec<CodespaceRange>,
+    cid_ranges: Vec<CidRange>,
+    vertical: bool,
+}
+
+impl CMap {
+    /// The Identity-H and Identity-V CMaps, which map two-byte codes to the same CIDs.
+    pub fn identity(vertical: bool) -> Self {
+        CMap {
+            codespace_ranges: vec![CodespaceRange {
+                low: vec![0x00, 0x00],
+                high: vec![0xff, 0xff],
+            }],
+            cid_ranges: vec![CidRange {
+                length: 2,
+                start: 0,
+                end: 0xffff,
+                cid: 0,
+            }],
+            vertical,
+        }
+    }
+
+    fn predefined(name: &[u8]) -> Fallible<Self> {
+        match name {
+            b"Identity-H" => Ok(Self::identity(false)),
+            b"Identity-V" => Ok(Self::identity(true)),
+            _ => failure::bail!(
+                "unsupported predefined CMap {}",
+                String::from_utf8_lossy(name)
+            ),
+        }
+    }
+
+    /// Reads the `Encoding` of a Type0 font, which is either the name of a predefined
+    /// CMap or an embedded CMap stream.
+    pub fn try_from_object(doc: &lopdf::Document, obj: &lopdf::Object) -> Fallible<Self> {
+        Self::try_from_nested_object(doc, obj, 0)
+    }
+
+    fn try_from_nested_object(
+        doc: &lopdf::Document,
+        obj: &lopdf::Object,
+        depth: u32,
+    ) -> Fallible<Self> {
+        if depth > MAX_DEPTH {
+            failure::bail!("CMaps are nested too deeply");
+        }
+        match doc.resolve_object(obj)? {
+            lopdf::Object::Name(ref name) => Self::predefined(name),
+            lopdf::Object::Stream(ref stream) => {
+                let mut cmap = Self::parse(&stream.decoded_content())?;
+                if let Some(obj) = stream.dict.get(b"UseCMap") {
+                    cmap.inherit(Self::try_from_nested_object(doc, obj, depth + 1)?);
+                }
+                if let Some(obj) = stream.dict.get(b"WMode") {
+                    let wmode: u32 = doc.deserialize_object(obj)?;
+                    cmap.vertical = wmode == 1;
+                }
+                Ok(cmap)
+            }
+            obj => failure::bail!("invalid CMap {:?}", obj),
+        }
+    }
+
+    /// Parses the program of an embedded CMap.
+    pub fn parse(data: &[u8]) -> Fallible<Self> {
+        let mut cmap = CMap {
+            codespace_ranges: Vec::new(),
+            cid_ranges: Vec::new(),
+            vertical: false,
+        };
+        let tokens = tokenize(data);
+        let mut operands: Vec<&Token> = Vec::new();
+        let mut section: Option<&[u8]> = None;
+        for (index, token) in tokens.iter().enumerate() {
+            if let Token::Word(word) = *token {
+                match word {
+                    b"begincodespacerange" | b"begincidrange" | b"begincidchar" => {
+                        section = Some(word);
+                        operands.clear();
+                        continue;
+                    }
+                    b"endcodespacerange" | b"endcidrange" | b"endcidchar" => {
+                        section = None;
+                        operands.clear();
+                        continue;
+                    }
+                    b"usecmap" => {
+                        match operands.last() {
+                            Some(Token::Name(name)) => cmap.inherit(Self::predefined(name)?),
+                            _ => failure::bail!("usecmap without a CMap name"),
+                        }
+                        operands.clear();
+                        continue;
+                    }
+                    b"def" => {
+                        if let [.., Token::Name(b"WMode"), Token::Word(wmode)] = tokens[..index] {
+                            cmap.vertical = wmode == b"1";
+                        }
+                    }
+                    _ if section.is_some() => {}
+                    _ => {
+                        operands.clear();
+                        continue;
+                    }
+                }
+            }
+            operands.push(token);
+
+            match (section, operands.as_slice()) {
+                (Some(b"begincodespacerange"), [Token::HexString(low), Token::HexString(high)]) => {
+                    if low.len() != high.len() || low.is_empty() || low.len() > MAX_CODE_LENGTH {
+                        failure::bail!("invalid codespace range");
+                    }
+                    cmap.codespace_ranges.push(CodespaceRange {
+                        low: low.clone(),
+                        high: high.clone(),
+                    });
+                }
+                (
+                    Some(b"begincidrange"),
+                    [Token::HexString(start), Token::HexString(end), Token::Word(cid)],
+                ) => {
+                    cmap.cid_ranges.push(CidRange {
+                        length: start.len(),
+                        start: code_value(start),
+                        end: code_value(end),
+                        cid: parse_cid(cid)?,
+                    });
+                }
+                (Some(b"begincidchar"), [Token::HexString(code), Token::Word(cid)]) => {
+                    let value = code_value(code);
+                    cmap.cid_ranges.push(CidRange {
+                        length: code.len(),
+                        start: value,
+                        end: value,
+                        cid: parse_cid(cid)?,
+                    });
+                }
+                (Some(_), operands) if operands.len() < 3 => continue,
+                (Some(_), _) => failure::bail!("invalid CMap mapping"),
+                _ => continue,
+            }
+            operands.clear();
+        }
+        if cmap.codespace_ranges.is_empty() && cmap.cid_ranges.is_empty() {
+            failure::bail!("CMap has no mappings");
+        }
+        Ok(cmap)
+    }
+
+    /// Adds the mappings of a CMap referenced with `usecmap`, which apply where this
+    /// CMap doesn't define its own.
+    fn inherit(&mut self, parent: CMap) {
+        self.codespace_ranges.extend(parent.codespace_ranges);
+        self.cid_ranges.extend(parent.cid_ranges);
+    }
+
+    pub fn is_vertical(&self) -> bool {
+        self.vertical
+    }
+
+    /// Splits a string into character codes, using the codespace ranges to determine
+    /// the length of each code.
+    pub fn char_codes<'a>(&'a self, bytes: &'a [u8]) -> impl Iterator<Item = CharCode> + 'a {
+        let mut pos = 0;
+        std::iter::from_fn(move || {
+            if pos >= bytes.len() {
+                return None;
+            }
+            let remaining = &bytes[pos..];
+            let length = (1..=remaining.len().min(MAX_CODE_LENGTH))
+                .find(|length| {
+                    let code = &remaining[..*length];
+                    self.codespace_ranges
+                        .iter()
+                        .any(|range| range.matches(code))
+                })
+                .unwrap_or_else(|| {
+                    // codes that don't match are as long as the shortest codespace range
+                    let shortest = self.codespace_ranges.iter().map(|range| range.low.len());
+                    shortest.min().unwrap_or(1).min(remaining.len())
+                });
+            pos += length;
+            Some(CharCode {
+                code: code_value(&remaining[..length]),
+                length,
+            })
+        })
+    }
+
+    /// Returns the CID of a character code, or 0 for the missing glyph if unmapped or
+    /// mapped past the largest CID.
+    pub fn lookup(&self, char_code: CharCode) -> u32 {
+        self.cid_ranges
+            .iter()
+            .find(|range| {
+                range.length == char_code.length
+                    && char_code.code >= range.start
+                    && char_code.code <= range.end
+            })
+            .and_then(|range| range.cid.checked_add(char_code.code - range.start))
+            .unwrap_or(0)
+    }
+}
+
+/// Limits how many codes a `bfrange` of a ToUnicode CMap can map, and how many
+/// mappings are read from one, since they are expanded to individual codes.
+const MAX_RANGE_LENGTH: u32 = 0x10000;
+const MAX_UNICODE_MAPPINGS: usize = 1 << 20;
+
+/// Returns the first character of a UTF-16BE string.
+fn first_char(bytes: &[u8]) -> Option<u32> {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]));
+    std::char::decode_utf16(units).next()?.ok().map(u32::from)
+}
+
+/// Reads the Unicode values that a ToUnicode CMap maps character codes to, keeping
+/// only the first character of codes that map to several.
+pub fn parse_to_unicode(data: &[u8]) -> Vec<(CharCode, u32)> {
+    let tokens = tokenize(data);
+    let mut mappings = Vec::new();
+    let mut operands: Vec<&Token> = Vec::new();
+    let mut section: Option<&[u8]> = None;
+    for token in &tokens {
+        match *token {
+            Token::Word(word @ b"beginbfchar") | Token::Word(word @ b"beginbfrange") => {
+                section = Some(word);
+                operands.clear();
+                continue;
+            }
+            Token::Word(b"endbfchar") | Token::Word(b"endbfrange") => {
+                section = None;
+                operands.clear();
+                continue;
+            }
+            _ if section.is_none() => continue,
+            _ => operands.push(token),
+        }
+
+        // codes in a range only differ in their last byte, but are read as numbers
+        let range = |start: &[u8], end: &[u8]| {
+            let (start, end) = (code_value(start), code_value(end));
+            let valid = start <= end && end - start < MAX_RANGE_LENGTH;
+            (start..=end).filter(move |_| valid)
+        };
+        match (section, operands.as_slice()) {
+            (Some(b"beginbfchar"), [Token::HexString(code), Token::HexString(unicode)]) => {
+                if let Some(unicode) = first_char(unicode) {
+                    let code = CharCode {
+                        code: code_value(code),
+                        length: code.len(),
+                    };
+                    mappings.push((code, unicode));
+                }
+            }
+            (
+                Some(b"beginbfrange"),
+                [Token::HexString(start), Token::HexString(end), Token::HexString(unicode)],
+            ) => {
+                if let Some(unicode) = first_char(unicode) {
+                    for (offset, code) in range(start, end).enumerate() {
+                        let code = CharCode {
+                            code,
+                            length: start.len(),
+                        };
+                        mappings.push((code, unicode.saturating_add(offset as u32)));
+                    }
+                }
+            }
+            (
+                Some(b"beginbfrange"),
+                [Token::HexString(start), Token::HexString(end), Token::ArrayStart, values @ .., Token::ArrayEnd],
+            ) => {
+                for (code, value) in range(start, end).zip(values.iter()) {
+                    if let Token::HexString(unicode) = value {
+                        let code = CharCode {
+                            code,
+                            length: start.len(),
+                        };
+                        mappings.extend(first_char(unicode).map(|unicode| (code, unicode)));
+                    }
+                }
+            }
+            (Some(b"beginbfrange"), [_, _, Token::ArrayStart, ..]) => continue,
+            (Some(b"beginbfrange"), operands) if operands.len() < 3 => continue,
+            (Some(_), operands) if operands.len() < 2 => continue,
+            _ => {}
+        }
+        operands.clear();
+        if mappings.len() > MAX_UNICODE_MAPPINGS {
+            log::warn!("ignoring the rest of a ToUnicode CMap with too many mappings");
+            mappings.truncate(MAX_UNICODE_MAPPINGS);
+            break;
+        }
+    }
+    mappings
+}
+
+fn parse_cid(word: &[u8]) -> Fallible<u32> {
+    std::str::from_utf8(word)
+        .ok()
+        .and_then(|word| word.parse().ok())
+        .ok_or_else(|| failure::format_err!("invalid CID {}", String::from_utf8_lossy(word)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity() {
+        let cmap = CMap::identity(false);
+        let codes = cmap.char_codes(&[0x01, 0x02, 0x00]).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                CharCode {
+                    code: 0x0102,
+                    length: 2
+                },
+                CharCode {
+                    code: 0x00,
+                    length: 1
+                }
+            ],
+            codes
+        );
+        assert_eq!(0x0102, cmap.lookup(codes[0]));
+    }
+
+    #[test]
+    fn embedded_cmap_with_mixed_code_lengths() {
+        let program = b"%!PS-Adobe-3.0 Resource-CMap\n\
+            /CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+            /CMapName /Test-H def\n/WMode 1 def\n\
+            2 begincodespacerange\n<00> <80>\n<8140> <9ffc>\nendcodespacerange\n\
+            1 begincidrange\n<20> <7e> 1\nendcidrange\n\
+            2 begincidchar\n<8140> 633\n<8141> 634\nendcidchar\n\
+            endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend\n";
+        let cmap = CMap::parse(program).unwrap();
+        assert!(cmap.is_vertical());
+
+        let codes = cmap
+            .char_codes(&[0x41, 0x81, 0x41, 0xa0, 0x20])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![(0x41, 1), (0x8141, 2), (0xa0, 1), (0x20, 1)],
+            codes
+                .iter()
+                .map(|code| (code.code, code.length))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![34, 634, 0, 1],
+            codes
+                .iter()
+                .map(|code| cmap.lookup(*code))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn self_referencing_and_overflowing_cmaps() {
+        let program = b"begincmap\n1 begincodespacerange\n<00> <ff>\nendcodespacerange\n\
+            1 begincidrange\n<00> <ff> 4294967041\nendcidrange\nendcmap\n";
+        let mut doc = lopdf::Document::new();
+        let id = doc.add_object(lopdf::Object::Null);
+        let mut dict = lopdf::Dictionary::new();
+        dict.set("UseCMap", id);
+        doc.objects.insert(
+            id,
+            lopdf::Object::Stream(lopdf::Stream::new(dict, program.to_vec())),
+        );
+        assert!(CMap::try_from_object(&doc, &id.into()).is_err());
+
+        let cmap = CMap::parse(program).unwrap();
+        let code = |code| CharCode { code, length: 1 };
+        assert_eq!(u32::MAX, cmap.lookup(code(0xfe)));
+        assert_eq!(0, cmap.lookup(code(0xff)));
+    }
+
+    #[test]
+    fn to_unicode() {
+        let program = b"/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+            1 begincodespacerange\n<0000> <ffff>\nendcodespacerange\n\
+            2 beginbfchar\n<0003> <0020>\n<0010> <d835dc9c>\nendbfchar\n\
+            2 beginbfrange\n<0024> <0026> <0041>\n<0030> <0031> [<0066> <00660069>]\n\
+            endbfrange\nendcmap\n";
+        let mappings = parse_to_unicode(program)
+            .into_iter()
+            .map(|(code, unicode)| (code.code, code.length, unicode))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (0x03, 2, 0x20),
+                (0x10, 2, 0x1d49c),
+                (0x24, 2, 0x41),
+                (0x25, 2, 0x42),
+                (0x26, 2, 0x43),
+                (0x30, 2, 0x66),
+                (0x31, 2, 0x66),
+            ],
+            mappings
+        );
+        assert!(parse_to_unicode(b"beginbfrange\n<0000> <ffffff> <0041>\nendbfrange\n").is_empty());
+    }
+}
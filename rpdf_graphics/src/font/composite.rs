@@ -0,0 +1,346 @@
+use std::convert::TryFrom;
+
+use failure::Fallible;
+use serde_derive::Deserialize;
+
+use rpdf_lopdf_extra::*;
+
+use super::cmap::{CMap, CharCode};
+
+/// The metrics of consecutive CIDs, either one set shared by the whole range or one
+/// set per CID.
+#[derive(Debug, PartialEq)]
+struct MetricsRange {
+    first: u32,
+    last: u32,
+    values: Vec<f64>,
+}
+
+impl MetricsRange {
+    fn lookup(&self, cid: u32, count: usize) -> Option<&[f64]> {
+        if cid < self.first || cid > self.last {
+            return None;
+        }
+        if self.values.len() == count {
+            return Some(&self.values);
+        }
+        let start = (cid - self.first) as usize * count;
+        self.values.get(start..start + count)
+    }
+}
+
+/// Parses a `W` or `W2` array, in which each CID has `count` values. Entries are
+/// either `c [values...]` listing the values of consecutive CIDs from `c`, or
+/// `first last values` sharing the values across the range.
+fn parse_metrics(
+    doc: &lopdf::Document,
+    obj: &lopdf::Object,
+    count: usize,
+) -> Fallible<Vec<MetricsRange>> {
+    let array = doc
+        .resolve_object(obj)?
+        .as_array()
+        .ok_or_else(|| failure::format_err!("CID metrics must be an array"))?;
+    let mut ranges = Vec::new();
+    let mut items = array.iter();
+    while let Some(first) = items.next() {
+        let first: u32 = doc.deserialize_object(first)?;
+        let next = match items.next() {
+            Some(next) => doc.resolve_object(next)?,
+            None => failure::bail!("incomplete CID metrics"),
+        };
+        let range = match next {
+            lopdf::Object::Array(ref values) => {
+                let values = values
+                    .iter()
+                    .map(|value| doc.deserialize_object(value))
+                    .collect::<Fallible<Vec<f64>>>()?;
+                if values.is_empty() || values.len() % count != 0 {
+                    failure::bail!("invalid CID metrics");
+                }
+                let last = u32::try_from(values.len() / count - 1)
+                    .ok()
+                    .and_then(|length| first.checked_add(length))
+                    .ok_or_else(|| failure::format_err!("invalid CID metrics"))?;
+                MetricsRange {
+                    first,
+                    last,
+                    values,
+                }
+            }
+            last => {
+                let last: u32 = doc.deserialize_object(last)?;
+                let values = items
+                    .by_ref()
+                    .take(count)
+                    .map(|value| doc.deserialize_object(value))
+                    .collect::<Fallible<Vec<f64>>>()?;
+                if values.len() != count || last < first {
+                    failure::bail!("invalid CID metrics");
+                }
+                MetricsRange {
+                    first,
+                    last,
+                    values,
+                }
+            }
+        };
+        ranges.push(range);
+    }
+    Ok(ranges)
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(variant_identifier)]
+enum CidFontType {
+    CIDFontType0,
+    CIDFontType2,
+}
+
+#[derive(Debug, Deserialize)]
+struct CidFontDictionary {
+    #[serde(rename = "Subtype")]
+    subtype: CidFontType,
+    #[serde(rename = "DW")]
+    default_width: Option<f64>,
+    #[serde(rename = "DW2")]
+    default_vertical_metrics: Option<(f64, f64)>,
+}
+
+/// The displacement and position vector of a glyph in vertical writing mode, in text
+/// space units per unit of font size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VerticalMetrics {
+    pub advance: f64,
+    /// The offset of the glyph's vertical origin from its horizontal origin.
+    pub origin: (f64, f64),
+}
+
+/// A Type0 font, which shows glyphs of a descendant CIDFont selected by the CIDs that
+/// its CMap maps multi-byte character codes to.
+pub struct CompositeFont {
+    cmap: CMap,
+    default_width: f64,
+    widths: Vec<MetricsRange>,
+    default_vertical_metrics: (f64, f64),
+    vertical_metrics: Vec<MetricsRange>,
+    /// The glyph of each CID, or `None` if CIDs are glyph indices.
+    cid_to_gid: Option<Vec<u16>>,
+}
+
+impl CompositeFont {
+    /// Reads a Type0 font dictionary and the CIDFont dictionary it descends to.
+    pub fn try_from_dictionary(
+        doc: &lopdf::Document,
+        dict: &lopdf::Dictionary,
+        descendant_obj: &lopdf::Object,
+    ) -> Fallible<Self> {
+        let cmap = CMap::try_from_object(doc, dict.try_get(b"Encoding")?)?;
+        let cid_font: CidFontDictionary = doc.deserialize_object(descendant_obj)?;
+        let descendant = doc.resolve_object(descendant_obj)?.try_as_dict()?;
+        let widths = match descendant.get(b"W") {
+            Some(obj) => parse_metrics(doc, obj, 1)?,
+            None => Vec::new(),
+        };
+        let vertical_metrics = match descendant.get(b"W2") {
+            Some(obj) => parse_metrics(doc, obj, 3)?,
+            None => Vec::new(),
+        };
+        let cid_to_gid = match descendant.get(b"CIDToGIDMap") {
+            Some(obj) if cid_font.subtype == CidFontType::CIDFontType2 => {
+                match doc.resolve_object(obj)? {
+                    lopdf::Object::Name(ref name) if name == b"Identity" => None,
+                    lopdf::Object::Stream(ref stream) => Some(
+                        stream
+                            .decoded_content()
+                            .chunks_exact(2)
+                            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                            .collect(),
+                    ),
+                    obj => failure::bail!("invalid CIDToGIDMap {:?}", obj),
+                }
+            }
+            _ => None,
+        };
+
+        Ok(CompositeFont {
+            cmap,
+            default_width: cid_font.default_width.unwrap_or(1000.0),
+            widths,
+            default_vertical_metrics: cid_font
+                .default_vertical_metrics
+                .unwrap_or((880.0, -1000.0)),
+            vertical_metrics,
+            cid_to_gid,
+        })
+    }
+
+    pub fn set_cid_to_gid(&mut self, cid_to_gid: Vec<u16>) {
+        self.cid_to_gid = Some(cid_to_gid);
+    }
+
+    /// Selects the glyphs of a substitute font program, whose glyph indices are
+    /// unrelated to the font's CIDs, by the Unicode values of the character codes.
+    /// CIDs without a Unicode value are drawn with the missing glyph.
+    pub fn select_glyphs_by_unicode<F>(&mut self, to_unicode: &[(CharCode, u32)], glyph: F)
+    where
+        F: Fn(u32) -> Option<u32>,
+    {
+        let mut cid_to_gid = Vec::new();
+        for &(code, unicode) in to_unicode {
+            let cid = self.cid(code) as usize;
+            let gid = match glyph(unicode).map(u16::try_from) {
+                Some(Ok(gid)) if cid <= usize::from(u16::MAX) => gid,
+                _ => continue,
+            };
+            if cid_to_gid.len() <= cid {
+                cid_to_gid.resize(cid + 1, 0);
+            }
+            cid_to_gid[cid] = gid;
+        }
+        self.cid_to_gid = Some(cid_to_gid);
+    }
+
+    pub fn char_codes<'a>(&'a self, bytes: &'a [u8]) -> impl Iterator<Item = CharCode> + 'a {
+        self.cmap.char_codes(bytes)
+    }
+
+    pub fn cid(&self, code: CharCode) -> u32 {
+        self.cmap.lookup(code)
+    }
+
+    pub fn glyph_index(&self, code: CharCode) -> u32 {
+        let cid = self.cid(code);
+        match self.cid_to_gid {
+            Some(ref cid_to_gid) => cid_to_gid
+                .get(cid as usize)
+                .map_or(0, |gid| u32::from(*gid)),
+            None => cid,
+        }
+    }
+
+    pub fn width(&self, code: CharCode) -> f64 {
+        let cid = self.cid(code);
+        let width = self
+            .widths
+            .iter()
+            .find_map(|range| range.lookup(cid, 1))
+            .map_or(self.default_width, |values| values[0]);
+        width / 1000.0
+    }
+
+    pub fn is_vertical(&self) -> bool {
+        self.cmap.is_vertical()
+    }
+
+    pub fn vertical_metrics(&self, code: CharCode) -> Option<VerticalMetrics> {
+        if !self.is_vertical() {
+            return None;
+        }
+        let cid = self.cid(code);
+        let (advance, origin_x, origin_y) = match self
+            .vertical_metrics
+            .iter()
+            .find_map(|range| range.lookup(cid, 3))
+        {
+            Some(values) => (values[0], values[1], values[2]),
+            None => {
+                let (origin_y, advance) = self.default_vertical_metrics;
+                (advance, self.width(code) * 500.0, origin_y)
+            }
+        };
+        Some(VerticalMetrics {
+            advance: advance / 1000.0,
+            origin: (origin_x / 1000.0, origin_y / 1000.0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn width_arrays() {
+        let doc = lopdf::Document::new();
+        let array = lopdf::Object::Array(vec![
+            lopdf::Object::Integer(1),
+            lopdf::Object::Array(vec![
+                lopdf::Object::Integer(500),
+                lopdf::Object::Integer(600),
+            ]),
+            lopdf::Object::Integer(10),
+            lopdf::Object::Integer(20),
+            lopdf::Object::Integer(250),
+        ]);
+        let ranges = parse_metrics(&doc, &array, 1).unwrap();
+        let lookup = |cid| ranges.iter().find_map(|range| range.lookup(cid, 1));
+        assert_eq!(None, lookup(0));
+        assert_eq!(Some(&[500.0][..]), lookup(1));
+        assert_eq!(Some(&[600.0][..]), lookup(2));
+        assert_eq!(None, lookup(3));
+        assert_eq!(Some(&[250.0][..]), lookup(10));
+        assert_eq!(Some(&[250.0][..]), lookup(20));
+        assert_eq!(None, lookup(21));
+    }
+
+    #[test]
+    fn vertical_width_arrays() {
+        let doc = lopdf::Document::new();
+        let array = lopdf::Object::Array(vec![
+            lopdf::Object::Integer(5),
+            lopdf::Object::Array(vec![
+                lopdf::Object::Integer(-1000),
+                lopdf::Object::Integer(500),
+                lopdf::Object::Integer(880),
+                lopdf::Object::Integer(-900),
+                lopdf::Object::Integer(450),
+                lopdf::Object::Integer(800),
+            ]),
+        ]);
+        let ranges = parse_metrics(&doc, &array, 3).unwrap();
+        assert_eq!(Some(&[-900.0, 450.0, 800.0][..]), ranges[0].lookup(6, 3));
+        assert_eq!(None, ranges[0].lookup(7, 3));
+        assert!(parse_metrics(
+            &doc,
+            &lopdf::Object::Array(vec![lopdf::Object::Integer(1)]),
+            1
+        )
+        .is_err());
+        assert!(parse_metrics(
+            &doc,
+            &lopdf::Object::Array(vec![
+                lopdf::Object::Integer(i64::from(u32::MAX)),
+                lopdf::Object::Array(vec![
+                    lopdf::Object::Integer(500),
+                    lopdf::Object::Integer(600),
+                ]),
+            ]),
+            1
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn substitute_glyphs_by_unicode() {
+        let doc = lopdf::Document::new();
+        let mut descendant = lopdf::Dictionary::new();
+        descendant.set("Subtype", lopdf::Object::Name(b"CIDFontType2".to_vec()));
+        let mut dict = lopdf::Dictionary::new();
+        dict.set("Encoding", lopdf::Object::Name(b"Identity-H".to_vec()));
+        let mut font = CompositeFont::try_from_dictionary(&doc, &dict, &descendant.into()).unwrap();
+        let code = |code| CharCode { code, length: 2 };
+        assert_eq!(0x24, font.glyph_index(code(0x24)));
+
+        let to_unicode = [(code(0x24), 0x41), (code(0x25), 0x42), (code(0x26), 0x4e00)];
+        font.select_glyphs_by_unicode(&to_unicode, |unicode| match unicode {
+            0x41 => Some(36),
+            0x42 => Some(37),
+            _ => None,
+        });
+        assert_eq!(36, font.glyph_index(code(0x24)));
+        assert_eq!(37, font.glyph_index(code(0x25)));
+        assert_eq!(0, font.glyph_index(code(0x26)));
+        assert_eq!(0, font.glyph_index(code(0x27)));
+    }
+}
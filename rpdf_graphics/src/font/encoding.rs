@@ -1,6 +1,7 @@
 use serde_derive::Deserialize;
 
 mod differences;
+pub mod tables;
 
 #[derive(Debug, Deserialize, PartialEq)]
 #[serde(untagged)]
@@ -10,13 +11,12 @@ pub enum Encoding {
 }
 
 impl Encoding {
-    pub fn lookup(&self, char_code: u8) -> Option<&GlyphName> {
+    /// Resolves the encoding into a complete table, given the font's built-in encoding,
+    /// which dictionaries without a `BaseEncoding` modify.
+    pub fn to_table(&self, built_in: &EncodingTable) -> EncodingTable {
         match self {
-            Encoding::Predefined(_) => {
-                // TODO: consult predefined lookup table
-                None
-            }
-            Encoding::Dictionary(enc) => enc.lookup(char_code),
+            Encoding::Predefined(enc) => enc.to_table(),
+            Encoding::Dictionary(enc) => enc.to_table(built_in),
         }
     }
 }
@@ -29,6 +29,16 @@ pub enum PredefinedEncoding {
     WinAnsiEncoding,
 }
 
+impl PredefinedEncoding {
+    pub fn to_table(&self) -> EncodingTable {
+        EncodingTable::from_names(match self {
+            PredefinedEncoding::MacRomanEncoding => &tables::MAC_ROMAN,
+            PredefinedEncoding::MacExpertEncoding => &tables::MAC_EXPERT,
+            PredefinedEncoding::WinAnsiEncoding => &tables::WIN_ANSI,
+        })
+    }
+}
+
 #[derive(Debug, Deserialize, PartialEq)]
 pub struct EncodingDictionary {
     #[serde(rename = "BaseEncoding")]
@@ -38,13 +48,56 @@ pub struct EncodingDictionary {
 }
 
 impl EncodingDictionary {
-    pub fn lookup(&self, char_code: u8) -> Option<&GlyphName> {
-        if let Some(glyph_name) = self.differences.lookup(char_code) {
-            Some(glyph_name)
-        } else {
-            // TODO: consult base lookup table
-            None
+    pub fn to_table(&self, built_in: &EncodingTable) -> EncodingTable {
+        let mut table = match self.base {
+            Some(ref base) => base.to_table(),
+            None => built_in.clone(),
+        };
+        for (char_code, glyph_name) in self.differences.iter() {
+            table.set(char_code, glyph_name.clone());
         }
+        table
+    }
+}
+
+/// A complete mapping from single-byte character codes to glyph names.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncodingTable(Vec<Option<GlyphName>>);
+
+impl EncodingTable {
+    pub fn empty() -> Self {
+        EncodingTable(vec![None; 256])
+    }
+
+    pub fn from_names(names: &[Option<&str>; 256]) -> Self {
+        EncodingTable(
+            names
+                .iter()
+                .map(|name| name.map(|name| GlyphName(name.as_bytes().to_owned())))
+                .collect(),
+        )
+    }
+
+    pub fn standard() -> Self {
+        Self::from_names(&tables::STANDARD)
+    }
+
+    /// Returns the built-in encoding of a standard font by its base font name. Symbol and
+    /// ZapfDingbats have their own, while other Latin fonts use StandardEncoding.
+    pub fn for_base_font(base_font: &[u8]) -> Self {
+        match base_font {
+            b"Symbol" => Self::from_names(&tables::SYMBOL),
+            b"ZapfDingbats" => Self::from_names(&tables::ZAPF_DINGBATS),
+            _ => Self::standard(),
+        }
+    }
+
+    pub fn lookup(&self, char_code: u8) -> Option<&GlyphName> {
+        self.0[usize::from(char_code)].as_ref()
+    }
+
+    pub fn set(&mut self, char_code: u8, glyph_name: GlyphName) {
+        self.0[usize::from(char_code)] = Some(glyph_name);
     }
 }
 
@@ -52,6 +105,10 @@ impl EncodingDictionary {
 pub struct GlyphName(Vec<u8>);
 
 impl GlyphName {
+    pub fn new(name: &[u8]) -> Self {
+        GlyphName(name.to_owned())
+    }
+
     pub fn as_bytes(&self) -> &[u8] {
         self.0.as_slice()
     }
@@ -88,4 +145,67 @@ mod tests {
                 .unwrap()
         );
     }
+
+    #[test]
+    fn predefined_encodings() {
+        let win_ansi = PredefinedEncoding::WinAnsiEncoding.to_table();
+        assert_eq!(b"quotedblleft", win_ansi.lookup(0x93).unwrap().as_bytes());
+        assert_eq!(b"eacute", win_ansi.lookup(0xe9).unwrap().as_bytes());
+        assert_eq!(b"quotesingle", win_ansi.lookup(0x27).unwrap().as_bytes());
+
+        let mac_roman = PredefinedEncoding::MacRomanEncoding.to_table();
+        assert_eq!(b"eacute", mac_roman.lookup(0x8e).unwrap().as_bytes());
+        assert_eq!(b"quotedblleft", mac_roman.lookup(0xd2).unwrap().as_bytes());
+
+        let mac_expert = PredefinedEncoding::MacExpertEncoding.to_table();
+        assert_eq!(b"ff", mac_expert.lookup(0x56).unwrap().as_bytes());
+
+        assert_eq!(
+            b"quoteright",
+            EncodingTable::standard().lookup(0x27).unwrap().as_bytes()
+        );
+        assert_eq!(None, EncodingTable::standard().lookup(0x80));
+    }
+
+    #[test]
+    fn built_in_encodings() {
+        let symbol = EncodingTable::for_base_font(b"Symbol");
+        assert_eq!(b"Euro", symbol.lookup(0xa0).unwrap().as_bytes());
+        assert_eq!(b"Upsilon1", symbol.lookup(0xa1).unwrap().as_bytes());
+
+        let dingbats = EncodingTable::for_base_font(b"ZapfDingbats");
+        assert_eq!(b"a89", dingbats.lookup(0x80).unwrap().as_bytes());
+        assert_eq!(b"a96", dingbats.lookup(0x8d).unwrap().as_bytes());
+        assert_eq!(None, dingbats.lookup(0x8e));
+    }
+
+    #[test]
+    fn differences_modify_base_encoding() {
+        let mut dict = lopdf::Dictionary::new();
+        dict.set(
+            "Differences",
+            lopdf::Object::Array(vec![
+                lopdf::Object::Integer(0x41),
+                lopdf::Object::from("Alpha"),
+                lopdf::Object::from("Beta"),
+            ]),
+        );
+        let built_in = EncodingTable::from_names(&tables::SYMBOL);
+
+        let encoding: Encoding = lopdf::Document::new()
+            .deserialize_object(&lopdf::Object::Dictionary(dict.clone()))
+            .unwrap();
+        let table = encoding.to_table(&built_in);
+        assert_eq!(b"Beta", table.lookup(0x42).unwrap().as_bytes());
+        assert_eq!(b"gamma", table.lookup(0x67).unwrap().as_bytes());
+
+        dict.set("BaseEncoding", lopdf::Object::from("WinAnsiEncoding"));
+        let encoding: Encoding = lopdf::Document::new()
+            .deserialize_object(&lopdf::Object::Dictionary(dict))
+            .unwrap();
+        let table = encoding.to_table(&built_in);
+        assert_eq!(b"Alpha", table.lookup(0x41).unwrap().as_bytes());
+        assert_eq!(b"g", table.lookup(0x67).unwrap().as_bytes());
+        assert_eq!(b"quotedblright", table.lookup(0x94).unwrap().as_bytes());
+    }
 }
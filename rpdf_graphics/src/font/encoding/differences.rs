@@ -7,8 +7,10 @@ use super::GlyphName;
 pub struct Differences(BTreeMap<u8, GlyphName>);
 
 impl Differences {
-    pub fn lookup(&self, char_code: u8) -> Option<&GlyphName> {
-        self.0.get(&char_code)
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &GlyphName)> {
+        self.0
+            .iter()
+            .map(|(char_code, glyph_name)| (*char_code, glyph_name))
     }
 }
 
@@ -0,0 +1,290 @@
+use std::path::PathBuf;
+
+use super::system;
+use super::{FLAG_FIXED_PITCH, FLAG_ITALIC, FLAG_NONSYMBOLIC, FLAG_SERIF, FLAG_SYMBOLIC};
+
+mod metrics;
+
+/// One of the 14 fonts that PDF readers are expected to provide, which documents may
+/// use without embedding them.
+pub struct StandardFont {
+    name: &'static str,
+    /// Glyph widths sorted by glyph name, or `None` for the fixed-pitch Courier fonts.
+    widths: Option<&'static [(&'static str, u16)]>,
+    symbolic: bool,
+    /// Files of freely-licensed faces with the same metrics, in order of preference.
+    substitutes: &'static [&'static str],
+}
+
+const fn font(
+    name: &'static str,
+    widths: Option<&'static [(&'static str, u16)]>,
+    substitutes: &'static [&'static str],
+) -> StandardFont {
+    StandardFont {
+        name,
+        widths,
+        symbolic: false,
+        substitutes,
+    }
+}
+
+static STANDARD_FONTS: [StandardFont; 14] = [
+    font(
+        "Helvetica",
+        Some(metrics::HELVETICA),
+        &[
+            "LiberationSans-Regular.ttf",
+            "NimbusSans-Regular.otf",
+            "n019003l.pfb",
+        ],
+    ),
+    font(
+        "Helvetica-Bold",
+        Some(metrics::HELVETICA_BOLD),
+        &[
+            "LiberationSans-Bold.ttf",
+            "NimbusSans-Bold.otf",
+            "n019004l.pfb",
+        ],
+    ),
+    font(
+        "Helvetica-Oblique",
+        Some(metrics::HELVETICA),
+        &[
+            "LiberationSans-Italic.ttf",
+            "NimbusSans-Italic.otf",
+            "n019023l.pfb",
+        ],
+    ),
+    font(
+        "Helvetica-BoldOblique",
+        Some(metrics::HELVETICA_BOLD),
+        &[
+            "LiberationSans-BoldItalic.ttf",
+            "NimbusSans-BoldItalic.otf",
+            "n019024l.pfb",
+        ],
+    ),
+    font(
+        "Times-Roman",
+        Some(metrics::TIMES_ROMAN),
+        &[
+            "LiberationSerif-Regular.ttf",
+            "NimbusRoman-Regular.otf",
+            "n021003l.pfb",
+        ],
+    ),
+    font(
+        "Times-Bold",
+        Some(metrics::TIMES_BOLD),
+        &[
+            "LiberationSerif-Bold.ttf",
+            "NimbusRoman-Bold.otf",
+            "n021004l.pfb",
+        ],
+    ),
+    font(
+        "Times-Italic",
+        Some(metrics::TIMES_ITALIC),
+        &[
+            "LiberationSerif-Italic.ttf",
+            "NimbusRoman-Italic.otf",
+            "n021023l.pfb",
+        ],
+    ),
+    font(
+        "Times-BoldItalic",
+        Some(metrics::TIMES_BOLD_ITALIC),
+        &[
+            "LiberationSerif-BoldItalic.ttf",
+            "NimbusRoman-BoldItalic.otf",
+            "n021024l.pfb",
+        ],
+    ),
+    font(
+        "Courier",
+        None,
+        &[
+            "LiberationMono-Regular.ttf",
+            "NimbusMonoPS-Regular.otf",
+            "n022003l.pfb",
+        ],
+    ),
+    font(
+        "Courier-Bold",
+        None,
+        &[
+            "LiberationMono-Bold.ttf",
+            "NimbusMonoPS-Bold.otf",
+            "n022004l.pfb",
+        ],
+    ),
+    font(
+        "Courier-Oblique",
+        None,
+        &[
+            "LiberationMono-Italic.ttf",
+            "NimbusMonoPS-Italic.otf",
+            "n022023l.pfb",
+        ],
+    ),
+    font(
+        "Courier-BoldOblique",
+        None,
+        &[
+            "LiberationMono-BoldItalic.ttf",
+            "NimbusMonoPS-BoldItalic.otf",
+            "n022024l.pfb",
+        ],
+    ),
+    StandardFont {
+        name: "Symbol",
+        widths: Some(metrics::SYMBOL),
+        symbolic: true,
+        substitutes: &["StandardSymbolsPS.otf", "s050000l.pfb"],
+    },
+    StandardFont {
+        name: "ZapfDingbats",
+        widths: Some(metrics::ZAPF_DINGBATS),
+        symbolic: true,
+        substitutes: &["D050000L.otf", "d050000l.pfb"],
+    },
+];
+
+/// Names that common producers use for the standard fonts, including the Windows core
+/// fonts that are metric-compatible with them.
+static ALIASES: &[(&str, &str)] = &[
+    ("Arial", "Helvetica"),
+    ("ArialMT", "Helvetica"),
+    ("Arial,Bold", "Helvetica-Bold"),
+    ("Arial-BoldMT", "Helvetica-Bold"),
+    ("Arial,Italic", "Helvetica-Oblique"),
+    ("Arial-ItalicMT", "Helvetica-Oblique"),
+    ("Arial,BoldItalic", "Helvetica-BoldOblique"),
+    ("Arial-BoldItalicMT", "Helvetica-BoldOblique"),
+    ("Helvetica,Bold", "Helvetica-Bold"),
+    ("Helvetica,Italic", "Helvetica-Oblique"),
+    ("Helvetica,BoldItalic", "Helvetica-BoldOblique"),
+    ("Helvetica-Italic", "Helvetica-Oblique"),
+    ("Helvetica-BoldItalic", "Helvetica-BoldOblique"),
+    ("TimesNewRoman", "Times-Roman"),
+    ("TimesNewRomanPSMT", "Times-Roman"),
+    ("TimesNewRoman,Bold", "Times-Bold"),
+    ("TimesNewRomanPS-BoldMT", "Times-Bold"),
+    ("TimesNewRoman,Italic", "Times-Italic"),
+    ("TimesNewRomanPS-ItalicMT", "Times-Italic"),
+    ("TimesNewRoman,BoldItalic", "Times-BoldItalic"),
+    ("TimesNewRomanPS-BoldItalicMT", "Times-BoldItalic"),
+    ("Times", "Times-Roman"),
+    ("Times,Bold", "Times-Bold"),
+    ("Times,Italic", "Times-Italic"),
+    ("Times,BoldItalic", "Times-BoldItalic"),
+    ("CourierNew", "Courier"),
+    ("CourierNewPSMT", "Courier"),
+    ("CourierNew,Bold", "Courier-Bold"),
+    ("CourierNewPS-BoldMT", "Courier-Bold"),
+    ("CourierNew,Italic", "Courier-Oblique"),
+    ("CourierNewPS-ItalicMT", "Courier-Oblique"),
+    ("CourierNew,BoldItalic", "Courier-BoldOblique"),
+    ("CourierNewPS-BoldItalicMT", "Courier-BoldOblique"),
+    ("Courier,Bold", "Courier-Bold"),
+    ("Courier,Italic", "Courier-Oblique"),
+    ("Courier,BoldItalic", "Courier-BoldOblique"),
+    ("Symbol,Bold", "Symbol"),
+    ("Symbol,Italic", "Symbol"),
+    ("Symbol,BoldItalic", "Symbol"),
+];
+
+/// Removes the tag that identifies a font subset, six uppercase letters followed by a
+/// plus sign.
+pub fn strip_subset_prefix(base_font: &[u8]) -> &[u8] {
+    match base_font.get(..7) {
+        Some([tag @ .., b'+']) if tag.iter().all(u8::is_ascii_uppercase) => &base_font[7..],
+        _ => base_font,
+    }
+}
+
+impl StandardFont {
+    /// Finds the standard font that a `BaseFont` name refers to.
+    pub fn find(base_font: &[u8]) -> Option<&'static StandardFont> {
+        let base_font = strip_subset_prefix(base_font);
+        let name = ALIASES
+            .iter()
+            .find(|(alias, _)| alias.as_bytes() == base_font)
+            .map_or(base_font, |(_, name)| name.as_bytes());
+        STANDARD_FONTS
+            .iter()
+            .find(|font| font.name.as_bytes() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Whether the font has its own character set rather than the standard Latin one.
+    pub fn is_symbolic(&self) -> bool {
+        self.symbolic
+    }
+
+    /// Returns the font descriptor flags that describe the font.
+    pub fn flags(&self) -> u32 {
+        let mut flags = if self.symbolic {
+            FLAG_SYMBOLIC
+        } else {
+            FLAG_NONSYMBOLIC
+        };
+        if self.name.starts_with("Courier") {
+            flags |= FLAG_FIXED_PITCH | FLAG_SERIF;
+        } else if self.name.starts_with("Times") {
+            flags |= FLAG_SERIF;
+        }
+        if self.name.ends_with("Italic") || self.name.ends_with("Oblique") {
+            flags |= FLAG_ITALIC;
+        }
+        flags
+    }
+
+    /// Returns the width of a glyph in glyph space units.
+    pub fn width(&self, glyph_name: &[u8]) -> Option<u16> {
+        let widths = match self.widths {
+            Some(widths) => widths,
+            None => return Some(600),
+        };
+        widths
+            .binary_search_by(|(name, _)| name.as_bytes().cmp(glyph_name))
+            .ok()
+            .map(|index| widths[index].1)
+    }
+
+    /// Finds an installed font file that can stand in for the font.
+    pub fn substitute(&self) -> Option<PathBuf> {
+        system::find_font_file(self.substitutes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_standard_fonts() {
+        let name = |base_font: &[u8]| StandardFont::find(base_font).map(StandardFont::name);
+        assert_eq!(Some("Times-Roman"), name(b"Times-Roman"));
+        assert_eq!(Some("Helvetica-Bold"), name(b"Arial,Bold"));
+        assert_eq!(Some("Courier"), name(b"ABCDEF+CourierNewPSMT"));
+        assert_eq!(Some("ZapfDingbats"), name(b"ZapfDingbats"));
+        assert_eq!(None, name(b"Abcdef+Helvetica"));
+        assert_eq!(None, name(b"Palatino-Roman"));
+    }
+
+    #[test]
+    fn widths() {
+        let helvetica = StandardFont::find(b"Helvetica").unwrap();
+        assert_eq!(Some(667), helvetica.width(b"A"));
+        assert_eq!(Some(278), helvetica.width(b"space"));
+        assert_eq!(None, helvetica.width(b"uni4E00"));
+        let courier = StandardFont::find(b"Courier-Bold").unwrap();
+        assert_eq!(Some(600), courier.width(b"W"));
+    }
+}
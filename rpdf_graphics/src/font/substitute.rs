@@ -0,0 +1,220 @@
+use std::path::PathBuf;
+
+use failure::Fallible;
+use serde_derive::Deserialize;
+
+use rpdf_lopdf_extra::*;
+
+use super::standard::strip_subset_prefix;
+use super::system::{self, SystemFace};
+use super::{FLAG_FIXED_PITCH, FLAG_FORCE_BOLD, FLAG_ITALIC, FLAG_SERIF, FLAG_SYMBOLIC};
+use crate::data::Name;
+
+#[derive(Default, Deserialize)]
+struct FontDescriptor {
+    #[serde(rename = "FontName")]
+    font_name: Option<Name>,
+    #[serde(rename = "Flags")]
+    flags: Option<u32>,
+    #[serde(rename = "FontWeight")]
+    font_weight: Option<f64>,
+    #[serde(rename = "ItalicAngle")]
+    italic_angle: Option<f64>,
+    #[serde(rename = "StemV")]
+    stem_v: Option<f64>,
+}
+
+/// Lowercases a font name and removes the separators that vary between its spellings.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// The style of a font that isn't embedded, which a face installed on the system is
+/// chosen to resemble.
+#[derive(Debug, PartialEq)]
+pub struct FontDescription {
+    postscript_name: String,
+    family: String,
+    weight: u16,
+    italic: bool,
+    fixed_pitch: bool,
+    serif: bool,
+    symbolic: bool,
+}
+
+impl FontDescription {
+    /// Describes a font by its font descriptor, if it has one, and its name. Flags that
+    /// the descriptor doesn't give default to `default_flags`.
+    pub fn from_descriptor(
+        doc: &lopdf::Document,
+        base_font: &[u8],
+        descriptor_obj: Option<&lopdf::Object>,
+        default_flags: u32,
+    ) -> Fallible<Self> {
+        let descriptor: FontDescriptor = match descriptor_obj {
+            Some(descriptor_obj) => doc.deserialize_object(descriptor_obj)?,
+            None => FontDescriptor::default(),
+        };
+        let name = match descriptor.font_name {
+            Some(Name(ref font_name)) => font_name.as_slice(),
+            None => base_font,
+        };
+        let postscript_name = String::from_utf8_lossy(strip_subset_prefix(name)).into_owned();
+        // style suffixes are separated by a hyphen in PostScript names and by a comma in
+        // the names of TrueType fonts
+        let (family, style) = match postscript_name.find(['-', ',']) {
+            Some(index) => postscript_name.split_at(index),
+            None => (postscript_name.as_str(), ""),
+        };
+        let flags = descriptor.flags.unwrap_or(default_flags);
+
+        let weight = match descriptor.font_weight {
+            Some(font_weight) => font_weight,
+            None if flags & FLAG_FORCE_BOLD != 0 => 700.0,
+            None if style.contains("Black") || style.contains("Heavy") => 900.0,
+            None if style.contains("Semibold") || style.contains("Demi") => 600.0,
+            None if style.contains("Bold") => 700.0,
+            None if style.contains("Light") => 300.0,
+            // vertical stems of regular weight faces are around 80 units wide, and those
+            // of bold ones around 140
+            None => descriptor
+                .stem_v
+                .map_or(400.0, |stem_v| 400.0 + (stem_v - 80.0) * 5.0),
+        };
+        let italic = flags & FLAG_ITALIC != 0
+            || descriptor.italic_angle.is_some_and(|angle| angle != 0.0)
+            || style.contains("Italic")
+            || style.contains("Oblique");
+
+        Ok(FontDescription {
+            family: normalize(family),
+            postscript_name: normalize(&postscript_name),
+            weight: ((weight / 100.0).round() * 100.0).clamp(100.0, 900.0) as u16,
+            italic,
+            fixed_pitch: flags & FLAG_FIXED_PITCH != 0,
+            serif: flags & FLAG_SERIF != 0,
+            symbolic: flags & FLAG_SYMBOLIC != 0,
+        })
+    }
+
+    /// Scores how closely a face matches the description, lower being closer. Faces of
+    /// other families only stand in for fonts with a standard Latin character set,
+    /// since the glyphs of symbolic fonts are particular to them.
+    fn score(&self, face: &SystemFace) -> Option<u32> {
+        if normalize(&face.postscript_name) == self.postscript_name {
+            return Some(0);
+        }
+        let face_family = normalize(&face.family);
+        let same_family = face_family == self.family
+            || (face_family.len() >= 4 && self.family.starts_with(&face_family));
+        if self.symbolic && !same_family {
+            return None;
+        }
+
+        let mut score = 0;
+        if !same_family {
+            score += 1000;
+        }
+        if face.symbolic {
+            score += 500;
+        }
+        if face.fixed_pitch != self.fixed_pitch {
+            score += 400;
+        }
+        score += match face.serif {
+            Some(serif) if serif != self.serif => 200,
+            Some(_) => 0,
+            None => 50,
+        };
+        if face.italic != self.italic {
+            score += 100;
+        }
+        score += (i32::from(face.weight) - i32::from(self.weight)).unsigned_abs() / 10;
+        Some(score)
+    }
+
+    /// Finds the installed face that most closely resembles the font, returning its
+    /// file and its index in the file.
+    pub fn find_substitute(&self) -> Option<(PathBuf, u32)> {
+        system::find_face(|face| self.score(face))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn face(postscript_name: &str, family: &str, weight: u16, serif: bool) -> SystemFace {
+        SystemFace {
+            path: PathBuf::new(),
+            index: 0,
+            postscript_name: postscript_name.to_owned(),
+            family: family.to_owned(),
+            weight,
+            italic: postscript_name.contains("Italic"),
+            fixed_pitch: false,
+            serif: Some(serif),
+            symbolic: false,
+        }
+    }
+
+    #[test]
+    fn description_from_descriptor() {
+        let doc = lopdf::Document::new();
+        let mut descriptor = lopdf::Dictionary::new();
+        descriptor.set(
+            "FontName",
+            lopdf::Object::Name(b"ABCDEF+Georgia,Bold".to_vec()),
+        );
+        descriptor.set("Flags", lopdf::Object::Integer(34));
+        descriptor.set("StemV", lopdf::Object::Integer(136));
+        let description =
+            FontDescription::from_descriptor(&doc, b"Georgia,Bold", Some(&descriptor.into()), 0)
+                .unwrap();
+        assert_eq!(
+            FontDescription {
+                postscript_name: "georgiabold".to_owned(),
+                family: "georgia".to_owned(),
+                weight: 700,
+                italic: false,
+                fixed_pitch: false,
+                serif: true,
+                symbolic: false,
+            },
+            description
+        );
+
+        let description =
+            FontDescription::from_descriptor(&doc, b"Frutiger-LightItalic", None, 0).unwrap();
+        assert_eq!(300, description.weight);
+        assert!(description.italic);
+    }
+
+    #[test]
+    fn closest_face() {
+        let doc = lopdf::Document::new();
+        let mut descriptor = lopdf::Dictionary::new();
+        descriptor.set("Flags", lopdf::Object::Integer(34));
+        descriptor.set("FontWeight", lopdf::Object::Integer(700));
+        let description =
+            FontDescription::from_descriptor(&doc, b"Garamond-Bold", Some(&descriptor.into()), 0)
+                .unwrap();
+        let faces = [
+            face("DejaVuSans-Bold", "DejaVu Sans", 700, false),
+            face("DejaVuSerif", "DejaVu Serif", 400, true),
+            face("DejaVuSerif-Bold", "DejaVu Serif", 700, true),
+            face("DejaVuSerif-BoldItalic", "DejaVu Serif", 700, true),
+        ];
+        let closest = faces
+            .iter()
+            .min_by_key(|face| description.score(face))
+            .unwrap();
+        assert_eq!("DejaVuSerif-Bold", closest.postscript_name);
+
+        let garamond = face("Garamond-Bold", "Garamond", 700, true);
+        assert_eq!(Some(0), description.score(&garamond));
+    }
+}
@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use super::truetype;
+
+/// Returns the directories that fonts are installed to on this platform.
+pub fn default_font_directories() -> Vec<PathBuf> {
+    let mut directories = Vec::new();
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    if cfg!(target_os = "macos") {
+        directories.push(PathBuf::from("/Library/Fonts"));
+        directories.push(PathBuf::from("/System/Library/Fonts"));
+        directories.extend(home.map(|home| home.join("Library/Fonts")));
+    } else if cfg!(windows) {
+        let windows = std::env::var_os("WINDIR").unwrap_or_else(|| "C:\\Windows".into());
+        directories.push(PathBuf::from(windows).join("Fonts"));
+    } else {
+        directories.push(PathBuf::from("/usr/share/fonts"));
+        directories.push(PathBuf::from("/usr/local/share/fonts"));
+        if let Some(home) = home {
+            directories.push(home.join(".fonts"));
+            directories.push(home.join(".local/share/fonts"));
+        }
+    }
+    directories
+}
+
+/// A TrueType or OpenType face installed on the system, described by the tables that
+/// substitutes for non-embedded fonts are chosen by.
+pub struct SystemFace {
+    pub path: PathBuf,
+    /// The index of the face in a font collection, or 0 for a file with a single face.
+    pub index: u32,
+    pub postscript_name: String,
+    pub family: String,
+    pub weight: u16,
+    pub italic: bool,
+    pub fixed_pitch: bool,
+    /// Whether the glyphs have serifs, if the face's PANOSE classification says.
+    pub serif: Option<bool>,
+    /// Whether the face has a symbol cmap instead of a Unicode one.
+    pub symbolic: bool,
+}
+
+impl SystemFace {
+    fn parse(path: PathBuf, index: u32, data: &[u8]) -> Option<Self> {
+        let postscript_name = truetype::name(data, 6)?;
+        let family = truetype::name(data, 16).or_else(|| truetype::name(data, 1))?;
+        let (weight, italic, serif) = match truetype::find_table(data, b"OS/2") {
+            Some(os2) => {
+                let family_type = os2.get(32).copied();
+                let serif_style = os2.get(33).copied();
+                let serif = match (family_type, serif_style) {
+                    // the latin text family
+                    (Some(2), Some(2..=10)) => Some(true),
+                    (Some(2), Some(11..=13)) => Some(false),
+                    _ => None,
+                };
+                let fs_selection = truetype::read_u16(os2, 62)?;
+                (truetype::read_u16(os2, 4)?, fs_selection & 1 != 0, serif)
+            }
+            None => {
+                let mac_style = truetype::read_u16(truetype::find_table(data, b"head")?, 44)?;
+                let weight = if mac_style & 1 != 0 { 700 } else { 400 };
+                (weight, mac_style & 2 != 0, None)
+            }
+        };
+        let fixed_pitch = truetype::find_table(data, b"post")
+            .and_then(|post| truetype::read_u32(post, 12))
+            .is_some_and(|is_fixed_pitch| is_fixed_pitch != 0);
+        let symbolic = truetype::Cmap::parse(data)
+            .is_ok_and(|cmap| cmap.has_subtable(3, 0) && !cmap.has_subtable(3, 1));
+        Some(SystemFace {
+            path,
+            index,
+            postscript_name,
+            family,
+            weight,
+            italic,
+            fixed_pitch,
+            serif,
+            symbolic,
+        })
+    }
+}
+
+/// Reads `length` bytes of a file from `offset`.
+fn read_at(file: &mut File, offset: u64, length: usize) -> Option<Vec<u8>> {
+    let mut data = vec![0; length];
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    file.read_exact(&mut data).ok()?;
+    Some(data)
+}
+
+/// Limits the size of the tables read to describe a face, which are far smaller in
+/// practice.
+const MAX_TABLE_LENGTH: usize = 1 << 20;
+
+/// Limits the number of faces read from a font collection.
+const MAX_COLLECTION_FACES: usize = 1024;
+
+/// Reads the tables of the face at `offset` of a file that a face is described by,
+/// without the glyph data, and assembles them into a font of their own. Only the
+/// subtable records of the cmap are read.
+fn read_face_tables(file: &mut File, offset: u64) -> Option<Vec<u8>> {
+    let header = read_at(file, offset, 12)?;
+    let num_tables = usize::from(truetype::read_u16(&header, 4)?);
+    let records = read_at(file, offset + 12, num_tables * 16)?;
+    let mut tables = Vec::new();
+    for record in records.chunks_exact(16) {
+        let mut tag = [0; 4];
+        tag.copy_from_slice(&record[..4]);
+        let offset = u64::from(truetype::read_u32(record, 8)?);
+        let length = truetype::read_u32(record, 12)? as usize;
+        let length = match &tag {
+            b"name" | b"OS/2" | b"head" | b"post" => length,
+            b"cmap" => {
+                let num_subtables = truetype::read_u16(&read_at(file, offset, 4)?, 2)?;
+                4 + usize::from(num_subtables) * 8
+            }
+            _ => continue,
+        };
+        if length > MAX_TABLE_LENGTH {
+            return None;
+        }
+        tables.push((tag, read_at(file, offset, length)?));
+    }
+    tables.sort_by_key(|(tag, _)| *tag);
+    truetype::build_sfnt(&[0, 1, 0, 0], tables)
+}
+
+/// Describes the faces of a TrueType or OpenType font or font collection.
+fn read_faces(path: &Path) -> Vec<SystemFace> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return Vec::new(),
+    };
+    let offsets = match read_at(&mut file, 0, 12) {
+        Some(ref header) if header.starts_with(b"ttcf") => {
+            let num_fonts = truetype::read_u32(header, 8).unwrap_or(0) as usize;
+            read_at(&mut file, 12, num_fonts.min(MAX_COLLECTION_FACES) * 4)
+                .map(|offsets| {
+                    offsets
+                        .chunks_exact(4)
+                        .filter_map(|offset| truetype::read_u32(offset, 0))
+                        .collect()
+                })
+                .unwrap_or_default()
+        }
+        Some(_) => vec![0],
+        None => Vec::new(),
+    };
+    offsets
+        .into_iter()
+        .zip(0..)
+        .filter_map(|(offset, index)| {
+            let data = read_face_tables(&mut file, u64::from(offset))?;
+            SystemFace::parse(path.to_owned(), index, &data)
+        })
+        .collect()
+}
+
+/// The font files found in the font directories.
+struct FontIndex {
+    /// Paths by lowercase file name.
+    files: HashMap<String, PathBuf>,
+    faces: Vec<SystemFace>,
+}
+
+impl FontIndex {
+    fn build(directories: &[PathBuf]) -> Self {
+        let mut index = FontIndex {
+            files: HashMap::new(),
+            faces: Vec::new(),
+        };
+        for directory in directories {
+            index.add_directory(directory);
+        }
+        log::debug!("found {} font faces", index.faces.len());
+        index
+    }
+
+    fn add_directory(&mut self, directory: &Path) {
+        let entries = match std::fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            // symbolic links to directories are not followed, so that they can't form cycles
+            if matches!(entry.file_type(), Ok(file_type) if file_type.is_dir()) {
+                self.add_directory(&path);
+                continue;
+            }
+            let file_name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(file_name) => file_name.to_lowercase(),
+                None => continue,
+            };
+            if [".ttf", ".otf", ".ttc"]
+                .iter()
+                .any(|extension| file_name.ends_with(extension))
+            {
+                self.faces.extend(read_faces(&path));
+            }
+            self.files.entry(file_name).or_insert(path);
+        }
+    }
+}
+
+struct FontDirectories {
+    /// The configured directories, or `None` for the platform's default ones.
+    directories: Option<Vec<PathBuf>>,
+    index: Option<Arc<FontIndex>>,
+}
+
+static FONT_DIRECTORIES: Mutex<FontDirectories> = Mutex::new(FontDirectories {
+    directories: None,
+    index: None,
+});
+
+/// Sets the directories that substitutes for non-embedded fonts are searched in, in
+/// order of preference, instead of the platform's default font directories.
+pub fn set_font_directories(directories: Vec<PathBuf>) {
+    let mut font_directories = FONT_DIRECTORIES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    font_directories.directories = Some(directories);
+    font_directories.index = None;
+}
+
+/// Returns the index of the font directories, which is built on first use.
+fn font_index() -> Arc<FontIndex> {
+    let mut font_directories = FONT_DIRECTORIES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some(ref index) = font_directories.index {
+        return index.clone();
+    }
+    let index = Arc::new(match font_directories.directories {
+        Some(ref directories) => FontIndex::build(directories),
+        None => FontIndex::build(&default_font_directories()),
+    });
+    font_directories.index = Some(index.clone());
+    index
+}
+
+/// Finds the first of a list of font files in the font directories. File names are
+/// compared case-insensitively, since they vary between distributions.
+pub fn find_font_file(file_names: &[&str]) -> Option<PathBuf> {
+    let index = font_index();
+    file_names
+        .iter()
+        .find_map(|file_name| index.files.get(&file_name.to_lowercase()))
+        .cloned()
+}
+
+/// Returns the file and face index of the face that a scoring function ranks lowest,
+/// if any gets a score.
+pub fn find_face<F>(score: F) -> Option<(PathBuf, u32)>
+where
+    F: Fn(&SystemFace) -> Option<u32>,
+{
+    let index = font_index();
+    index
+        .faces
+        .iter()
+        .filter_map(|face| Some((score(face)?, face)))
+        .min_by_key(|&(score, _)| score)
+        .map(|(_, face)| (face.path.clone(), face.index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a face with a naming table and an OS/2 table that describe it, and a glyph
+    /// table that isn't read to describe it.
+    fn face(postscript_name: &str, weight: u16) -> Vec<u8> {
+        let names = [(1, "Test"), (6, postscript_name)];
+        let mut storage = Vec::new();
+        let mut name = Vec::new();
+        for value in &[0, names.len() as u16, 6 + 12 * names.len() as u16] {
+            name.extend_from_slice(&value.to_be_bytes());
+        }
+        for &(name_id, value) in &names {
+            let units = value.encode_utf16().collect::<Vec<_>>();
+            for value in &[3, 1, 0x409, name_id, 2 * units.len() as u16] {
+                name.extend_from_slice(&value.to_be_bytes());
+            }
+            name.extend_from_slice(&(storage.len() as u16).to_be_bytes());
+            storage.extend(units.iter().flat_map(|unit| unit.to_be_bytes().to_vec()));
+        }
+        name.extend(storage);
+
+        let mut os2 = vec![0; 78];
+        os2[4..6].copy_from_slice(&weight.to_be_bytes());
+        truetype::build_sfnt(
+            &[0, 1, 0, 0],
+            vec![(*b"OS/2", os2), (*b"glyf", vec![0; 64]), (*b"name", name)],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn faces_of_fonts_and_collections() {
+        let directory = std::env::temp_dir().join(format!("rpdf-fonts-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let font_path = directory.join("Test-Bold.ttf");
+        std::fs::write(&font_path, face("Test-Bold", 700)).unwrap();
+
+        // the table offsets of the faces of a collection are from the start of the file
+        let faces = [face("Test-Regular", 400), face("Test-Light", 300)];
+        let mut collection = b"ttcf".to_vec();
+        let mut offsets = Vec::new();
+        let mut offset = 12 + 4 * faces.len();
+        for face in &faces {
+            offsets.push(offset as u32);
+            offset += face.len();
+        }
+        for value in [0x0001_0000, faces.len() as u32].iter().chain(&offsets) {
+            collection.extend_from_slice(&value.to_be_bytes());
+        }
+        for (face, offset) in faces.iter().zip(offsets) {
+            let mut face = face.clone();
+            for index in 0..usize::from(truetype::read_u16(&face, 4).unwrap()) {
+                let record = 12 + index * 16 + 8;
+                let table_offset = truetype::read_u32(&face, record).unwrap() + offset;
+                face[record..record + 4].copy_from_slice(&table_offset.to_be_bytes());
+            }
+            collection.extend(face);
+        }
+        let collection_path = directory.join("Test.ttc");
+        std::fs::write(&collection_path, &collection).unwrap();
+
+        let describe = |path: &Path| {
+            read_faces(path)
+                .into_iter()
+                .map(|face| (face.postscript_name, face.index, face.weight))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![("Test-Bold".to_owned(), 0, 700)], describe(&font_path));
+        assert_eq!(
+            vec![
+                ("Test-Regular".to_owned(), 0, 400),
+                ("Test-Light".to_owned(), 1, 300)
+            ],
+            describe(&collection_path)
+        );
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,382 @@
+use std::convert::TryFrom;
+
+use failure::Fallible;
+
+pub fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+pub fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Returns the contents of a table of an sfnt-housed (TrueType or OpenType) font.
+pub fn find_table<'a>(data: &'a [u8], tag: &[u8; 4]) -> Option<&'a [u8]> {
+    let num_tables = read_u16(data, 4)?;
+    for index in 0..usize::from(num_tables) {
+        let record = 12 + index * 16;
+        if data.get(record..record + 4)? == tag {
+            let offset = read_u32(data, record + 8)? as usize;
+            let length = read_u32(data, record + 12)? as usize;
+            return data.get(offset..offset.checked_add(length)?);
+        }
+    }
+    None
+}
+
+/// Extracts a face of a TrueType or OpenType collection as a font of its own.
+pub fn collection_face(data: &[u8], index: u32) -> Option<Vec<u8>> {
+    if data.get(..4)? != b"ttcf" || index >= read_u32(data, 8)? {
+        return None;
+    }
+    let offset = read_u32(data, 12 + 4 * index as usize)? as usize;
+    let face = data.get(offset..)?;
+    let mut version = [0; 4];
+    version.copy_from_slice(face.get(..4)?);
+    // the offsets of the tables of a collection are from the start of the file
+    let mut tables = Vec::new();
+    for index in 0..usize::from(read_u16(face, 4)?) {
+        let record = 12 + index * 16;
+        let mut tag = [0; 4];
+        tag.copy_from_slice(face.get(record..record + 4)?);
+        let offset = read_u32(face, record + 8)? as usize;
+        let length = read_u32(face, record + 12)? as usize;
+        tables.push((tag, data.get(offset..offset.checked_add(length)?)?.to_vec()));
+    }
+    tables.sort_by_key(|(tag, _)| *tag);
+    build_sfnt(&version, tables)
+}
+
+fn table_checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+/// Assembles an sfnt-housed font from its tables, which must be sorted by tag. Returns
+/// `None` if there are no tables, or too many or too large ones for the table directory.
+pub fn build_sfnt(version: &[u8; 4], tables: Vec<([u8; 4], Vec<u8>)>) -> Option<Vec<u8>> {
+    let num_tables = u16::try_from(tables.len()).ok().filter(|n| *n > 0)?;
+    let entry_selector = 15 - num_tables.leading_zeros() as u16;
+    let search_range = 16 << entry_selector;
+    let mut font = version.to_vec();
+    for value in &[
+        num_tables,
+        search_range,
+        entry_selector,
+        num_tables.checked_mul(16)? - search_range,
+    ] {
+        font.extend_from_slice(&value.to_be_bytes());
+    }
+
+    let mut offset = 12 + tables.len() * 16;
+    let mut head_offset = None;
+    for (tag, data) in &tables {
+        if tag == b"head" {
+            head_offset = Some(offset);
+        }
+        font.extend_from_slice(tag);
+        font.extend_from_slice(&table_checksum(data).to_be_bytes());
+        font.extend_from_slice(&u32::try_from(offset).ok()?.to_be_bytes());
+        font.extend_from_slice(&u32::try_from(data.len()).ok()?.to_be_bytes());
+        offset = offset.checked_add(data.len().checked_add(3)? & !3)?;
+    }
+    for (_, data) in tables {
+        font.extend_from_slice(&data);
+        font.resize((font.len() + 3) & !3, 0);
+    }
+
+    if let Some(head_offset) = head_offset {
+        let adjustment = 0xb1b0_afbau32.wrapping_sub(table_checksum(&font));
+        font[head_offset + 8..head_offset + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+    Some(font)
+}
+
+/// Returns a string from the naming table, preferring the Windows Unicode entries over
+/// the Macintosh Roman ones.
+pub fn name(font_data: &[u8], name_id: u16) -> Option<String> {
+    let data = find_table(font_data, b"name")?;
+    let count = usize::from(read_u16(data, 2)?);
+    let storage = usize::from(read_u16(data, 4)?);
+    let mut mac_name = None;
+    for index in 0..count {
+        let record = 6 + index * 12;
+        if read_u16(data, record + 6)? != name_id {
+            continue;
+        }
+        let length = usize::from(read_u16(data, record + 8)?);
+        let offset = storage + usize::from(read_u16(data, record + 10)?);
+        let bytes = data.get(offset..offset + length)?;
+        match (read_u16(data, record)?, read_u16(data, record + 2)?) {
+            (3, 0) | (3, 1) => {
+                let units = bytes
+                    .chunks_exact(2)
+                    .map(|pair| u16::from_be_bytes([pair[0], pair[1]]));
+                return Some(
+                    std::char::decode_utf16(units)
+                        .filter_map(Result::ok)
+                        .collect(),
+                );
+            }
+            (1, 0) => mac_name = Some(bytes.iter().map(|&byte| char::from(byte)).collect()),
+            _ => {}
+        }
+    }
+    mac_name
+}
+
+/// The advance widths of the glyphs of an sfnt-housed font.
+pub struct HorizontalMetrics {
+    units_per_em: u16,
+    advances: Vec<u16>,
+}
+
+impl HorizontalMetrics {
+    pub fn parse(font_data: &[u8]) -> Fallible<Self> {
+        let invalid = || failure::format_err!("invalid horizontal metrics");
+        let head = find_table(font_data, b"head").ok_or_else(invalid)?;
+        let hhea = find_table(font_data, b"hhea").ok_or_else(invalid)?;
+        let hmtx = find_table(font_data, b"hmtx").ok_or_else(invalid)?;
+        let units_per_em = read_u16(head, 18).ok_or_else(invalid)?;
+        let count = read_u16(hhea, 34).ok_or_else(invalid)?;
+        let advances = (0..usize::from(count))
+            .map(|glyph| read_u16(hmtx, glyph * 4))
+            .collect::<Option<Vec<u16>>>()
+            .ok_or_else(invalid)?;
+        if units_per_em == 0 || advances.is_empty() {
+            return Err(invalid());
+        }
+        Ok(HorizontalMetrics {
+            units_per_em,
+            advances,
+        })
+    }
+
+    /// Returns the advance width of a glyph in em. Glyphs after the last metrics entry
+    /// share its advance width.
+    pub fn advance(&self, glyph: u32) -> f64 {
+        let advance = self
+            .advances
+            .get(glyph as usize)
+            .or_else(|| self.advances.last())
+            .copied()
+            .unwrap_or(0);
+        f64::from(advance) / f64::from(self.units_per_em)
+    }
+}
+
+/// The character to glyph mappings of a TrueType font, by platform and encoding.
+pub struct Cmap {
+    data: Vec<u8>,
+    /// Platform id, encoding id and offset of each subtable.
+    subtables: Vec<(u16, u16, usize)>,
+}
+
+impl Cmap {
+    pub fn parse(font_data: &[u8]) -> Fallible<Self> {
+        let data = find_table(font_data, b"cmap")
+            .ok_or_else(|| failure::format_err!("font has no cmap table"))?;
+        let invalid = || failure::format_err!("invalid cmap table");
+        let num_subtables = read_u16(data, 2).ok_or_else(invalid)?;
+        let mut subtables = Vec::with_capacity(usize::from(num_subtables));
+        for index in 0..usize::from(num_subtables) {
+            let record = 4 + index * 8;
+            subtables.push((
+                read_u16(data, record).ok_or_else(invalid)?,
+                read_u16(data, record + 2).ok_or_else(invalid)?,
+                read_u32(data, record + 4).ok_or_else(invalid)? as usize,
+            ));
+        }
+        Ok(Cmap {
+            data: data.to_owned(),
+            subtables,
+        })
+    }
+
+    pub fn has_subtable(&self, platform_id: u16, encoding_id: u16) -> bool {
+        self.subtables
+            .iter()
+            .any(|&(platform, encoding, _)| (platform, encoding) == (platform_id, encoding_id))
+    }
+
+    /// Maps a character code to a glyph through the given subtable, returning `None`
+    /// if the subtable is missing or maps the code to the missing glyph.
+    pub fn lookup(&self, platform_id: u16, encoding_id: u16, code: u32) -> Option<u32> {
+        let &(_, _, offset) = self
+            .subtables
+            .iter()
+            .find(|&&(platform, encoding, _)| (platform, encoding) == (platform_id, encoding_id))?;
+        let data = self.data.get(offset..)?;
+        let glyph = match read_u16(data, 0)? {
+            0 => u32::from(*data.get(6 + code as usize)?),
+            4 => u32::from(lookup_format_4(data, code)?),
+            6 => {
+                let first_code = u32::from(read_u16(data, 6)?);
+                let entry_count = u32::from(read_u16(data, 8)?);
+                if code < first_code || code - first_code >= entry_count {
+                    return None;
+                }
+                u32::from(read_u16(data, 10 + 2 * (code - first_code) as usize)?)
+            }
+            12 => lookup_format_12(data, code)?,
+            format => {
+                log::debug!("unsupported cmap subtable format {}", format);
+                return None;
+            }
+        };
+        if glyph == 0 {
+            None
+        } else {
+            Some(glyph)
+        }
+    }
+}
+
+fn lookup_format_4(data: &[u8], code: u32) -> Option<u16> {
+    if code > 0xffff {
+        return None;
+    }
+    let code = code as u16;
+    let seg_count = usize::from(read_u16(data, 6)? / 2);
+    let end_codes = 14;
+    let start_codes = end_codes + seg_count * 2 + 2;
+    let id_deltas = start_codes + seg_count * 2;
+    let id_range_offsets = id_deltas + seg_count * 2;
+    for segment in 0..seg_count {
+        if code > read_u16(data, end_codes + segment * 2)? {
+            continue;
+        }
+        let start_code = read_u16(data, start_codes + segment * 2)?;
+        if code < start_code {
+            return None;
+        }
+        let id_delta = read_u16(data, id_deltas + segment * 2)?;
+        let range_offset_position = id_range_offsets + segment * 2;
+        let id_range_offset = read_u16(data, range_offset_position)?;
+        if id_range_offset == 0 {
+            return Some(code.wrapping_add(id_delta));
+        }
+        // the offset is relative to its own position in the idRangeOffset array
+        let position = range_offset_position
+            + usize::from(id_range_offset)
+            + usize::from(code - start_code) * 2;
+        let glyph = read_u16(data, position)?;
+        if glyph == 0 {
+            return None;
+        }
+        return Some(glyph.wrapping_add(id_delta));
+    }
+    None
+}
+
+fn lookup_format_12(data: &[u8], code: u32) -> Option<u32> {
+    let num_groups = read_u32(data, 12)? as usize;
+    for group in 0..num_groups {
+        let record = 16 + group * 12;
+        let start_code = read_u32(data, record)?;
+        let end_code = read_u32(data, record + 4)?;
+        if code >= start_code && code <= end_code {
+            return Some(read_u32(data, record + 8)? + (code - start_code));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_u16(data: &mut Vec<u8>, values: &[u16]) {
+        for value in values {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+
+    /// Builds a font with a cmap table holding a format 4 subtable for (3, 1) and a
+    /// format 0 subtable for (1, 0).
+    fn font_with_cmap() -> Vec<u8> {
+        let mut format_4 = Vec::new();
+        // segments: 0x41..=0x43 by delta, 0xf020..=0xf021 through the glyph array, end
+        push_u16(&mut format_4, &[4, 0, 0, 6, 4, 1, 2]);
+        push_u16(&mut format_4, &[0x43, 0xf021, 0xffff, 0]);
+        push_u16(&mut format_4, &[0x41, 0xf020, 0xffff]);
+        push_u16(&mut format_4, &[(10u16).wrapping_sub(0x41), 0, 1]);
+        push_u16(&mut format_4, &[0, 4, 0]);
+        push_u16(&mut format_4, &[20, 21]);
+        let length = format_4.len() as u16;
+        format_4[2..4].copy_from_slice(&length.to_be_bytes());
+
+        let mut format_0 = Vec::new();
+        push_u16(&mut format_0, &[0, 262, 0]);
+        format_0.extend((0..=255u8).map(|code| if code == 0x8e { 30 } else { 0 }));
+
+        let mut cmap = Vec::new();
+        push_u16(&mut cmap, &[0, 2, 3, 1, 0, 20, 1, 0]);
+        cmap.extend_from_slice(&((20 + format_4.len()) as u32).to_be_bytes());
+        cmap.extend(format_4);
+        cmap.extend(format_0);
+
+        let mut font = Vec::new();
+        font.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+        push_u16(&mut font, &[1, 16, 0, 0]);
+        font.extend_from_slice(b"cmap");
+        font.extend_from_slice(&0u32.to_be_bytes());
+        font.extend_from_slice(&28u32.to_be_bytes());
+        font.extend_from_slice(&(cmap.len() as u32).to_be_bytes());
+        font.extend(cmap);
+        font
+    }
+
+    #[test]
+    fn cmap_lookup() {
+        let cmap = Cmap::parse(&font_with_cmap()).unwrap();
+        assert!(cmap.has_subtable(3, 1));
+        assert!(!cmap.has_subtable(3, 0));
+        assert_eq!(Some(10), cmap.lookup(3, 1, 0x41));
+        assert_eq!(Some(12), cmap.lookup(3, 1, 0x43));
+        assert_eq!(None, cmap.lookup(3, 1, 0x44));
+        assert_eq!(Some(20), cmap.lookup(3, 1, 0xf020));
+        assert_eq!(Some(21), cmap.lookup(3, 1, 0xf021));
+        assert_eq!(Some(30), cmap.lookup(1, 0, 0x8e));
+        assert_eq!(None, cmap.lookup(1, 0, 0x41));
+        assert_eq!(None, cmap.lookup(3, 0, 0x41));
+    }
+
+    #[test]
+    fn collection_faces() {
+        let font = font_with_cmap();
+        let mut collection = b"ttcf".to_vec();
+        for value in &[0x0001_0000u32, 1, 16] {
+            collection.extend_from_slice(&value.to_be_bytes());
+        }
+        // the offset of the table is from the start of the collection
+        let mut face = font.clone();
+        let offset = read_u32(&face, 20).unwrap() + 16;
+        face[20..24].copy_from_slice(&offset.to_be_bytes());
+        collection.extend(face);
+
+        let extracted = collection_face(&collection, 0).unwrap();
+        assert_eq!(find_table(&font, b"cmap"), find_table(&extracted, b"cmap"));
+        assert_eq!(None, collection_face(&collection, 1));
+        assert_eq!(None, collection_face(&font, 0));
+
+        // a face without tables
+        let mut empty = collection[..16].to_vec();
+        empty.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(None, collection_face(&empty, 0));
+    }
+
+    #[test]
+    fn sfnt_table_count_limits() {
+        assert_eq!(None, build_sfnt(b"OTTO", Vec::new()));
+        let tables = (0..4096u32)
+            .map(|index| (index.to_be_bytes(), Vec::new()))
+            .collect();
+        assert_eq!(None, build_sfnt(b"OTTO", tables));
+    }
+}
@@ -0,0 +1,123 @@
+use super::encoding::{EncodingTable, GlyphName};
+
+fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\r' | b'\n' | b'\x0c' | b'\0')
+}
+
+fn is_delimiter(byte: u8) -> bool {
+    matches!(
+        byte,
+        b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%'
+    )
+}
+
+/// Splits PostScript source into tokens, keeping the slash of literal names and
+/// dropping comments. Strings and procedures are not interpreted.
+fn tokenize(data: &[u8]) -> Vec<&[u8]> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let byte = data[pos];
+        if is_whitespace(byte) {
+            pos += 1;
+        } else if byte == b'%' {
+            while pos < data.len() && data[pos] != b'\n' && data[pos] != b'\r' {
+                pos += 1;
+            }
+        } else if is_delimiter(byte) && byte != b'/' {
+            tokens.push(&data[pos..=pos]);
+            pos += 1;
+        } else {
+            let start = pos;
+            pos += 1;
+            while pos < data.len() && !is_whitespace(data[pos]) && !is_delimiter(data[pos]) {
+                pos += 1;
+            }
+            tokens.push(&data[start..pos]);
+        }
+    }
+    tokens
+}
+
+/// Returns the cleartext portion of a Type 1 font program, which precedes the
+/// encrypted private dictionary and charstrings.
+fn cleartext(data: &[u8]) -> &[u8] {
+    // programs in PFB format start with a segment header
+    let data = if data.len() >= 6 && data[0] == 0x80 && data[1] == 0x01 {
+        let length = u32::from_le_bytes([data[2], data[3], data[4], data[5]]) as usize;
+        &data[6..data.len().min(6 + length)]
+    } else {
+        data
+    };
+    match data
+        .windows(b"eexec".len())
+        .position(|window| window == b"eexec")
+    {
+        Some(end) => &data[..end],
+        None => data,
+    }
+}
+
+/// Parses the `/Encoding` entry of a Type 1 font program, which is either the name
+/// `StandardEncoding` or an array filled in by `dup <code> /<name> put` sequences.
+pub fn built_in_encoding(data: &[u8]) -> Option<EncodingTable> {
+    let tokens = tokenize(cleartext(data));
+    let start = tokens.iter().position(|token| *token == b"/Encoding")?;
+    let mut tokens = tokens[start + 1..].iter();
+    let first = tokens.next()?;
+    if *first == b"StandardEncoding" {
+        return Some(EncodingTable::standard());
+    }
+
+    let mut table = EncodingTable::empty();
+    let mut previous: [&[u8]; 3] = [b"", b"", b""];
+    for token in tokens {
+        if *token == b"def" || *token == b"readonly" {
+            break;
+        }
+        if *token == b"put" && previous[0] == b"dup" && previous[2].starts_with(b"/") {
+            let code = std::str::from_utf8(previous[1])
+                .ok()
+                .and_then(|code| code.parse::<u8>().ok());
+            if let Some(code) = code {
+                table.set(code, GlyphName::new(&previous[2][1..]));
+            }
+        }
+        previous = [previous[1], previous[2], token];
+    }
+    Some(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standard_encoding() {
+        let program = b"%!PS-AdobeFont-1.0: CMR10\n/FontName /CMR10 def\n\
+            /Encoding StandardEncoding def\ncurrentfile eexec\n\x8f\x02";
+        assert_eq!(Some(EncodingTable::standard()), built_in_encoding(program));
+    }
+
+    #[test]
+    fn encoding_array() {
+        let program = b"%!PS-AdobeFont-1.0: CMSY10\n\
+            /Encoding 256 array\n0 1 255 {1 index exch /.notdef put} for\n\
+            dup 0 /minus put\ndup 161 /minus put % comment\ndup 50 /element put\n\
+            readonly def\ndup 51 /owner put\ncurrentfile eexec\n\x8f\x02";
+        let table = built_in_encoding(program).unwrap();
+        assert_eq!(b"minus", table.lookup(0).unwrap().as_bytes());
+        assert_eq!(b"minus", table.lookup(161).unwrap().as_bytes());
+        assert_eq!(b"element", table.lookup(50).unwrap().as_bytes());
+        assert_eq!(None, table.lookup(51));
+        assert_eq!(None, table.lookup(65));
+    }
+
+    #[test]
+    fn missing_encoding() {
+        assert_eq!(
+            None,
+            built_in_encoding(b"/FontName /F def currentfile eexec")
+        );
+    }
+}
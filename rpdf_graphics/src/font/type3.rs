@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+
+use failure::Fallible;
+
+use rpdf_lopdf_extra::*;
+
+/// The glyphs of a Type 3 font, which are described by content streams.
+pub struct Type3Glyphs {
+    matrix: euclid::Transform2D<f32>,
+    char_procs: HashMap<Vec<u8>, lopdf::Object>,
+    resources: Option<lopdf::Object>,
+}
+
+impl Type3Glyphs {
+    pub fn try_from_dictionary(doc: &lopdf::Document, dict: &lopdf::Dictionary) -> Fallible<Self> {
+        let [a, b, c, d, e, f]: [f32; 6] = doc.deserialize_object(dict.try_get(b"FontMatrix")?)?;
+        let char_procs = doc
+            .resolve_object(dict.try_get(b"CharProcs")?)?
+            .try_as_dict()?
+            .iter()
+            .map(|(name, obj)| (name.clone(), obj.clone()))
+            .collect();
+        Ok(Type3Glyphs {
+            matrix: euclid::Transform2D::row_major(a, b, c, d, e, f),
+            char_procs,
+            resources: dict.get(b"Resources").cloned(),
+        })
+    }
+
+    /// Maps glyph space to text space.
+    pub fn matrix(&self) -> &euclid::Transform2D<f32> {
+        &self.matrix
+    }
+
+    /// Returns the content stream describing a glyph, usually as a reference.
+    pub fn char_proc(&self, glyph_name: &[u8]) -> Option<&lopdf::Object> {
+        self.char_procs.get(glyph_name)
+    }
+
+    /// The resources used by glyph descriptions, if the font has its own.
+    pub fn resources(&self) -> Option<&lopdf::Object> {
+        self.resources.as_ref()
+    }
+}
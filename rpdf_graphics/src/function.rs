@@ -0,0 +1,559 @@
+use failure::Fallible;
+use serde_derive::Deserialize;
+
+use rpdf_lopdf_extra::*;
+
+use crate::data::BitReader;
+
+mod calculator;
+
+/// Limits how deeply stitching functions can nest, so that one that refers to itself
+/// can't exhaust the stack.
+const MAX_DEPTH: u32 = 16;
+
+/// Limits the number of samples of a sampled function, which its `Size` entry would
+/// otherwise let allocate an arbitrary amount of memory.
+const MAX_SAMPLES: usize = 1 << 24;
+
+#[derive(Deserialize)]
+struct FunctionDictionary {
+    #[serde(rename = "FunctionType")]
+    function_type: u32,
+    #[serde(rename = "Domain")]
+    domain: Vec<f32>,
+    #[serde(rename = "Range")]
+    range: Option<Vec<f32>>,
+}
+
+#[derive(Deserialize)]
+struct SampledFunctionDictionary {
+    #[serde(rename = "Size")]
+    size: Vec<u32>,
+    #[serde(rename = "BitsPerSample")]
+    bits_per_sample: u32,
+    #[serde(rename = "Encode")]
+    encode: Option<Vec<f32>>,
+    #[serde(rename = "Decode")]
+    decode: Option<Vec<f32>>,
+}
+
+#[derive(Deserialize)]
+struct ExponentialFunctionDictionary {
+    #[serde(rename = "C0")]
+    c0: Option<Vec<f32>>,
+    #[serde(rename = "C1")]
+    c1: Option<Vec<f32>>,
+    #[serde(rename = "N")]
+    exponent: f32,
+}
+
+#[derive(Deserialize)]
+struct StitchingFunctionDictionary {
+    #[serde(rename = "Bounds")]
+    bounds: Vec<f32>,
+    #[serde(rename = "Encode")]
+    encode: Vec<f32>,
+}
+
+fn interpolate(x: f32, x_min: f32, x_max: f32, y_min: f32, y_max: f32) -> f32 {
+    if x_max == x_min {
+        return y_min;
+    }
+    y_min + (x - x_min) * (y_max - y_min) / (x_max - x_min)
+}
+
+fn clip_to_ranges(values: &mut [f32], ranges: &[f32]) {
+    for (value, range) in values.iter_mut().zip(ranges.chunks(2)) {
+        if range.len() == 2 {
+            *value = value.max(range[0]).min(range[1]);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SampledFunction {
+    size: Vec<u32>,
+    encode: Vec<f32>,
+    outputs: usize,
+    samples: Vec<f32>,
+}
+
+impl SampledFunction {
+    fn try_from_object(
+        doc: &lopdf::Document,
+        obj: &lopdf::Object,
+        domain: &[f32],
+        range: &[f32],
+    ) -> Fallible<Self> {
+        let dict: SampledFunctionDictionary = doc.deserialize_object(obj)?;
+        let stream = doc.resolve_object(obj)?.try_as_stream()?;
+        let data = stream.decoded_content();
+
+        let inputs = domain.len() / 2;
+        let outputs = range.len() / 2;
+        if dict.size.len() != inputs || outputs == 0 {
+            failure::bail!("sampled function has inconsistent dimensions");
+        }
+        if dict.bits_per_sample == 0 || dict.bits_per_sample > 32 {
+            failure::bail!("unsupported bits per sample {}", dict.bits_per_sample);
+        }
+
+        let size = dict.size;
+        if size.contains(&0) {
+            failure::bail!("sampled function has no samples");
+        }
+        let encode = dict.encode.unwrap_or_else(|| {
+            size.iter()
+                .flat_map(|size| vec![0.0, *size as f32 - 1.0])
+                .collect()
+        });
+        let decode = dict.decode.unwrap_or_else(|| range.to_owned());
+        if encode.len() < inputs * 2 || decode.len() < outputs * 2 {
+            failure::bail!("sampled function has too few encode or decode values");
+        }
+
+        let max_sample = ((1u64 << dict.bits_per_sample) - 1) as f32;
+        let count = size
+            .iter()
+            .try_fold(outputs, |count, size| count.checked_mul(*size as usize))
+            .filter(|count| *count <= MAX_SAMPLES)
+            .ok_or_else(|| failure::format_err!("sampled function has too many samples"))?;
+        let mut reader = BitReader::new(&data);
+        let mut samples = Vec::with_capacity(count);
+        for index in 0..count {
+            let raw = reader.read(dict.bits_per_sample).unwrap_or(0) as f32;
+            let output = index % outputs;
+            samples.push(interpolate(
+                raw,
+                0.0,
+                max_sample,
+                decode[output * 2],
+                decode[output * 2 + 1],
+            ));
+        }
+
+        Ok(SampledFunction {
+            size,
+            encode,
+            outputs,
+            samples,
+        })
+    }
+
+    fn evaluate(&self, domain: &[f32], input: &[f32]) -> Vec<f32> {
+        let mut floors = Vec::with_capacity(self.size.len());
+        let mut fractions = Vec::with_capacity(self.size.len());
+        for (i, x) in input.iter().enumerate() {
+            let max_index = (self.size[i] as f32 - 1.0).max(0.0);
+            let e = interpolate(
+                *x,
+                domain[i * 2],
+                domain[i * 2 + 1],
+                self.encode[i * 2],
+                self.encode[i * 2 + 1],
+            )
+            .max(0.0)
+            .min(max_index);
+            let floor = e.floor().min((max_index - 1.0).max(0.0));
+            floors.push(floor as usize);
+            fractions.push(e - floor);
+        }
+
+        // multilinear interpolation between the samples at the corners of the enclosing cell
+        let mut output = vec![0.0; self.outputs];
+        for corner in 0..(1usize << input.len()) {
+            let mut weight = 1.0;
+            let mut offset = 0;
+            let mut stride = 1;
+            for i in 0..input.len() {
+                let upper = corner & (1 << i) != 0;
+                let index = if upper {
+                    weight *= fractions[i];
+                    (floors[i] + 1).min(self.size[i] as usize - 1)
+                } else {
+                    weight *= 1.0 - fractions[i];
+                    floors[i]
+                };
+                offset += index * stride;
+                stride *= self.size[i] as usize;
+            }
+            if weight == 0.0 {
+                continue;
+            }
+            for (j, value) in output.iter_mut().enumerate() {
+                *value += weight * self.samples[offset * self.outputs + j];
+            }
+        }
+        output
+    }
+}
+
+#[derive(Debug)]
+struct ExponentialFunction {
+    c0: Vec<f32>,
+    c1: Vec<f32>,
+    exponent: f32,
+}
+
+impl ExponentialFunction {
+    fn try_from_object(doc: &lopdf::Document, obj: &lopdf::Object) -> Fallible<Self> {
+        let dict: ExponentialFunctionDictionary = doc.deserialize_object(obj)?;
+        let c0 = dict.c0.unwrap_or_else(|| vec![0.0]);
+        let c1 = dict.c1.unwrap_or_else(|| vec![1.0]);
+        if c0.len() != c1.len() {
+            failure::bail!("exponential function has mismatched C0 and C1");
+        }
+        Ok(ExponentialFunction {
+            c0,
+            c1,
+            exponent: dict.exponent,
+        })
+    }
+
+    fn evaluate(&self, input: &[f32]) -> Vec<f32> {
+        let factor = input[0].powf(self.exponent);
+        self.c0
+            .iter()
+            .zip(self.c1.iter())
+            .map(|(c0, c1)| c0 + factor * (c1 - c0))
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+struct StitchingFunction {
+    functions: Vec<Function>,
+    bounds: Vec<f32>,
+    encode: Vec<f32>,
+}
+
+impl StitchingFunction {
+    fn try_from_object(doc: &lopdf::Document, obj: &lopdf::Object, depth: u32) -> Fallible<Self> {
+        let dict: StitchingFunctionDictionary = doc.deserialize_object(obj)?;
+        let functions = doc
+            .resolve_object(function_dictionary(doc, obj)?.try_get(b"Functions")?)?
+            .as_array()
+            .ok_or_else(|| failure::format_err!("stitching function is missing functions"))?
+            .iter()
+            .map(|function_obj| Function::try_from_nested_object(doc, function_obj, depth + 1))
+            .collect::<Fallible<Vec<_>>>()?;
+        if functions.is_empty()
+            || dict.bounds.len() + 1 != functions.len()
+            || dict.encode.len() != functions.len() * 2
+        {
+            failure::bail!("stitching function has inconsistent dimensions");
+        }
+        Ok(StitchingFunction {
+            functions,
+            bounds: dict.bounds,
+            encode: dict.encode,
+        })
+    }
+
+    fn evaluate(&self, domain: &[f32], input: &[f32]) -> Vec<f32> {
+        let x = input[0];
+        let index = self
+            .bounds
+            .iter()
+            .position(|bound| x < *bound)
+            .unwrap_or(self.bounds.len());
+        let lower = if index == 0 {
+            domain[0]
+        } else {
+            self.bounds[index - 1]
+        };
+        let upper = if index == self.bounds.len() {
+            domain[1]
+        } else {
+            self.bounds[index]
+        };
+        let encoded = interpolate(
+            x,
+            lower,
+            upper,
+            self.encode[index * 2],
+            self.encode[index * 2 + 1],
+        );
+        self.functions[index].evaluate(&[encoded])
+    }
+}
+
+#[derive(Debug)]
+enum FunctionKind {
+    Sampled(SampledFunction),
+    Exponential(ExponentialFunction),
+    Stitching(StitchingFunction),
+    Calculator(calculator::CalculatorFunction),
+}
+
+/// A PDF function object, mapping `m` input values to `n` output values.
+#[derive(Debug)]
+pub struct Function {
+    domain: Vec<f32>,
+    range: Option<Vec<f32>>,
+    kind: FunctionKind,
+}
+
+fn function_dictionary<'a>(
+    doc: &'a lopdf::Document,
+    obj: &'a lopdf::Object,
+) -> Fallible<&'a lopdf::Dictionary> {
+    match doc.resolve_object(obj)? {
+        lopdf::Object::Dictionary(ref dict) => Ok(dict),
+        lopdf::Object::Stream(ref stream) => Ok(&stream.dict),
+        _ => failure::bail!("function is neither a dictionary nor a stream"),
+    }
+}
+
+impl Function {
+    pub fn try_from_object(doc: &lopdf::Document, obj: &lopdf::Object) -> Fallible<Self> {
+        Self::try_from_nested_object(doc, obj, 0)
+    }
+
+    fn try_from_nested_object(
+        doc: &lopdf::Document,
+        obj: &lopdf::Object,
+        depth: u32,
+    ) -> Fallible<Self> {
+        if depth > MAX_DEPTH {
+            failure::bail!("stitching functions are nested too deeply");
+        }
+        let dict: FunctionDictionary = doc.deserialize_object(obj)?;
+        if dict.domain.is_empty() || dict.domain.len() % 2 != 0 {
+            failure::bail!("function has an invalid domain");
+        }
+
+        let kind = match dict.function_type {
+            0 => {
+                let range = dict
+                    .range
+                    .as_ref()
+                    .ok_or_else(|| failure::format_err!("sampled function is missing a range"))?;
+                FunctionKind::Sampled(SampledFunction::try_from_object(
+                    doc,
+                    obj,
+                    &dict.domain,
+                    range,
+                )?)
+            }
+            2 => FunctionKind::Exponential(ExponentialFunction::try_from_object(doc, obj)?),
+            3 => FunctionKind::Stitching(StitchingFunction::try_from_object(doc, obj, depth)?),
+            4 => {
+                if dict.range.is_none() {
+                    failure::bail!("calculator function is missing a range");
+                }
+                let stream = doc.resolve_object(obj)?.try_as_stream()?;
+                FunctionKind::Calculator(calculator::CalculatorFunction::parse(
+                    &stream.decoded_content(),
+                )?)
+            }
+            function_type => failure::bail!("unsupported function type {}", function_type),
+        };
+
+        Ok(Function {
+            domain: dict.domain,
+            range: dict.range,
+            kind,
+        })
+    }
+
+    pub fn input_size(&self) -> usize {
+        self.domain.len() / 2
+    }
+
+    pub fn evaluate(&self, input: &[f32]) -> Vec<f32> {
+        let mut input = input.to_owned();
+        input.resize(self.input_size(), 0.0);
+        clip_to_ranges(&mut input, &self.domain);
+
+        let mut output = match self.kind {
+            FunctionKind::Sampled(ref function) => function.evaluate(&self.domain, &input),
+            FunctionKind::Exponential(ref function) => function.evaluate(&input),
+            FunctionKind::Stitching(ref function) => function.evaluate(&self.domain, &input),
+            FunctionKind::Calculator(ref function) => {
+                let outputs = self.range.as_ref().map_or(0, |range| range.len() / 2);
+                function.evaluate(&input, outputs).unwrap_or_else(|err| {
+                    log::warn!("failed to evaluate calculator function: {}", err);
+                    vec![0.0; outputs]
+                })
+            }
+        };
+
+        if let Some(ref range) = self.range {
+            clip_to_ranges(&mut output, range);
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbers(values: &[f64]) -> lopdf::Object {
+        lopdf::Object::Array(values.iter().map(|v| lopdf::Object::Real(*v)).collect())
+    }
+
+    fn exponential(c0: f64, c1: f64, exponent: f64) -> lopdf::Dictionary {
+        let mut dict = lopdf::Dictionary::new();
+        dict.set("FunctionType", lopdf::Object::Integer(2));
+        dict.set("Domain", numbers(&[0.0, 1.0]));
+        dict.set("C0", numbers(&[c0]));
+        dict.set("C1", numbers(&[c1]));
+        dict.set("N", lopdf::Object::Real(exponent));
+        dict
+    }
+
+    fn assert_evaluates(function: &Function, cases: &[(&[f32], &[f32])]) {
+        for (input, expected) in cases {
+            let output = function.evaluate(input);
+            assert_eq!(expected.len(), output.len());
+            for (actual, expected) in output.iter().zip(expected.iter()) {
+                assert!(
+                    (actual - expected).abs() < 1e-4,
+                    "{:?} gave {:?}, expected {:?}",
+                    input,
+                    output,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sampled_function() {
+        // a 2x2 grid of 8-bit samples with two outputs each
+        let mut dict = lopdf::Dictionary::new();
+        dict.set("FunctionType", lopdf::Object::Integer(0));
+        dict.set("Domain", numbers(&[0.0, 1.0, 0.0, 1.0]));
+        dict.set("Range", numbers(&[0.0, 1.0, 0.0, 2.0]));
+        dict.set(
+            "Size",
+            lopdf::Object::Array(vec![lopdf::Object::Integer(2), lopdf::Object::Integer(2)]),
+        );
+        dict.set("BitsPerSample", lopdf::Object::Integer(8));
+        let samples = vec![0, 0, 255, 255, 0, 255, 255, 0];
+        let obj = lopdf::Object::Stream(lopdf::Stream::new(dict, samples));
+        let function = Function::try_from_object(&lopdf::Document::new(), &obj).unwrap();
+
+        assert_evaluates(
+            &function,
+            &[
+                (&[0.0, 0.0], &[0.0, 0.0]),
+                (&[1.0, 0.0], &[1.0, 2.0]),
+                (&[0.0, 1.0], &[0.0, 2.0]),
+                (&[1.0, 1.0], &[1.0, 0.0]),
+                (&[0.5, 0.5], &[0.5, 1.0]),
+                (&[0.25, 0.0], &[0.25, 0.5]),
+                // inputs are clipped to the domain
+                (&[2.0, -1.0], &[1.0, 2.0]),
+            ],
+        );
+    }
+
+    #[test]
+    fn exponential_function() {
+        let obj = lopdf::Object::Dictionary(exponential(1.0, 3.0, 2.0));
+        let function = Function::try_from_object(&lopdf::Document::new(), &obj).unwrap();
+        assert_evaluates(
+            &function,
+            &[
+                (&[0.0], &[1.0]),
+                (&[0.5], &[1.5]),
+                (&[1.0], &[3.0]),
+                (&[1.5], &[3.0]),
+            ],
+        );
+    }
+
+    #[test]
+    fn stitching_function() {
+        let mut dict = lopdf::Dictionary::new();
+        dict.set("FunctionType", lopdf::Object::Integer(3));
+        dict.set("Domain", numbers(&[0.0, 2.0]));
+        dict.set(
+            "Functions",
+            lopdf::Object::Array(vec![
+                exponential(0.0, 1.0, 1.0).into(),
+                exponential(1.0, 0.0, 1.0).into(),
+            ]),
+        );
+        dict.set("Bounds", numbers(&[1.0]));
+        dict.set("Encode", numbers(&[0.0, 1.0, 1.0, 0.0]));
+        let obj = lopdf::Object::Dictionary(dict);
+        let function = Function::try_from_object(&lopdf::Document::new(), &obj).unwrap();
+        assert_evaluates(
+            &function,
+            &[
+                (&[0.0], &[0.0]),
+                (&[0.5], &[0.5]),
+                (&[1.0], &[0.0]),
+                (&[1.5], &[0.5]),
+                (&[2.0], &[1.0]),
+            ],
+        );
+    }
+
+    #[test]
+    fn calculator_function() {
+        let mut dict = lopdf::Dictionary::new();
+        dict.set("FunctionType", lopdf::Object::Integer(4));
+        dict.set("Domain", numbers(&[-1.0, 1.0, -1.0, 1.0]));
+        dict.set("Range", numbers(&[0.0, 1.0]));
+        // a round spot function
+        let program = b"{ dup mul exch dup mul add 1 exch sub }".to_vec();
+        let obj = lopdf::Object::Stream(lopdf::Stream::new(dict, program));
+        let function = Function::try_from_object(&lopdf::Document::new(), &obj).unwrap();
+        assert_evaluates(
+            &function,
+            &[
+                (&[0.0, 0.0], &[1.0]),
+                (&[0.5, 0.5], &[0.5]),
+                // outputs are clipped to the range
+                (&[1.0, 1.0], &[0.0]),
+            ],
+        );
+
+        let mut dict = lopdf::Dictionary::new();
+        dict.set("FunctionType", lopdf::Object::Integer(4));
+        dict.set("Domain", numbers(&[0.0, 1.0]));
+        let obj = lopdf::Object::Stream(lopdf::Stream::new(dict, b"{ }".to_vec()));
+        assert!(Function::try_from_object(&lopdf::Document::new(), &obj).is_err());
+    }
+
+    #[test]
+    fn invalid_functions() {
+        let sampled = |size: i64, encode: &[f64]| {
+            let mut dict = lopdf::Dictionary::new();
+            dict.set("FunctionType", lopdf::Object::Integer(0));
+            dict.set("Domain", numbers(&[0.0, 1.0]));
+            dict.set("Range", numbers(&[0.0, 1.0]));
+            dict.set(
+                "Size",
+                lopdf::Object::Array(vec![lopdf::Object::Integer(size)]),
+            );
+            dict.set("BitsPerSample", lopdf::Object::Integer(8));
+            dict.set("Encode", numbers(encode));
+            lopdf::Object::Stream(lopdf::Stream::new(dict, vec![0, 255]))
+        };
+        let doc = lopdf::Document::new();
+        assert!(Function::try_from_object(&doc, &sampled(2, &[0.0, 1.0])).is_ok());
+        assert!(Function::try_from_object(&doc, &sampled(0, &[0.0, 1.0])).is_err());
+        assert!(Function::try_from_object(&doc, &sampled(2, &[0.0])).is_err());
+        assert!(
+            Function::try_from_object(&doc, &sampled(i64::from(u32::MAX), &[0.0, 1.0])).is_err()
+        );
+
+        // a stitching function that contains itself
+        let mut doc = lopdf::Document::new();
+        let id = doc.add_object(lopdf::Object::Null);
+        let mut dict = lopdf::Dictionary::new();
+        dict.set("FunctionType", lopdf::Object::Integer(3));
+        dict.set("Domain", numbers(&[0.0, 1.0]));
+        dict.set("Functions", lopdf::Object::Array(vec![id.into()]));
+        dict.set("Bounds", numbers(&[]));
+        dict.set("Encode", numbers(&[0.0, 1.0]));
+        doc.objects.insert(id, dict.into());
+        assert!(Function::try_from_object(&doc, &id.into()).is_err());
+    }
+}
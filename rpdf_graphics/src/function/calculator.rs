@@ -0,0 +1,614 @@
+use failure::Fallible;
+
+/// Maximum depth of the operand stack, as required of conforming readers.
+const MAX_STACK_DEPTH: usize = 100;
+/// Maximum nesting of procedures, which are parsed recursively.
+const MAX_PROCEDURE_DEPTH: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Value {
+    Integer(i64),
+    Real(f64),
+    Boolean(bool),
+}
+
+impl Value {
+    fn to_real(self) -> Fallible<f64> {
+        match self {
+            Value::Integer(value) => Ok(value as f64),
+            Value::Real(value) => Ok(value),
+            Value::Boolean(_) => failure::bail!("expected a number, found a boolean"),
+        }
+    }
+
+    fn to_integer(self) -> Fallible<i64> {
+        match self {
+            Value::Integer(value) => Ok(value),
+            _ => failure::bail!("expected an integer, found {:?}", self),
+        }
+    }
+
+    fn to_boolean(self) -> Fallible<bool> {
+        match self {
+            Value::Boolean(value) => Ok(value),
+            _ => failure::bail!("expected a boolean, found {:?}", self),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Operator {
+    Abs,
+    Add,
+    Atan,
+    Ceiling,
+    Cos,
+    Cvi,
+    Cvr,
+    Div,
+    Exp,
+    Floor,
+    Idiv,
+    Ln,
+    Log,
+    Mod,
+    Mul,
+    Neg,
+    Round,
+    Sin,
+    Sqrt,
+    Sub,
+    Truncate,
+    And,
+    Bitshift,
+    Eq,
+    Ge,
+    Gt,
+    Le,
+    Lt,
+    Ne,
+    Not,
+    Or,
+    Xor,
+    Copy,
+    Dup,
+    Exch,
+    Index,
+    Pop,
+    Roll,
+}
+
+impl Operator {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "abs" => Operator::Abs,
+            "add" => Operator::Add,
+            "atan" => Operator::Atan,
+            "ceiling" => Operator::Ceiling,
+            "cos" => Operator::Cos,
+            "cvi" => Operator::Cvi,
+            "cvr" => Operator::Cvr,
+            "div" => Operator::Div,
+            "exp" => Operator::Exp,
+            "floor" => Operator::Floor,
+            "idiv" => Operator::Idiv,
+            "ln" => Operator::Ln,
+            "log" => Operator::Log,
+            "mod" => Operator::Mod,
+            "mul" => Operator::Mul,
+            "neg" => Operator::Neg,
+            "round" => Operator::Round,
+            "sin" => Operator::Sin,
+            "sqrt" => Operator::Sqrt,
+            "sub" => Operator::Sub,
+            "truncate" => Operator::Truncate,
+            "and" => Operator::And,
+            "bitshift" => Operator::Bitshift,
+            "eq" => Operator::Eq,
+            "ge" => Operator::Ge,
+            "gt" => Operator::Gt,
+            "le" => Operator::Le,
+            "lt" => Operator::Lt,
+            "ne" => Operator::Ne,
+            "not" => Operator::Not,
+            "or" => Operator::Or,
+            "xor" => Operator::Xor,
+            "copy" => Operator::Copy,
+            "dup" => Operator::Dup,
+            "exch" => Operator::Exch,
+            "index" => Operator::Index,
+            "pop" => Operator::Pop,
+            "roll" => Operator::Roll,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug)]
+enum Instruction {
+    Push(Value),
+    Operator(Operator),
+    If(Vec<Instruction>),
+    IfElse(Vec<Instruction>, Vec<Instruction>),
+}
+
+fn tokenize(data: &[u8]) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut comment = false;
+    for &byte in data {
+        if comment {
+            comment = byte != b'\n' && byte != b'\r';
+            continue;
+        }
+        match byte {
+            b'{' | b'}' | b'%' | b' ' | b'\t' | b'\r' | b'\n' | b'\x0c' | b'\0' => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+                match byte {
+                    b'{' | b'}' => tokens.push((byte as char).to_string()),
+                    b'%' => comment = true,
+                    _ => {}
+                }
+            }
+            _ => token.push(byte as char),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// Parses the instructions of a procedure whose opening brace has been consumed.
+fn parse_procedure(
+    tokens: &mut std::vec::IntoIter<String>,
+    depth: usize,
+) -> Fallible<Vec<Instruction>> {
+    if depth > MAX_PROCEDURE_DEPTH {
+        failure::bail!("calculator procedures are nested too deeply");
+    }
+    let mut instructions = Vec::new();
+    // procedures are only valid as the operands of `if` and `ifelse`
+    let mut procedures = Vec::new();
+    while let Some(token) = tokens.next() {
+        match token.as_str() {
+            "{" => {
+                procedures.push(parse_procedure(tokens, depth + 1)?);
+            }
+            "}" => {
+                if !procedures.is_empty() {
+                    failure::bail!("procedure without a conditional operator");
+                }
+                return Ok(instructions);
+            }
+            "if" if procedures.len() == 1 => {
+                instructions.push(Instruction::If(procedures.remove(0)));
+            }
+            "ifelse" if procedures.len() == 2 => {
+                let if_false = procedures.remove(1);
+                let if_true = procedures.remove(0);
+                instructions.push(Instruction::IfElse(if_true, if_false));
+            }
+            _ if !procedures.is_empty() => {
+                failure::bail!("procedure without a conditional operator");
+            }
+            "true" => instructions.push(Instruction::Push(Value::Boolean(true))),
+            "false" => instructions.push(Instruction::Push(Value::Boolean(false))),
+            name => {
+                if let Some(operator) = Operator::from_name(name) {
+                    instructions.push(Instruction::Operator(operator));
+                } else if let Ok(value) = name.parse::<i64>() {
+                    instructions.push(Instruction::Push(Value::Integer(value)));
+                } else if let Ok(value) = name.parse::<f64>() {
+                    instructions.push(Instruction::Push(Value::Real(value)));
+                } else {
+                    failure::bail!("unknown calculator operator {}", name);
+                }
+            }
+        }
+    }
+    failure::bail!("unterminated procedure")
+}
+
+/// A type 4 function, given as a program in a subset of the PostScript language.
+#[derive(Debug)]
+pub struct CalculatorFunction {
+    program: Vec<Instruction>,
+}
+
+struct Stack {
+    values: Vec<Value>,
+}
+
+impl Stack {
+    fn push(&mut self, value: Value) -> Fallible<()> {
+        if self.values.len() >= MAX_STACK_DEPTH {
+            failure::bail!("calculator stack overflow");
+        }
+        self.values.push(value);
+        Ok(())
+    }
+
+    fn push_real(&mut self, value: f64) -> Fallible<()> {
+        self.push(Value::Real(value))
+    }
+
+    fn pop(&mut self) -> Fallible<Value> {
+        self.values
+            .pop()
+            .ok_or_else(|| failure::format_err!("calculator stack underflow"))
+    }
+
+    fn pop_real(&mut self) -> Fallible<f64> {
+        self.pop()?.to_real()
+    }
+
+    fn pop_integer(&mut self) -> Fallible<i64> {
+        self.pop()?.to_integer()
+    }
+
+    /// Returns the index of the value `depth` entries below the top of the stack.
+    fn index_from_top(&self, depth: i64) -> Fallible<usize> {
+        if depth < 0 || depth as usize >= self.values.len() {
+            failure::bail!("calculator stack underflow");
+        }
+        Ok(self.values.len() - 1 - depth as usize)
+    }
+
+    /// Applies an arithmetic operator that keeps integer operands as integers unless
+    /// the result overflows.
+    fn arithmetic(
+        &mut self,
+        integer: fn(i64, i64) -> Option<i64>,
+        real: fn(f64, f64) -> f64,
+    ) -> Fallible<()> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        match (a, b) {
+            (Value::Integer(a), Value::Integer(b)) => match integer(a, b) {
+                Some(value) => self.push(Value::Integer(value)),
+                None => self.push_real(real(a as f64, b as f64)),
+            },
+            _ => self.push_real(real(a.to_real()?, b.to_real()?)),
+        }
+    }
+
+    fn compare(&mut self, compare: fn(std::cmp::Ordering) -> bool) -> Fallible<()> {
+        let b = self.pop()?.to_real()?;
+        let a = self.pop()?.to_real()?;
+        let ordering = a
+            .partial_cmp(&b)
+            .ok_or_else(|| failure::format_err!("comparison of NaN"))?;
+        self.push(Value::Boolean(compare(ordering)))
+    }
+
+    fn bitwise(
+        &mut self,
+        integer: fn(i64, i64) -> i64,
+        boolean: fn(bool, bool) -> bool,
+    ) -> Fallible<()> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        match (a, b) {
+            (Value::Boolean(a), Value::Boolean(b)) => self.push(Value::Boolean(boolean(a, b))),
+            (a, b) => self.push(Value::Integer(integer(a.to_integer()?, b.to_integer()?))),
+        }
+    }
+
+    /// Applies a rounding operator, which preserves the type of its operand.
+    fn round(&mut self, round: fn(f64) -> f64) -> Fallible<()> {
+        match self.pop()? {
+            Value::Integer(value) => self.push(Value::Integer(value)),
+            value => self.push_real(round(value.to_real()?)),
+        }
+    }
+
+    fn execute(&mut self, instructions: &[Instruction]) -> Fallible<()> {
+        for instruction in instructions {
+            match *instruction {
+                Instruction::Push(value) => self.push(value)?,
+                Instruction::Operator(operator) => self.execute_operator(operator)?,
+                Instruction::If(ref procedure) => {
+                    if self.pop()?.to_boolean()? {
+                        self.execute(procedure)?;
+                    }
+                }
+                Instruction::IfElse(ref if_true, ref if_false) => {
+                    if self.pop()?.to_boolean()? {
+                        self.execute(if_true)?;
+                    } else {
+                        self.execute(if_false)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn execute_operator(&mut self, operator: Operator) -> Fallible<()> {
+        match operator {
+            Operator::Abs => match self.pop()? {
+                Value::Integer(value) => match value.checked_abs() {
+                    Some(value) => self.push(Value::Integer(value)),
+                    None => self.push_real((value as f64).abs()),
+                },
+                value => self.push_real(value.to_real()?.abs()),
+            },
+            Operator::Add => self.arithmetic(i64::checked_add, |a, b| a + b),
+            Operator::Sub => self.arithmetic(i64::checked_sub, |a, b| a - b),
+            Operator::Mul => self.arithmetic(i64::checked_mul, |a, b| a * b),
+            Operator::Neg => match self.pop()? {
+                Value::Integer(value) => match value.checked_neg() {
+                    Some(value) => self.push(Value::Integer(value)),
+                    None => self.push_real(-(value as f64)),
+                },
+                value => self.push_real(-value.to_real()?),
+            },
+            Operator::Div => {
+                let b = self.pop_real()?;
+                let a = self.pop_real()?;
+                if b == 0.0 {
+                    failure::bail!("division by zero");
+                }
+                self.push_real(a / b)
+            }
+            Operator::Idiv | Operator::Mod => {
+                let b = self.pop_integer()?;
+                let a = self.pop_integer()?;
+                let value = match operator {
+                    Operator::Idiv => a.checked_div(b),
+                    _ => a.checked_rem(b),
+                };
+                match value {
+                    Some(value) => self.push(Value::Integer(value)),
+                    None => failure::bail!("division by zero"),
+                }
+            }
+            Operator::Atan => {
+                let den = self.pop_real()?;
+                let num = self.pop_real()?;
+                if num == 0.0 && den == 0.0 {
+                    failure::bail!("undefined result of atan");
+                }
+                let angle = num.atan2(den).to_degrees();
+                self.push_real(if angle < 0.0 { angle + 360.0 } else { angle })
+            }
+            Operator::Sin => {
+                let angle = self.pop_real()?;
+                self.push_real(angle.to_radians().sin())
+            }
+            Operator::Cos => {
+                let angle = self.pop_real()?;
+                self.push_real(angle.to_radians().cos())
+            }
+            Operator::Exp => {
+                let exponent = self.pop_real()?;
+                let base = self.pop_real()?;
+                self.push_real(base.powf(exponent))
+            }
+            Operator::Ln | Operator::Log | Operator::Sqrt => {
+                let value = self.pop_real()?;
+                let result = match operator {
+                    Operator::Ln => value.ln(),
+                    Operator::Log => value.log10(),
+                    _ => value.sqrt(),
+                };
+                if result.is_nan() {
+                    failure::bail!("{:?} of {} is undefined", operator, value);
+                }
+                self.push_real(result)
+            }
+            Operator::Ceiling => self.round(f64::ceil),
+            Operator::Floor => self.round(f64::floor),
+            // PostScript rounds halves up, towards positive infinity
+            Operator::Round => self.round(|value| (value + 0.5).floor()),
+            Operator::Truncate => self.round(f64::trunc),
+            Operator::Cvi => {
+                let value = self.pop_real()?.trunc();
+                if value < i64::MIN as f64 || value > i64::MAX as f64 {
+                    failure::bail!("cvi result out of range");
+                }
+                self.push(Value::Integer(value as i64))
+            }
+            Operator::Cvr => {
+                let value = self.pop_real()?;
+                self.push_real(value)
+            }
+            Operator::And => self.bitwise(|a, b| a & b, |a, b| a && b),
+            Operator::Or => self.bitwise(|a, b| a | b, |a, b| a || b),
+            Operator::Xor => self.bitwise(|a, b| a ^ b, |a, b| a != b),
+            Operator::Not => match self.pop()? {
+                Value::Boolean(value) => self.push(Value::Boolean(!value)),
+                value => self.push(Value::Integer(!value.to_integer()?)),
+            },
+            Operator::Bitshift => {
+                let shift = self.pop_integer()?;
+                let value = self.pop_integer()?;
+                let result = match shift {
+                    0..=63 => value << shift,
+                    -63..=-1 => ((value as u64) >> -shift) as i64,
+                    _ => 0,
+                };
+                self.push(Value::Integer(result))
+            }
+            Operator::Eq | Operator::Ne => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                let equal = match (a, b) {
+                    (Value::Boolean(a), Value::Boolean(b)) => a == b,
+                    (Value::Boolean(_), _) | (_, Value::Boolean(_)) => false,
+                    (a, b) => a.to_real()? == b.to_real()?,
+                };
+                self.push(Value::Boolean(match operator {
+                    Operator::Eq => equal,
+                    _ => !equal,
+                }))
+            }
+            Operator::Ge => self.compare(|ordering| ordering != std::cmp::Ordering::Less),
+            Operator::Gt => self.compare(|ordering| ordering == std::cmp::Ordering::Greater),
+            Operator::Le => self.compare(|ordering| ordering != std::cmp::Ordering::Greater),
+            Operator::Lt => self.compare(|ordering| ordering == std::cmp::Ordering::Less),
+            Operator::Copy => {
+                let count = self.pop_integer()?;
+                if count > 0 {
+                    let start = self.index_from_top(count - 1)?;
+                    for index in start..start + count as usize {
+                        self.push(self.values[index])?;
+                    }
+                } else if count < 0 {
+                    failure::bail!("negative copy count");
+                }
+                Ok(())
+            }
+            Operator::Dup => {
+                let value = *self
+                    .values
+                    .last()
+                    .ok_or_else(|| failure::format_err!("calculator stack underflow"))?;
+                self.push(value)
+            }
+            Operator::Exch => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push(b)?;
+                self.push(a)
+            }
+            Operator::Index => {
+                let depth = self.pop_integer()?;
+                let index = self.index_from_top(depth)?;
+                self.push(self.values[index])
+            }
+            Operator::Pop => self.pop().map(|_| ()),
+            Operator::Roll => {
+                let shift = self.pop_integer()?;
+                let count = self.pop_integer()?;
+                if count < 0 {
+                    failure::bail!("negative roll count");
+                }
+                if count > 0 {
+                    let start = self.index_from_top(count - 1)?;
+                    let shift = shift.rem_euclid(count) as usize;
+                    self.values[start..].rotate_right(shift);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl CalculatorFunction {
+    pub fn parse(data: &[u8]) -> Fallible<Self> {
+        let mut tokens = tokenize(data).into_iter();
+        match tokens.next() {
+            Some(ref token) if token == "{" => {}
+            _ => failure::bail!("calculator function does not start with a procedure"),
+        }
+        let program = parse_procedure(&mut tokens, 0)?;
+        if tokens.next().is_some() {
+            failure::bail!("trailing data after calculator function");
+        }
+        Ok(CalculatorFunction { program })
+    }
+
+    /// Runs the program with the inputs on the stack, returning the `outputs` values
+    /// left on top of it.
+    pub fn evaluate(&self, input: &[f32], outputs: usize) -> Fallible<Vec<f32>> {
+        let mut stack = Stack {
+            values: input.iter().map(|x| Value::Real(f64::from(*x))).collect(),
+        };
+        stack.execute(&self.program)?;
+        if stack.values.len() < outputs {
+            failure::bail!("calculator function produced too few outputs");
+        }
+        stack.values[stack.values.len() - outputs..]
+            .iter()
+            .map(|value| value.to_real().map(|value| value as f32))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(program: &str, input: &[f32], outputs: usize) -> Fallible<Vec<f32>> {
+        CalculatorFunction::parse(program.as_bytes())?.evaluate(input, outputs)
+    }
+
+    #[test]
+    fn operators() {
+        let cases: &[(&str, &[f32], &[f32])] = &[
+            ("{ add }", &[1.0, 2.5], &[3.5]),
+            ("{ sub }", &[1.0, 2.5], &[-1.5]),
+            ("{ mul 0.5 mul }", &[3.0, 4.0], &[6.0]),
+            ("{ div }", &[1.0, 4.0], &[0.25]),
+            ("{ cvi 7 exch idiv }", &[2.0], &[3.0]),
+            ("{ cvi -7 exch mod }", &[3.0], &[-1.0]),
+            ("{ neg abs }", &[2.0], &[2.0]),
+            ("{ dup floor exch ceiling }", &[1.5], &[1.0, 2.0]),
+            ("{ round }", &[-2.5], &[-2.0]),
+            ("{ truncate }", &[-2.7], &[-2.0]),
+            ("{ sqrt }", &[16.0], &[4.0]),
+            ("{ 2 exch exp }", &[3.0], &[8.0]),
+            ("{ log }", &[100.0], &[2.0]),
+            ("{ 1 atan }", &[-1.0], &[315.0]),
+            ("{ sin }", &[90.0], &[1.0]),
+            ("{ cos }", &[180.0], &[-1.0]),
+            ("{ cvi 3 bitshift }", &[1.0], &[8.0]),
+            ("{ cvi -1 bitshift }", &[6.0], &[3.0]),
+            ("{ cvi 5 and 2 or 1 xor }", &[7.0], &[6.0]),
+            ("{ 1 2 3 3 1 roll }", &[], &[3.0, 1.0, 2.0]),
+            ("{ 1 2 3 2 copy }", &[], &[1.0, 2.0, 3.0, 2.0, 3.0]),
+            ("{ 1 2 3 2 index }", &[], &[1.0, 2.0, 3.0, 1.0]),
+            ("{ 1 2 pop }", &[], &[1.0]),
+        ];
+        for (program, input, output) in cases {
+            let result = evaluate(program, input, output.len()).unwrap();
+            for (actual, expected) in result.iter().zip(output.iter()) {
+                assert!(
+                    (actual - expected).abs() < 1e-5,
+                    "{} with {:?} gave {:?}",
+                    program,
+                    input,
+                    result
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn conditionals() {
+        let program = "{ dup 0.5 gt { pop 1 } { 0.5 lt { 0 } { 0.5 } ifelse } ifelse }";
+        assert_eq!(vec![1.0], evaluate(program, &[0.7], 1).unwrap());
+        assert_eq!(vec![0.0], evaluate(program, &[0.2], 1).unwrap());
+        assert_eq!(vec![0.5], evaluate(program, &[0.5], 1).unwrap());
+
+        let program = "{ dup 0 eq { pop 2 } if true not { 3 } if }";
+        assert_eq!(vec![2.0], evaluate(program, &[0.0], 1).unwrap());
+        assert_eq!(vec![4.0], evaluate(program, &[4.0], 1).unwrap());
+    }
+
+    #[test]
+    fn invalid_programs() {
+        assert!(CalculatorFunction::parse(b"add").is_err());
+        assert!(CalculatorFunction::parse(b"{ 1 2 add").is_err());
+        assert!(CalculatorFunction::parse(b"{ { 1 } }").is_err());
+        assert!(CalculatorFunction::parse(b"{ foo }").is_err());
+        assert!(evaluate("{ add }", &[1.0], 1).is_err());
+        assert!(evaluate("{ 0 div }", &[1.0], 1).is_err());
+        assert!(evaluate("{ pop }", &[1.0], 1).is_err());
+        assert!(evaluate("{ 1 2 eq }", &[], 1).is_err());
+
+        let mut nested = String::from("{ true ");
+        for _ in 0..1000 {
+            nested.push_str("{ true ");
+        }
+        for _ in 0..1000 {
+            nested.push_str("} if ");
+        }
+        nested.push('}');
+        assert!(CalculatorFunction::parse(nested.as_bytes()).is_err());
+    }
+}
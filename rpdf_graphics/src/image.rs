@@ -0,0 +1,270 @@
+use std::sync::Arc;
+
+use failure::Fallible;
+use serde_derive::Deserialize;
+
+use rpdf_lopdf_extra::*;
+
+use crate::clip::Clip;
+use crate::colorspace::ColorSpace;
+use crate::data::BitReader;
+use crate::filter;
+use crate::resources::Resources;
+use crate::transparency::TransparencyState;
+use crate::GraphicsState;
+
+/// Limits the number of pixels of an image, whose samples are expanded to 4 bytes each.
+const MAX_PIXELS: u64 = 1 << 26;
+
+#[derive(Deserialize)]
+struct ImageDictionary {
+    #[serde(rename = "Width")]
+    width: u32,
+    #[serde(rename = "Height")]
+    height: u32,
+    #[serde(rename = "BitsPerComponent")]
+    bits_per_component: Option<u32>,
+    #[serde(rename = "ImageMask")]
+    image_mask: Option<bool>,
+    #[serde(rename = "Decode")]
+    decode: Option<Vec<f32>>,
+}
+
+impl ImageDictionary {
+    fn bits_per_component(&self) -> Fallible<u32> {
+        let bits = if self.image_mask == Some(true) {
+            1
+        } else {
+            self.bits_per_component.unwrap_or(8)
+        };
+        match bits {
+            1 | 2 | 4 | 8 | 16 => Ok(bits),
+            _ => failure::bail!("unsupported bits per component {}", bits),
+        }
+    }
+
+    fn pixel_count(&self) -> Fallible<usize> {
+        let count = u64::from(self.width) * u64::from(self.height);
+        if count > MAX_PIXELS {
+            failure::bail!(
+                "image of {}x{} pixels is too large",
+                self.width,
+                self.height
+            );
+        }
+        Ok(count as usize)
+    }
+}
+
+/// Calls `f` with the raw samples of each pixel, row by row from the top of the image.
+fn for_each_pixel<F>(data: &[u8], width: u32, height: u32, components: usize, bits: u32, mut f: F)
+where
+    F: FnMut(&[u32]),
+{
+    let mut reader = BitReader::new(data);
+    let mut samples = vec![0; components];
+    for _ in 0..height {
+        for _ in 0..width {
+            for sample in samples.iter_mut() {
+                *sample = reader.read(bits).unwrap_or(0);
+            }
+            f(&samples);
+        }
+        // rows always start at a byte boundary
+        reader.align();
+    }
+}
+
+/// Maps raw samples to colour component values through a `Decode` array.
+struct Decoder {
+    ranges: Vec<(f32, f32)>,
+    max_sample: f32,
+}
+
+impl Decoder {
+    fn new(decode: &[f32], bits: u32) -> Self {
+        Decoder {
+            ranges: decode
+                .chunks(2)
+                .filter(|range| range.len() == 2)
+                .map(|range| (range[0], range[1]))
+                .collect(),
+            max_sample: ((1u32 << bits) - 1) as f32,
+        }
+    }
+
+    fn decode(&self, index: usize, sample: u32) -> f32 {
+        let (min, max) = self.ranges.get(index).cloned().unwrap_or((0.0, 1.0));
+        min + sample as f32 * (max - min) / self.max_sample
+    }
+}
+
+fn read_color_space(resources: &Resources, obj: &lopdf::Object) -> Fallible<Arc<ColorSpace>> {
+    match resources.document().resolve_object(obj)? {
+        lopdf::Object::Name(ref name) => resources.color_space(name),
+        obj => Ok(Arc::new(ColorSpace::try_from_object(
+            resources.document(),
+            obj,
+        )?)),
+    }
+}
+
+/// A sampled image, painted into the unit square of user space.
+pub struct ImageObject {
+    pub transform: euclid::Transform2D<f32>,
+    pub width: u32,
+    pub height: u32,
+    /// Non-premultiplied RGBA samples, row by row starting at the top of the image.
+    pub pixels: Vec<u8>,
+    pub transparency: TransparencyState,
+    pub clip: Clip,
+}
+
+impl ImageObject {
+    /// Decodes an image XObject or inline image, given as its resolved stream object.
+    pub fn try_from_xobject(
+        resources: &Resources,
+        obj: &lopdf::Object,
+        state: &GraphicsState,
+    ) -> Fallible<Self> {
+        let stream = obj.try_as_stream()?;
+        let data = filter::decode(resources.document(), &stream.dict, &stream.content)?;
+        Self::try_from_data(resources, obj, &stream.dict, &data, state)
+    }
+
+    fn try_from_data(
+        resources: &Resources,
+        obj: &lopdf::Object,
+        dict: &lopdf::Dictionary,
+        data: &[u8],
+        state: &GraphicsState,
+    ) -> Fallible<Self> {
+        let document = resources.document();
+        let image_dict: ImageDictionary = document.deserialize_object(obj)?;
+        let bits = image_dict.bits_per_component()?;
+        let (width, height) = (image_dict.width, image_dict.height);
+        let mut pixels = Vec::with_capacity(image_dict.pixel_count()? * 4);
+
+        if image_dict.image_mask == Some(true) {
+            // stencil masks paint the current fill colour wherever the decoded sample is 0
+            let rgb = state.color_state.fill.to_rgb();
+            let color = [
+                (rgb.red * 255.0).round() as u8,
+                (rgb.green * 255.0).round() as u8,
+                (rgb.blue * 255.0).round() as u8,
+            ];
+            let decoder = Decoder::new(image_dict.decode.as_ref().map_or(&[], |d| d), bits);
+            for_each_pixel(data, width, height, 1, bits, |samples| {
+                pixels.extend_from_slice(&color);
+                pixels.push(if decoder.decode(0, samples[0]) < 0.5 {
+                    255
+                } else {
+                    0
+                });
+            });
+        } else {
+            let space = match dict.get(b"ColorSpace") {
+                Some(space_obj) => read_color_space(resources, space_obj)?,
+                None => failure::bail!("image is missing a colour space"),
+            };
+            let components = space.components();
+            let decode = image_dict.decode.clone().unwrap_or_else(|| match *space {
+                ColorSpace::Indexed { .. } => vec![0.0, ((1u32 << bits) - 1) as f32],
+                _ => (0..components)
+                    .flat_map(|index| {
+                        let (min, max) = space.component_range(index);
+                        vec![min, max]
+                    })
+                    .collect(),
+            });
+            let decoder = Decoder::new(&decode, bits);
+
+            // images usually have long runs of identical pixels, so remember the last one
+            let mut values = vec![0.0; components];
+            let mut last: Option<(Vec<u32>, [u8; 3])> = None;
+            for_each_pixel(data, width, height, components, bits, |samples| {
+                let color = match last {
+                    Some((ref last_samples, color)) if last_samples.as_slice() == samples => color,
+                    _ => {
+                        for (index, value) in values.iter_mut().enumerate() {
+                            *value = decoder.decode(index, samples[index]);
+                        }
+                        let rgb = space.to_rgb(&values);
+                        let color = [
+                            (rgb.red * 255.0).round() as u8,
+                            (rgb.green * 255.0).round() as u8,
+                            (rgb.blue * 255.0).round() as u8,
+                        ];
+                        last = Some((samples.to_owned(), color));
+                        color
+                    }
+                };
+                pixels.extend_from_slice(&color);
+                pixels.push(255);
+            });
+
+            if let Some(smask_obj) = dict.get(b"SMask") {
+                let smask_obj = document.resolve_object(smask_obj)?;
+                if let lopdf::Object::Stream(ref smask) = smask_obj {
+                    let smask_data = filter::decode(document, &smask.dict, &smask.content)?;
+                    Self::apply_soft_mask(
+                        document,
+                        smask_obj,
+                        &smask_data,
+                        width,
+                        height,
+                        &mut pixels,
+                    )?;
+                }
+            }
+        }
+
+        Ok(ImageObject {
+            transform: state.transform,
+            width,
+            height,
+            pixels,
+            transparency: state.transparency.clone(),
+            clip: state.clip.clone(),
+        })
+    }
+
+    /// Replaces the alpha channel with the samples of a soft mask image, which may have
+    /// a different resolution than the image itself.
+    fn apply_soft_mask(
+        document: &lopdf::Document,
+        obj: &lopdf::Object,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        pixels: &mut [u8],
+    ) -> Fallible<()> {
+        let mask_dict: ImageDictionary = document.deserialize_object(obj)?;
+        let bits = mask_dict.bits_per_component()?;
+        let decoder = Decoder::new(mask_dict.decode.as_ref().map_or(&[], |d| d), bits);
+        let mut alpha = Vec::with_capacity(mask_dict.pixel_count()?);
+        for_each_pixel(
+            data,
+            mask_dict.width,
+            mask_dict.height,
+            1,
+            bits,
+            |samples| {
+                alpha.push((decoder.decode(0, samples[0]).clamp(0.0, 1.0) * 255.0).round() as u8);
+            },
+        );
+        if alpha.is_empty() {
+            return Ok(());
+        }
+
+        for y in 0..height {
+            let mask_y = (y as u64 * u64::from(mask_dict.height) / u64::from(height)) as usize;
+            for x in 0..width {
+                let mask_x = (x as u64 * u64::from(mask_dict.width) / u64::from(width)) as usize;
+                let index = (y * width + x) as usize * 4 + 3;
+                pixels[index] = alpha[mask_y * mask_dict.width as usize + mask_x];
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,87 +1,923 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
 use std::sync::Arc;
 
 use failure::Fallible;
+use serde_derive::Deserialize;
 
+use rpdf_lopdf_extra::*;
+
+pub mod clip;
+pub mod color;
+pub mod colorspace;
+pub mod content;
 pub mod data;
+pub mod extgstate;
+pub mod filter;
 pub mod font;
+pub mod function;
+pub mod image;
+pub mod line;
+pub mod path;
+pub mod pattern;
+pub mod resources;
+pub mod shading;
 pub mod text;
+pub mod transparency;
 
 const OP_BEGIN_TEXT_OBJECT: &str = "BT";
 const OP_END_TEXT_OBJECT: &str = "ET";
 
+/// Maximum nesting depth of form XObjects, as a safeguard against pathological files.
+const MAX_FORM_DEPTH: usize = 32;
+
+/// Maximum number of forms, patterns and glyph descriptions executed for a page, which
+/// limits forms that invoke other forms several times from growing exponentially.
+const MAX_FORM_EXECUTIONS: usize = 100_000;
+
 pub enum GraphicsObject {
     Text(text::TextObject),
+    Path(path::PathObject),
+    Image(image::ImageObject),
+    Group(transparency::GroupObject),
+    Shading(shading::ShadingObject),
+    Tiling(pattern::TilingObject),
 }
 
+#[derive(Clone)]
 pub struct GraphicsState {
     transform: euclid::Transform2D<f32>,
+    clip: clip::Clip,
+    color_state: color::ColorState,
+    line_style: line::LineStyle,
     text_state: text::TextState,
+    transparency: transparency::TransparencyState,
 }
 
 impl Default for GraphicsState {
     fn default() -> Self {
         GraphicsState {
             transform: euclid::Transform2D::identity(),
+            clip: clip::Clip::default(),
+            color_state: color::ColorState::default(),
+            line_style: line::LineStyle::default(),
             text_state: text::TextState::default(),
+            transparency: transparency::TransparencyState::default(),
         }
     }
 }
 
-enum GraphicsObjectBuilder<'a> {
-    Text(text::TextObjectBuilder<'a>),
+enum GraphicsObjectBuilder {
+    Text(text::TextObjectBuilder),
+    Path(path::PathObjectBuilder),
+}
+
+#[derive(Deserialize)]
+struct FormDictionary {
+    #[serde(rename = "BBox")]
+    bbox: [f32; 4],
+    #[serde(rename = "Matrix")]
+    matrix: Option<[f32; 6]>,
+}
+
+#[derive(Deserialize)]
+struct GroupDictionary {
+    #[serde(rename = "S")]
+    subtype: data::Name,
+    #[serde(rename = "I")]
+    isolated: Option<bool>,
+    #[serde(rename = "K")]
+    knockout: Option<bool>,
 }
 
-pub struct GraphicsObjectDecoder<'a> {
+/// State shared by the decoder of a page's content stream and the decoders of the forms,
+/// patterns and glyph descriptions it executes.
+struct DecoderContext {
+    /// Resources of the content streams executed so far and their fonts, by the address
+    /// of their resource dictionary, so that the fonts of a form drawn several times are
+    /// only loaded once. The dictionaries outlive the decoders, so addresses aren't reused.
+    resources: RefCell<HashMap<*const lopdf::Dictionary, ContentResources>>,
+    /// The number of forms, patterns and glyph descriptions that can still be executed.
+    remaining_forms: Cell<usize>,
+    /// Soft masks decoded so far, by the address of their dictionary, together with the
+    /// transformation matrix they were painted with.
+    soft_masks: RefCell<HashMap<*const lopdf::Dictionary, SoftMaskEntry>>,
+}
+
+type SoftMaskEntry = (
+    euclid::Transform2D<f32>,
+    Option<Arc<transparency::SoftMask>>,
+);
+
+type ContentResources = (Rc<resources::Resources>, Rc<font::FontMap>);
+
+pub struct GraphicsObjectDecoder {
     document: Arc<lopdf::Document>,
-    font_map: &'a font::FontMap,
+    resources: Rc<resources::Resources>,
+    font_map: Rc<font::FontMap>,
     operations: std::vec::IntoIter<lopdf::content::Operation>,
     state: GraphicsState,
-    builder: Option<GraphicsObjectBuilder<'a>>,
+    state_stack: Vec<GraphicsState>,
+    builder: Option<GraphicsObjectBuilder>,
+    /// Objects produced together with the last one returned, such as the stroke of a
+    /// path filled with a pattern.
+    pending: VecDeque<GraphicsObject>,
+    /// The default coordinate space of the content stream, which patterns refer to.
+    base_transform: euclid::Transform2D<f32>,
+    /// The form XObject currently being executed by a `Do` operator.
+    form: Option<Box<GraphicsObjectDecoder>>,
+    /// Object ids of the forms and tiling patterns enclosing this content stream,
+    /// including itself.
+    form_ids: Vec<lopdf::ObjectId>,
+    context: Rc<DecoderContext>,
+    /// Whether the content stream describes a Type 3 glyph declared with `d1`, which is
+    /// painted in the colour of the text and ignores colour operators.
+    uncolored_glyph: bool,
 }
 
-impl<'a> GraphicsObjectDecoder<'a> {
+impl GraphicsObjectDecoder {
     pub fn decode(
         document: Arc<lopdf::Document>,
-        font_map: &'a font::FontMap,
+        resources: Rc<resources::Resources>,
+        font_map: Rc<font::FontMap>,
         data: &[u8],
     ) -> Fallible<Self> {
-        let content = lopdf::content::Content::decode(data)?;
+        Self::decode_with_state(
+            document,
+            resources,
+            font_map,
+            data,
+            GraphicsState::default(),
+            Vec::new(),
+            Rc::new(DecoderContext {
+                resources: RefCell::new(HashMap::new()),
+                remaining_forms: Cell::new(MAX_FORM_EXECUTIONS),
+                soft_masks: RefCell::new(HashMap::new()),
+            }),
+        )
+    }
+
+    fn decode_with_state(
+        document: Arc<lopdf::Document>,
+        resources: Rc<resources::Resources>,
+        font_map: Rc<font::FontMap>,
+        data: &[u8],
+        state: GraphicsState,
+        form_ids: Vec<lopdf::ObjectId>,
+        context: Rc<DecoderContext>,
+    ) -> Fallible<Self> {
+        let operations = content::decode(data)?;
         Ok(Self {
             document,
+            resources,
             font_map,
-            operations: content.operations.into_iter(),
-            state: GraphicsState::default(),
+            operations: operations.into_iter(),
+            base_transform: state.transform,
+            state,
+            state_stack: Vec::new(),
             builder: None,
+            pending: VecDeque::new(),
+            uncolored_glyph: false,
+            form: None,
+            form_ids,
+            context,
         })
     }
 
+    fn handle_graphics_state_operation(&mut self, op: &lopdf::content::Operation) -> Fallible<()> {
+        match op.operator.as_str() {
+            "q" => {
+                self.state_stack.push(self.state.clone());
+            }
+            "Q" => {
+                if let Some(state) = self.state_stack.pop() {
+                    self.state = state;
+                } else {
+                    log::warn!("graphics state stack underflow");
+                }
+            }
+            "cm" => {
+                let a = self.document.deserialize_object(&op.operands[0])?;
+                let b = self.document.deserialize_object(&op.operands[1])?;
+                let c = self.document.deserialize_object(&op.operands[2])?;
+                let d = self.document.deserialize_object(&op.operands[3])?;
+                let e = self.document.deserialize_object(&op.operands[4])?;
+                let f = self.document.deserialize_object(&op.operands[5])?;
+                let matrix = euclid::Transform2D::row_major(a, b, c, d, e, f);
+                self.state.transform = self.state.transform.pre_mul(&matrix);
+            }
+            "gs" => {
+                let data::Name(name) = self.document.deserialize_object(&op.operands[0])?;
+                let dict = match self.resources.get(b"ExtGState", &name) {
+                    Some(obj) => match obj.try_as_dict() {
+                        Ok(dict) => dict,
+                        Err(err) => {
+                            log::warn!(
+                                "ignoring ExtGState /{}: {}",
+                                String::from_utf8_lossy(&name),
+                                err
+                            );
+                            return Ok(());
+                        }
+                    },
+                    None => {
+                        log::warn!("ExtGState /{} not found", String::from_utf8_lossy(&name));
+                        return Ok(());
+                    }
+                };
+                extgstate::apply(&self.resources, dict, &mut self.state);
+                if let Some(soft_mask) = dict.get(b"SMask") {
+                    self.state.transparency.soft_mask = match self.decode_soft_mask(soft_mask) {
+                        Ok(soft_mask) => soft_mask,
+                        Err(err) => {
+                            log::warn!(
+                                "ignoring soft mask of ExtGState /{}: {}",
+                                String::from_utf8_lossy(&name),
+                                err
+                            );
+                            None
+                        }
+                    };
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_xobject_operation(
+        &mut self,
+        op: &lopdf::content::Operation,
+    ) -> Fallible<Option<GraphicsObject>> {
+        let data::Name(name) = self.document.deserialize_object(&op.operands[0])?;
+        // the XObject stays borrowed while a form replaces the decoder's current stream
+        let resources = self.resources.clone();
+        let xobject = match resources.get(b"XObject", &name) {
+            Some(xobject) => xobject,
+            None => {
+                log::warn!("XObject /{} not found", String::from_utf8_lossy(&name));
+                return Ok(None);
+            }
+        };
+        let subtype = xobject
+            .as_stream()
+            .and_then(|stream| stream.dict.get(b"Subtype"))
+            .and_then(|subtype| self.document.deserialize_object(subtype).ok());
+        let data::Name(subtype) = match subtype {
+            Some(subtype) => subtype,
+            None => {
+                log::warn!(
+                    "skipping XObject /{} without a subtype",
+                    String::from_utf8_lossy(&name)
+                );
+                return Ok(None);
+            }
+        };
+        match subtype.as_slice() {
+            b"Image" => {
+                match image::ImageObject::try_from_xobject(&self.resources, xobject, &self.state) {
+                    Ok(image_object) => Ok(Some(GraphicsObject::Image(image_object))),
+                    Err(err) => {
+                        log::warn!(
+                            "skipping image /{}: {}",
+                            String::from_utf8_lossy(&name),
+                            err
+                        );
+                        Ok(None)
+                    }
+                }
+            }
+            b"Form" => {
+                let id = self.resources.get_id(b"XObject", &name);
+                if let Err(err) = self.check_form(id) {
+                    log::warn!(
+                        "skipping form XObject /{}: {}",
+                        String::from_utf8_lossy(&name),
+                        err
+                    );
+                    return Ok(None);
+                }
+                match self.decode_form_xobject(xobject, id) {
+                    Ok(graphics_object) => Ok(graphics_object),
+                    Err(err) => {
+                        log::warn!(
+                            "skipping form XObject /{}: {}",
+                            String::from_utf8_lossy(&name),
+                            err
+                        );
+                        Ok(None)
+                    }
+                }
+            }
+            _ => {
+                log::warn!(
+                    "unsupported XObject subtype {}",
+                    String::from_utf8_lossy(&subtype)
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Decodes a form XObject, either as a transparency group or by starting to decode
+    /// its content stream in place of the current one.
+    fn decode_form_xobject(
+        &mut self,
+        xobject: &lopdf::Object,
+        id: Option<lopdf::ObjectId>,
+    ) -> Fallible<Option<GraphicsObject>> {
+        match self.group_dictionary(xobject)? {
+            Some(group_dict) => {
+                let group = self.decode_group(xobject, id, self.state.clone(), &group_dict)?;
+                Ok(Some(GraphicsObject::Group(group)))
+            }
+            None => {
+                self.form = Some(Box::new(
+                    self.decode_form(xobject, id, self.state.clone())?.0,
+                ));
+                Ok(None)
+            }
+        }
+    }
+
+    fn handle_shading_operation(
+        &self,
+        op: &lopdf::content::Operation,
+    ) -> Fallible<Option<GraphicsObject>> {
+        let data::Name(name) = self.document.deserialize_object(&op.operands[0])?;
+        let shading = match self.resources.shading(&name) {
+            Ok(shading) => shading,
+            Err(err) => {
+                log::warn!(
+                    "skipping shading /{}: {}",
+                    String::from_utf8_lossy(&name),
+                    err
+                );
+                return Ok(None);
+            }
+        };
+        let mut clip = self.state.clip.clone();
+        if let Some(bbox) = shading.bbox {
+            clip.intersect(clip::ClipPath {
+                transform: self.state.transform,
+                path: path::Path::from_rect(&bbox),
+                fill_rule: path::FillRule::NonZero,
+            });
+        }
+        Ok(Some(GraphicsObject::Shading(shading::ShadingObject {
+            transform: self.state.transform,
+            shading,
+            background: None,
+            transparency: self.state.transparency.clone(),
+            clip,
+        })))
+    }
+
+    /// Takes over the fill of a path painted with a pattern, returning the shading or
+    /// the tiled cell clipped to the path in its place.
+    fn fill_with_pattern(&self, path_object: &mut path::PathObject) -> Option<GraphicsObject> {
+        if path_object.stroke && self.state.color_state.stroke.pattern().is_some() {
+            log::warn!("stroking paths with patterns is not supported");
+        }
+        let fill_rule = path_object.fill?;
+        let name = self.state.color_state.fill.pattern()?;
+        let pattern = match self.resources.pattern(name) {
+            Ok(pattern) => pattern,
+            Err(err) => {
+                log::warn!(
+                    "unsupported pattern /{}: {}",
+                    String::from_utf8_lossy(name),
+                    err
+                );
+                return None;
+            }
+        };
+        let transform = pattern.matrix.post_mul(&self.base_transform);
+        let mut clip = path_object.clip.clone();
+        clip.intersect(clip::ClipPath {
+            transform: path_object.transform,
+            path: path_object.path.clone(),
+            fill_rule,
+        });
+        match pattern.kind {
+            pattern::PatternKind::Shading(ref shading) => {
+                if let Some(bbox) = shading.bbox {
+                    clip.intersect(clip::ClipPath {
+                        transform,
+                        path: path::Path::from_rect(&bbox),
+                        fill_rule: path::FillRule::NonZero,
+                    });
+                }
+                path_object.fill = None;
+                Some(GraphicsObject::Shading(shading::ShadingObject {
+                    transform,
+                    shading: shading.clone(),
+                    background: shading.background,
+                    transparency: path_object.transparency.clone(),
+                    clip,
+                }))
+            }
+            pattern::PatternKind::Tiling(ref tiling) => {
+                let id = self.resources.get_id(b"Pattern", name);
+                let objects = match self.decode_tiling(tiling, id) {
+                    Ok(objects) => objects,
+                    Err(err) => {
+                        log::warn!(
+                            "skipping pattern /{}: {}",
+                            String::from_utf8_lossy(name),
+                            err
+                        );
+                        return None;
+                    }
+                };
+                path_object.fill = None;
+                Some(GraphicsObject::Tiling(pattern::TilingObject {
+                    objects,
+                    transform,
+                    bbox: tiling.bbox,
+                    x_step: tiling.x_step,
+                    y_step: tiling.y_step,
+                    transparency: path_object.transparency.clone(),
+                    clip,
+                }))
+            }
+        }
+    }
+
+    /// Executes the content stream of a tiling pattern, returning the objects of a
+    /// single cell in pattern space.
+    fn decode_tiling(
+        &self,
+        tiling: &pattern::TilingPattern,
+        id: Option<lopdf::ObjectId>,
+    ) -> Fallible<Vec<GraphicsObject>> {
+        self.check_form(id)?;
+        let mut state = GraphicsState::default();
+        state.clip.intersect(clip::ClipPath {
+            transform: state.transform,
+            path: path::Path::from_rect(&tiling.bbox),
+            fill_rule: path::FillRule::NonZero,
+        });
+        // uncoloured patterns are painted in the colour given along with the pattern name
+        if !tiling.colored {
+            let fill = &self.state.color_state.fill;
+            let underlying = match **fill.space() {
+                colorspace::ColorSpace::Pattern {
+                    underlying: Some(ref underlying),
+                } => underlying.clone(),
+                _ => failure::bail!("uncoloured pattern without an underlying colour space"),
+            };
+            let color = color::Color::from_components(underlying, fill.components().to_vec());
+            state.color_state.fill = color.clone();
+            state.color_state.stroke = color;
+        }
+
+        let (resources, font_map) = self.content_resources(tiling.resources.as_ref())?;
+        let mut form_ids = self.form_ids.clone();
+        form_ids.extend(id);
+        Self::decode_with_state(
+            self.document.clone(),
+            resources,
+            font_map,
+            &tiling.content,
+            state,
+            form_ids,
+            self.context.clone(),
+        )?
+        .collect()
+    }
+
+    /// Executes the glyph descriptions of text shown with a Type 3 font, returning the
+    /// objects they paint.
+    fn decode_type3_fragment(
+        &self,
+        fragment: &text::TextFragment,
+    ) -> Fallible<Vec<GraphicsObject>> {
+        let glyphs = match fragment.font.type3_glyphs() {
+            Some(glyphs) if fragment.render_mode.is_visible() => glyphs,
+            _ => return Ok(Vec::new()),
+        };
+        let (resources, font_map) = self.content_resources(glyphs.resources())?;
+        // glyph space is scaled by the font matrix, font size and horizontal scaling, and
+        // rotated and skewed along with the text before being placed at each glyph origin
+        let m = &fragment.transform;
+        let orientation = euclid::Transform2D::row_major(m.m11, m.m12, m.m21, m.m22, 0.0, 0.0);
+
+        let mut objects = Vec::new();
+        for glyph in &fragment.glyphs {
+            let glyph_name = match fragment.font.decode_char(glyph.index as u8) {
+                Some(glyph_name) => glyph_name,
+                None => continue,
+            };
+            let proc_obj = match glyphs.char_proc(glyph_name.as_bytes()) {
+                Some(proc_obj) => proc_obj,
+                None => continue,
+            };
+            let id = match *proc_obj {
+                lopdf::Object::Reference(id) => Some(id),
+                _ => None,
+            };
+            if let Err(err) = self.check_form(id) {
+                log::warn!("skipping glyph description: {}", err);
+                continue;
+            }
+            let stream = self.document.resolve_object(proc_obj)?.try_as_stream()?;
+            let content = filter::decode(&self.document, &stream.dict, &stream.content)?;
+            let translation =
+                euclid::Transform2D::create_translation(glyph.origin.x, glyph.origin.y);
+            let glyph_transform = glyphs
+                .matrix()
+                .post_scale(
+                    fragment.font_size * glyph.horizontal_scale,
+                    fragment.font_size,
+                )
+                .post_mul(&orientation)
+                .post_mul(&translation);
+            let state = GraphicsState {
+                transform: glyph_transform,
+                ..self.state.clone()
+            };
+            let mut form_ids = self.form_ids.clone();
+            form_ids.extend(id);
+            let decoder = Self::decode_with_state(
+                self.document.clone(),
+                resources.clone(),
+                font_map.clone(),
+                &content,
+                state,
+                form_ids,
+                self.context.clone(),
+            )?;
+            for graphics_object in decoder {
+                match graphics_object {
+                    Ok(graphics_object) => objects.push(graphics_object),
+                    Err(err) => {
+                        log::warn!("skipping rest of glyph description: {}", err);
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(objects)
+    }
+
+    /// Checks that a form XObject, pattern or glyph description can be executed without
+    /// recursing endlessly or exceeding the page's budget, and takes it from the budget.
+    fn check_form(&self, id: Option<lopdf::ObjectId>) -> Fallible<()> {
+        if id.is_some_and(|id| self.form_ids.contains(&id)) {
+            failure::bail!("it is invoked recursively");
+        }
+        if self.form_ids.len() >= MAX_FORM_DEPTH {
+            failure::bail!("forms are nested too deeply");
+        }
+        let remaining = self.context.remaining_forms.get();
+        if remaining == 0 {
+            failure::bail!("too many forms have been executed for the page");
+        }
+        self.context.remaining_forms.set(remaining - 1);
+        Ok(())
+    }
+
+    /// Returns the group attributes of a form XObject, if it is a transparency group.
+    fn group_dictionary(&self, xobject: &lopdf::Object) -> Fallible<Option<GroupDictionary>> {
+        let group_dict: GroupDictionary = match xobject.try_as_stream()?.dict.get(b"Group") {
+            Some(obj) => self.document.deserialize_object(obj)?,
+            None => return Ok(None),
+        };
+        if group_dict.subtype.0 == b"Transparency" {
+            Ok(Some(group_dict))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the resources of a form or pattern content stream, given its `Resources`
+    /// entry.
+    fn content_resources(&self, obj: Option<&lopdf::Object>) -> Fallible<ContentResources> {
+        // content streams without their own resources use those of the page (deprecated,
+        // but common)
+        let dict = match obj {
+            Some(obj) => self.document.resolve_object(obj)?.try_as_dict()?,
+            None => return Ok((self.resources.clone(), self.font_map.clone())),
+        };
+        let key: *const lopdf::Dictionary = dict;
+        if let Some(content_resources) = self.context.resources.borrow().get(&key) {
+            return Ok(content_resources.clone());
+        }
+        let resources = resources::Resources::new(self.document.clone(), vec![dict.clone()]);
+        let font_map = font::FontMap::try_from_resources(&resources)?;
+        let content_resources = (Rc::new(resources), Rc::new(font_map));
+        self.context
+            .resources
+            .borrow_mut()
+            .insert(key, content_resources.clone());
+        Ok(content_resources)
+    }
+
+    /// Prepares a decoder for the content stream of a form XObject, which is executed
+    /// with the given graphics state. Also returns the form's bounding box.
+    fn decode_form(
+        &self,
+        xobject: &lopdf::Object,
+        id: Option<lopdf::ObjectId>,
+        mut state: GraphicsState,
+    ) -> Fallible<(GraphicsObjectDecoder, euclid::Rect<f32>)> {
+        let stream = xobject.try_as_stream()?;
+        let form_dict: FormDictionary = self.document.deserialize_object(xobject)?;
+
+        if let Some([a, b, c, d, e, f]) = form_dict.matrix {
+            let matrix = euclid::Transform2D::row_major(a, b, c, d, e, f);
+            state.transform = state.transform.pre_mul(&matrix);
+        }
+        let [x1, y1, x2, y2] = form_dict.bbox;
+        let bbox =
+            euclid::Rect::from_points([euclid::Point2D::new(x1, y1), euclid::Point2D::new(x2, y2)]);
+        state.clip.intersect(clip::ClipPath {
+            transform: state.transform,
+            path: path::Path::from_rect(&bbox),
+            fill_rule: path::FillRule::NonZero,
+        });
+
+        let (resources, font_map) = self.content_resources(stream.dict.get(b"Resources"))?;
+
+        let mut form_ids = self.form_ids.clone();
+        form_ids.extend(id);
+        let content = filter::decode(&self.document, &stream.dict, &stream.content)?;
+        let decoder = Self::decode_with_state(
+            self.document.clone(),
+            resources,
+            font_map,
+            &content,
+            state,
+            form_ids,
+            self.context.clone(),
+        )?;
+        Ok((decoder, bbox))
+    }
+
+    /// Executes a transparency group XObject, collecting its objects into a group that
+    /// is painted with the transparency parameters of `state`.
+    fn decode_group(
+        &self,
+        xobject: &lopdf::Object,
+        id: Option<lopdf::ObjectId>,
+        mut state: GraphicsState,
+        group_dict: &GroupDictionary,
+    ) -> Fallible<transparency::GroupObject> {
+        // the group's contents start out fully opaque, with no blending or soft mask
+        let transparency = std::mem::take(&mut state.transparency);
+        let clip = state.clip.clone();
+        let (mut decoder, bbox) = self.decode_form(xobject, id, state)?;
+        let transform = decoder.state.transform;
+        let objects = decoder.by_ref().collect::<Fallible<Vec<_>>>()?;
+        Ok(transparency::GroupObject {
+            objects,
+            transform,
+            bbox,
+            isolated: group_dict.isolated.unwrap_or(false),
+            knockout: group_dict.knockout.unwrap_or(false),
+            transparency,
+            clip,
+        })
+    }
+
+    /// Decodes the `SMask` entry of an ExtGState, painting its group with the current
+    /// transformation matrix. Masks are only decoded again when that matrix changes.
+    fn decode_soft_mask(
+        &self,
+        obj: &lopdf::Object,
+    ) -> Fallible<Option<Arc<transparency::SoftMask>>> {
+        let dict = match self.document.resolve_object(obj)? {
+            lopdf::Object::Name(ref name) if name == b"None" => return Ok(None),
+            lopdf::Object::Dictionary(ref dict) => dict,
+            obj => failure::bail!("invalid soft mask {:?}", obj),
+        };
+        let key: *const lopdf::Dictionary = dict;
+        if let Some((transform, soft_mask)) = self.context.soft_masks.borrow().get(&key) {
+            if *transform == self.state.transform {
+                return Ok(soft_mask.clone());
+            }
+        }
+        let soft_mask = self.decode_soft_mask_dictionary(dict)?;
+        self.context
+            .soft_masks
+            .borrow_mut()
+            .insert(key, (self.state.transform, soft_mask.clone()));
+        Ok(soft_mask)
+    }
+
+    fn decode_soft_mask_dictionary(
+        &self,
+        dict: &lopdf::Dictionary,
+    ) -> Fallible<Option<Arc<transparency::SoftMask>>> {
+        let data::Name(subtype) = self.document.deserialize_object(dict.try_get(b"S")?)?;
+        let subtype = match subtype.as_slice() {
+            b"Alpha" => transparency::SoftMaskType::Alpha,
+            b"Luminosity" => transparency::SoftMaskType::Luminosity,
+            _ => failure::bail!(
+                "invalid soft mask type {}",
+                String::from_utf8_lossy(&subtype)
+            ),
+        };
+
+        let group_obj = dict.try_get(b"G")?;
+        let id = match *group_obj {
+            lopdf::Object::Reference(id) => Some(id),
+            _ => None,
+        };
+        if let Err(err) = self.check_form(id) {
+            log::warn!("skipping soft mask: {}", err);
+            return Ok(None);
+        }
+        let xobject = self.document.resolve_object(group_obj)?;
+        let group_dict = match self.group_dictionary(xobject)? {
+            Some(group_dict) => group_dict,
+            None => failure::bail!("soft mask is not a transparency group"),
+        };
+        let state = GraphicsState {
+            transform: self.state.transform,
+            ..GraphicsState::default()
+        };
+        let group = self.decode_group(xobject, id, state, &group_dict)?;
+
+        // the backdrop is given in the colour space of the group
+        let backdrop = match dict.get(b"BC") {
+            Some(obj) => {
+                let components: Vec<f32> = self.document.deserialize_object(obj)?;
+                let group_space = xobject
+                    .try_as_stream()?
+                    .dict
+                    .get(b"Group")
+                    .and_then(|obj| self.document.resolve_object(obj).ok())
+                    .and_then(lopdf::Object::as_dict)
+                    .and_then(|dict| dict.get(b"CS"));
+                let space = match group_space {
+                    Some(obj) => colorspace::ColorSpace::try_from_object(&self.document, obj)?,
+                    None => match components.len() {
+                        4 => colorspace::ColorSpace::DeviceCMYK,
+                        3 => colorspace::ColorSpace::DeviceRGB,
+                        _ => colorspace::ColorSpace::DeviceGray,
+                    },
+                };
+                space.to_rgb(&components)
+            }
+            None => color::Rgb::BLACK,
+        };
+
+        let transfer = match dict.get(b"TR") {
+            Some(obj) => match self.document.resolve_object(obj)? {
+                lopdf::Object::Name(ref name) if name == b"Identity" => None,
+                obj => Some(function::Function::try_from_object(&self.document, obj)?),
+            },
+            None => None,
+        };
+
+        Ok(Some(Arc::new(transparency::SoftMask {
+            subtype,
+            group,
+            backdrop,
+            transfer,
+        })))
+    }
+
     fn try_next(&mut self) -> Fallible<Option<GraphicsObject>> {
+        if let Some(graphics_object) = self.pending.pop_front() {
+            return Ok(Some(graphics_object));
+        }
         loop {
+            if let Some(ref mut form) = self.form {
+                match form.try_next() {
+                    Ok(Some(graphics_object)) => return Ok(Some(graphics_object)),
+                    Ok(None) => {}
+                    Err(err) => log::warn!("skipping the rest of a form XObject: {}", err),
+                }
+                self.form = None;
+            }
             if let Some(operation) = self.operations.next() {
                 match operation.operator.as_str() {
                     OP_BEGIN_TEXT_OBJECT => {
-                        let text_builder =
-                            text::TextObjectBuilder::new(self.document.clone(), self.font_map);
+                        let text_builder = text::TextObjectBuilder::new(
+                            self.document.clone(),
+                            self.font_map.clone(),
+                        );
                         self.builder = Some(GraphicsObjectBuilder::Text(text_builder));
                     }
                     OP_END_TEXT_OBJECT => {
                         if let Some(GraphicsObjectBuilder::Text(text_builder)) = self.builder.take()
                         {
-                            return Ok(Some(GraphicsObject::Text(text_builder.build())));
+                            return Ok(Some(GraphicsObject::Text(text_builder.build(&self.state))));
                         } else {
                             failure::bail!("state transition error");
                         }
                     }
-                    "Tc" | "Tw" | "Tz" | "TL" | "Tf" => {
+                    "q" | "Q" | "cm" | "gs" => {
+                        self.handle_graphics_state_operation(&operation)?;
+                    }
+                    "G" | "g" | "RG" | "rg" | "K" | "k" | "CS" | "cs" | "SC" | "sc" | "SCN"
+                    | "scn" => {
+                        if !self.uncolored_glyph {
+                            self.state
+                                .color_state
+                                .handle_operation(&self.resources, &operation)?;
+                        }
+                    }
+                    "w" | "J" | "j" | "M" | "d" => {
+                        self.state
+                            .line_style
+                            .handle_operation(&self.document, &operation)?;
+                    }
+                    "W" | "W*" => {
+                        if let Some(GraphicsObjectBuilder::Path(ref mut path_builder)) =
+                            self.builder
+                        {
+                            path_builder.handle_operation(&operation)?;
+                        } else {
+                            log::warn!("clipping operator {:?} without a path", operation);
+                        }
+                    }
+                    "m" | "l" | "c" | "v" | "y" | "h" | "re" => {
+                        if let Some(GraphicsObjectBuilder::Path(ref mut path_builder)) =
+                            self.builder
+                        {
+                            path_builder.handle_operation(&operation)?;
+                        } else {
+                            let mut path_builder =
+                                path::PathObjectBuilder::new(self.document.clone());
+                            path_builder.handle_operation(&operation)?;
+                            self.builder = Some(GraphicsObjectBuilder::Path(path_builder));
+                        }
+                    }
+                    "S" | "s" | "f" | "F" | "f*" | "B" | "B*" | "b" | "b*" | "n" => {
+                        if let Some(GraphicsObjectBuilder::Path(path_builder)) = self.builder.take()
+                        {
+                            if let Some(mut path_object) =
+                                path_builder.build(&mut self.state, &operation)?
+                            {
+                                let pattern_object = self.fill_with_pattern(&mut path_object);
+                                let path_object =
+                                    if path_object.fill.is_some() || path_object.stroke {
+                                        Some(GraphicsObject::Path(path_object))
+                                    } else {
+                                        None
+                                    };
+                                if let Some(pattern_object) = pattern_object {
+                                    self.pending.extend(path_object);
+                                    return Ok(Some(pattern_object));
+                                } else if path_object.is_some() {
+                                    return Ok(path_object);
+                                }
+                            }
+                        } else {
+                            log::warn!("painting operator {:?} without a path", operation);
+                        }
+                    }
+                    "sh" => {
+                        if let Some(graphics_object) = self.handle_shading_operation(&operation)? {
+                            return Ok(Some(graphics_object));
+                        }
+                    }
+                    "Do" => {
+                        if let Some(graphics_object) = self.handle_xobject_operation(&operation)? {
+                            return Ok(Some(graphics_object));
+                        }
+                    }
+                    content::OP_INLINE_IMAGE => {
+                        match image::ImageObject::try_from_xobject(
+                            &self.resources,
+                            &operation.operands[0],
+                            &self.state,
+                        ) {
+                            Ok(image_object) => {
+                                return Ok(Some(GraphicsObject::Image(image_object)))
+                            }
+                            Err(err) => log::warn!("skipping inline image: {}", err),
+                        }
+                    }
+                    "d0" => {}
+                    "d1" => self.uncolored_glyph = true,
+                    "Tc" | "Tw" | "Tz" | "TL" | "Tf" | "Tr" | "Ts" => {
                         self.state
                             .text_state
                             .handle_operation(&self.document, &operation)?;
                     }
-                    _ => match self.builder {
-                        Some(GraphicsObjectBuilder::Text(ref mut text_builder)) => {
-                            text_builder.handle_operation(&mut self.state, &operation)?;
+                    _ => match self.builder.take() {
+                        Some(GraphicsObjectBuilder::Text(mut text_builder)) => {
+                            let start = text_builder.fragments().len();
+                            let result = text_builder.handle_operation(&mut self.state, &operation);
+                            for fragment in &text_builder.fragments()[start..] {
+                                match self.decode_type3_fragment(fragment) {
+                                    Ok(glyph_objects) => self.pending.extend(glyph_objects),
+                                    Err(err) => log::warn!("skipping Type 3 glyphs: {}", err),
+                                }
+                            }
+                            self.builder = Some(GraphicsObjectBuilder::Text(text_builder));
+                            result?;
+                            if let Some(graphics_object) = self.pending.pop_front() {
+                                return Ok(Some(graphics_object));
+                            }
                         }
-                        None => {}
+                        builder => self.builder = builder,
                     },
                 }
             } else {
@@ -91,7 +927,7 @@ impl<'a> GraphicsObjectDecoder<'a> {
     }
 }
 
-impl<'a> Iterator for GraphicsObjectDecoder<'a> {
+impl Iterator for GraphicsObjectDecoder {
     type Item = Fallible<GraphicsObject>;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -102,3 +938,603 @@ impl<'a> Iterator for GraphicsObjectDecoder<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(value: &[u8]) -> lopdf::Object {
+        lopdf::Object::Name(value.to_vec())
+    }
+
+    fn integers(values: &[i64]) -> lopdf::Object {
+        lopdf::Object::Array(values.iter().map(|v| lopdf::Object::Integer(*v)).collect())
+    }
+
+    /// Decodes a content stream with the given page resources.
+    fn decode_page(
+        document: Arc<lopdf::Document>,
+        page_resources: lopdf::Dictionary,
+        content: &[u8],
+    ) -> Vec<GraphicsObject> {
+        let resources = resources::Resources::new(document.clone(), vec![page_resources]);
+        let font_map = font::FontMap::try_from_resources(&resources).unwrap();
+        GraphicsObjectDecoder::decode(document, Rc::new(resources), Rc::new(font_map), content)
+            .unwrap()
+            .collect::<Fallible<Vec<_>>>()
+            .unwrap()
+    }
+
+    fn as_path(graphics_object: &GraphicsObject) -> &path::PathObject {
+        match graphics_object {
+            GraphicsObject::Path(path_object) => path_object,
+            _ => panic!("expected a path object"),
+        }
+    }
+
+    fn as_image(graphics_object: &GraphicsObject) -> &image::ImageObject {
+        match graphics_object {
+            GraphicsObject::Image(image_object) => image_object,
+            _ => panic!("expected an image object"),
+        }
+    }
+
+    fn as_shading(graphics_object: &GraphicsObject) -> &shading::ShadingObject {
+        match graphics_object {
+            GraphicsObject::Shading(shading_object) => shading_object,
+            _ => panic!("expected a shading object"),
+        }
+    }
+
+    fn as_text(graphics_object: &GraphicsObject) -> &text::TextObject {
+        match graphics_object {
+            GraphicsObject::Text(text_object) => text_object,
+            _ => panic!("expected a text object"),
+        }
+    }
+
+    #[test]
+    fn self_referencing_form_is_executed_once() {
+        let mut document = lopdf::Document::new();
+        let form_id = document.add_object(lopdf::Object::Null);
+
+        let mut xobjects = lopdf::Dictionary::new();
+        xobjects.set("F", form_id);
+        let mut form_resources = lopdf::Dictionary::new();
+        form_resources.set("XObject", xobjects.clone());
+        let mut form_dict = lopdf::Dictionary::new();
+        form_dict.set("Subtype", name(b"Form"));
+        form_dict.set("BBox", integers(&[0, 0, 10, 10]));
+        form_dict.set("Matrix", integers(&[2, 0, 0, 2, 5, 5]));
+        form_dict.set("Resources", form_resources);
+        let form = lopdf::Stream::new(form_dict, b"0 0 20 20 re f /F Do".to_vec());
+        document.objects.insert(form_id, form.into());
+
+        let mut page_resources = lopdf::Dictionary::new();
+        page_resources.set("XObject", xobjects);
+        let objects = decode_page(Arc::new(document), page_resources, b"/F Do /F Do");
+
+        assert_eq!(2, objects.len());
+        let path_object = as_path(&objects[0]);
+        assert_eq!(
+            euclid::Transform2D::row_major(2.0, 0.0, 0.0, 2.0, 5.0, 5.0),
+            path_object.transform
+        );
+        assert_eq!(1, path_object.clip.paths().len());
+        assert_eq!(
+            Some(euclid::Rect::new(
+                euclid::Point2D::zero(),
+                euclid::Size2D::new(10.0, 10.0)
+            )),
+            path_object.clip.paths()[0].path.as_rect()
+        );
+    }
+
+    #[test]
+    fn malformed_forms_are_skipped() {
+        let mut document = lopdf::Document::new();
+        let mut form_dict = lopdf::Dictionary::new();
+        form_dict.set("Subtype", name(b"Form"));
+        form_dict.set("BBox", integers(&[0, 0, 10, 10]));
+        // the operands of cm are invalid, so the rest of the form is skipped
+        let form = lopdf::Stream::new(
+            form_dict.clone(),
+            b"0 0 1 1 re f 1 0 0 (x) 0 0 cm 0 0 2 2 re f".to_vec(),
+        );
+        let form_id = document.add_object(form);
+        form_dict.set("Group", lopdf::Object::Integer(1));
+        let group_id = document.add_object(lopdf::Stream::new(form_dict, b"0 0 1 1 re f".to_vec()));
+
+        let mut xobjects = lopdf::Dictionary::new();
+        xobjects.set("F", form_id);
+        xobjects.set("G", group_id);
+        let mut page_resources = lopdf::Dictionary::new();
+        page_resources.set("XObject", xobjects);
+        let objects = decode_page(
+            Arc::new(document),
+            page_resources,
+            b"/F Do /G Do 0 0 3 3 re f",
+        );
+
+        assert_eq!(2, objects.len());
+        assert_eq!(
+            Some(euclid::Rect::new(
+                euclid::Point2D::zero(),
+                euclid::Size2D::new(1.0, 1.0)
+            )),
+            as_path(&objects[0]).path.as_rect()
+        );
+        assert_eq!(
+            Some(euclid::Rect::new(
+                euclid::Point2D::zero(),
+                euclid::Size2D::new(3.0, 3.0)
+            )),
+            as_path(&objects[1]).path.as_rect()
+        );
+    }
+
+    #[test]
+    fn forms_drawn_twice_share_their_fonts() {
+        let mut helvetica = lopdf::Dictionary::new();
+        helvetica.set("Subtype", name(b"Type1"));
+        helvetica.set("BaseFont", name(b"Helvetica"));
+        let mut fonts = lopdf::Dictionary::new();
+        fonts.set("F1", helvetica);
+        let mut form_resources = lopdf::Dictionary::new();
+        form_resources.set("Font", fonts);
+        let mut form_dict = lopdf::Dictionary::new();
+        form_dict.set("Subtype", name(b"Form"));
+        form_dict.set("BBox", integers(&[0, 0, 100, 100]));
+        form_dict.set("Resources", form_resources);
+        let mut document = lopdf::Document::new();
+        let form_id = document.add_object(lopdf::Stream::new(
+            form_dict,
+            b"BT /F1 10 Tf (a) Tj ET".to_vec(),
+        ));
+        let mut xobjects = lopdf::Dictionary::new();
+        xobjects.set("X", form_id);
+        let mut page_resources = lopdf::Dictionary::new();
+        page_resources.set("XObject", xobjects);
+        let objects = decode_page(Arc::new(document), page_resources, b"/X Do /X Do");
+
+        let font_ids = objects
+            .iter()
+            .map(|graphics_object| match graphics_object {
+                GraphicsObject::Text(text_object) => text_object.fragments[0].font.id(),
+                _ => panic!("expected a text object"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(2, font_ids.len());
+        assert_eq!(font_ids[0], font_ids[1]);
+    }
+
+    #[test]
+    fn form_executions_are_limited() {
+        // every form draws the next one twice, which would take 2^24 executions
+        let mut document = lopdf::Document::new();
+        let mut content = b"0 0 1 1 re f".to_vec();
+        let mut resources = lopdf::Dictionary::new();
+        for _ in 0..24 {
+            let mut form_dict = lopdf::Dictionary::new();
+            form_dict.set("Subtype", name(b"Form"));
+            form_dict.set("BBox", integers(&[0, 0, 10, 10]));
+            form_dict.set("Resources", resources);
+            let form_id = document.add_object(lopdf::Stream::new(form_dict, content));
+            let mut xobjects = lopdf::Dictionary::new();
+            xobjects.set("F", form_id);
+            resources = lopdf::Dictionary::new();
+            resources.set("XObject", xobjects);
+            content = b"/F Do /F Do".to_vec();
+        }
+        let objects = decode_page(Arc::new(document), resources, &content);
+        assert!(!objects.is_empty());
+        assert!(objects.len() <= MAX_FORM_EXECUTIONS);
+    }
+
+    #[test]
+    fn soft_masks_are_decoded_once_and_invalid_ones_ignored() {
+        let mut group = lopdf::Dictionary::new();
+        group.set("S", name(b"Transparency"));
+        let mut group_dict = lopdf::Dictionary::new();
+        group_dict.set("Subtype", name(b"Form"));
+        group_dict.set("BBox", integers(&[0, 0, 10, 10]));
+        group_dict.set("Group", group);
+        let mut document = lopdf::Document::new();
+        let group_id =
+            document.add_object(lopdf::Stream::new(group_dict, b"0 0 5 5 re f".to_vec()));
+        let mut soft_mask = lopdf::Dictionary::new();
+        soft_mask.set("S", name(b"Luminosity"));
+        soft_mask.set("G", group_id);
+        let mut masked = lopdf::Dictionary::new();
+        masked.set("SMask", soft_mask);
+        let mut invalid = lopdf::Dictionary::new();
+        invalid.set("SMask", lopdf::Dictionary::new());
+        let mut ext_g_states = lopdf::Dictionary::new();
+        ext_g_states.set("M", masked);
+        ext_g_states.set("X", invalid);
+        let mut page_resources = lopdf::Dictionary::new();
+        page_resources.set("ExtGState", ext_g_states);
+        let objects = decode_page(
+            Arc::new(document),
+            page_resources,
+            b"/M gs 0 0 1 1 re f /M gs 0 0 1 1 re f /X gs 0 0 1 1 re f",
+        );
+
+        assert_eq!(3, objects.len());
+        let soft_masks = objects
+            .iter()
+            .map(|graphics_object| as_path(graphics_object).transparency.soft_mask.clone())
+            .collect::<Vec<_>>();
+        match soft_masks.as_slice() {
+            [Some(first), Some(second), None] => assert!(Arc::ptr_eq(first, second)),
+            _ => panic!("expected the same soft mask twice and then none"),
+        }
+    }
+
+    #[test]
+    fn invalid_xobjects_are_skipped() {
+        let mut form_dict = lopdf::Dictionary::new();
+        form_dict.set("BBox", integers(&[0, 0, 10, 10]));
+        let mut xobjects = lopdf::Dictionary::new();
+        xobjects.set("A", integers(&[0, 0]));
+        xobjects.set("B", lopdf::Stream::new(form_dict, b"0 0 5 5 re f".to_vec()));
+        let mut page_resources = lopdf::Dictionary::new();
+        page_resources.set("XObject", xobjects);
+        let objects = decode_page(
+            Arc::new(lopdf::Document::new()),
+            page_resources,
+            b"/A Do /B Do 0 0 1 1 re f",
+        );
+
+        assert_eq!(1, objects.len());
+    }
+
+    #[test]
+    fn inline_image_is_decoded() {
+        let objects = decode_page(
+            Arc::new(lopdf::Document::new()),
+            lopdf::Dictionary::new(),
+            b"q 2 0 0 1 0 0 cm BI /W 2 /H 1 /CS /RGB /BPC 8 /F /AHx ID ff0000 00ff00> EI Q",
+        );
+
+        assert_eq!(1, objects.len());
+        let image_object = as_image(&objects[0]);
+        assert_eq!((2, 1), (image_object.width, image_object.height));
+        assert_eq!(vec![255, 0, 0, 255, 0, 255, 0, 255], image_object.pixels);
+        assert_eq!(
+            euclid::Transform2D::row_major(2.0, 0.0, 0.0, 1.0, 0.0, 0.0),
+            image_object.transform
+        );
+    }
+
+    #[test]
+    fn shading_pattern_fill_and_shading_operator() {
+        let mut function = lopdf::Dictionary::new();
+        function.set("FunctionType", lopdf::Object::Integer(2));
+        function.set("Domain", integers(&[0, 1]));
+        function.set("N", lopdf::Object::Integer(1));
+        let mut shading = lopdf::Dictionary::new();
+        shading.set("ShadingType", lopdf::Object::Integer(2));
+        shading.set("ColorSpace", name(b"DeviceGray"));
+        shading.set("Coords", integers(&[0, 0, 10, 0]));
+        shading.set("Function", function);
+        let mut pattern = lopdf::Dictionary::new();
+        pattern.set("PatternType", lopdf::Object::Integer(2));
+        pattern.set("Matrix", integers(&[1, 0, 0, 1, 5, 0]));
+        pattern.set("Shading", shading.clone());
+
+        let mut patterns = lopdf::Dictionary::new();
+        patterns.set("P", pattern);
+        let mut shadings = lopdf::Dictionary::new();
+        shadings.set("S", shading);
+        let mut page_resources = lopdf::Dictionary::new();
+        page_resources.set("Pattern", patterns);
+        page_resources.set("Shading", shadings);
+        let objects = decode_page(
+            Arc::new(lopdf::Document::new()),
+            page_resources,
+            b"2 0 0 2 0 0 cm /Pattern cs /P scn 0 0 5 5 re B /S sh",
+        );
+
+        assert_eq!(3, objects.len());
+        let shading_object = as_shading(&objects[0]);
+        // patterns are positioned relative to the page, not the current transform
+        assert_eq!(
+            euclid::Transform2D::row_major(1.0, 0.0, 0.0, 1.0, 5.0, 0.0),
+            shading_object.transform
+        );
+        assert_eq!(1, shading_object.clip.paths().len());
+        let path_object = as_path(&objects[1]);
+        assert!(path_object.fill.is_none());
+        assert!(path_object.stroke);
+        let shading_object = as_shading(&objects[2]);
+        assert_eq!(
+            euclid::Transform2D::row_major(2.0, 0.0, 0.0, 2.0, 0.0, 0.0),
+            shading_object.transform
+        );
+        assert!(shading_object.clip.is_empty());
+    }
+
+    #[test]
+    fn uncoloured_tiling_pattern_fill() {
+        let mut document = lopdf::Document::new();
+        let mut pattern_dict = lopdf::Dictionary::new();
+        pattern_dict.set("PatternType", lopdf::Object::Integer(1));
+        pattern_dict.set("PaintType", lopdf::Object::Integer(2));
+        pattern_dict.set("TilingType", lopdf::Object::Integer(1));
+        pattern_dict.set("BBox", integers(&[0, 0, 4, 4]));
+        pattern_dict.set("XStep", lopdf::Object::Integer(4));
+        pattern_dict.set("YStep", lopdf::Object::Integer(4));
+        let pattern = lopdf::Stream::new(pattern_dict, b"0 0 2 2 re f".to_vec());
+        let pattern_id = document.add_object(pattern);
+
+        let mut patterns = lopdf::Dictionary::new();
+        patterns.set("P", pattern_id);
+        let mut color_spaces = lopdf::Dictionary::new();
+        color_spaces.set(
+            "CS0",
+            lopdf::Object::Array(vec![name(b"Pattern"), name(b"DeviceRGB")]),
+        );
+        let mut page_resources = lopdf::Dictionary::new();
+        page_resources.set("Pattern", patterns);
+        page_resources.set("ColorSpace", color_spaces);
+        let objects = decode_page(
+            Arc::new(document),
+            page_resources,
+            b"/CS0 cs 1 0 0 /P scn 0 0 10 10 re f",
+        );
+
+        assert_eq!(1, objects.len());
+        let tiling_object = match objects[0] {
+            GraphicsObject::Tiling(ref tiling_object) => tiling_object,
+            _ => panic!("expected a tiling object"),
+        };
+        assert_eq!((4.0, 4.0), (tiling_object.x_step, tiling_object.y_step));
+        assert_eq!(1, tiling_object.clip.paths().len());
+        assert_eq!(1, tiling_object.objects.len());
+        let path_object = as_path(&tiling_object.objects[0]);
+        assert_eq!(color::Rgb::new(1.0, 0.0, 0.0), path_object.fill_color);
+        // the cell is clipped to the pattern's bounding box
+        assert_eq!(1, path_object.clip.paths().len());
+    }
+
+    #[test]
+    fn type3_glyphs_are_painted_from_their_descriptions() {
+        let mut document = lopdf::Document::new();
+        // the colour operator of an uncoloured glyph is ignored
+        let glyph = lopdf::Stream::new(
+            lopdf::Dictionary::new(),
+            b"600 0 0 0 1 1 d1 1 0 0 rg 0 0 1 1 re f".to_vec(),
+        );
+        let glyph_id = document.add_object(glyph);
+
+        let mut char_procs = lopdf::Dictionary::new();
+        char_procs.set("a", glyph_id);
+        let mut encoding = lopdf::Dictionary::new();
+        encoding.set(
+            "Differences",
+            lopdf::Object::Array(vec![lopdf::Object::Integer(97), name(b"a")]),
+        );
+        let mut font_dict = lopdf::Dictionary::new();
+        font_dict.set("Subtype", name(b"Type3"));
+        font_dict.set(
+            "FontMatrix",
+            lopdf::Object::Array(vec![
+                lopdf::Object::Real(0.01),
+                lopdf::Object::Integer(0),
+                lopdf::Object::Integer(0),
+                lopdf::Object::Real(0.01),
+                lopdf::Object::Integer(0),
+                lopdf::Object::Integer(0),
+            ]),
+        );
+        font_dict.set("CharProcs", char_procs);
+        font_dict.set("Encoding", encoding);
+        font_dict.set("FirstChar", lopdf::Object::Integer(97));
+        font_dict.set("LastChar", lopdf::Object::Integer(97));
+        font_dict.set("Widths", integers(&[600]));
+        let mut fonts = lopdf::Dictionary::new();
+        fonts.set("F1", font_dict);
+        let mut page_resources = lopdf::Dictionary::new();
+        page_resources.set("Font", fonts);
+
+        let objects = decode_page(
+            Arc::new(document),
+            page_resources,
+            b"0 0 1 rg BT /F1 1 Tf 100 200 Td (aa) Tj ET",
+        );
+
+        assert_eq!(3, objects.len());
+        for (object, x) in objects.iter().zip(&[100.0, 106.0]) {
+            let path_object = as_path(object);
+            assert_eq!(
+                euclid::Transform2D::row_major(0.01, 0.0, 0.0, 0.01, *x, 200.0),
+                path_object.transform
+            );
+            assert_eq!(color::Rgb::new(0.0, 0.0, 1.0), path_object.fill_color);
+        }
+        assert_eq!(2, as_text(&objects[2]).fragments[0].glyphs.len());
+    }
+
+    #[test]
+    fn text_render_mode_and_rise() {
+        let mut font_dict = lopdf::Dictionary::new();
+        font_dict.set("Subtype", name(b"Type1"));
+        font_dict.set("BaseFont", name(b"Helvetica"));
+        let mut fonts = lopdf::Dictionary::new();
+        fonts.set("F1", font_dict);
+        let mut page_resources = lopdf::Dictionary::new();
+        page_resources.set("Font", fonts);
+
+        // the clipping modes paint like the others, and an invalid rendering mode
+        // leaves the current one in effect
+        let objects = decode_page(
+            Arc::new(lopdf::Document::new()),
+            page_resources,
+            b"BT /F1 10 Tf 100 200 Td 7 Tr 5 Ts (a) Tj 4 Tr 9 Tr 0 Ts (a) Tj ET",
+        );
+
+        assert_eq!(1, objects.len());
+        let text_object = as_text(&objects[0]);
+        let invisible = &text_object.fragments[0];
+        assert_eq!(text::RenderMode::Invisible, invisible.render_mode);
+        assert!(!invisible.render_mode.is_visible());
+        assert_eq!(
+            euclid::Point2D::new(100.0, 205.0),
+            invisible.glyphs[0].origin
+        );
+        // the rise doesn't move the text position
+        let filled = &text_object.fragments[1];
+        assert_eq!(text::RenderMode::Fill, filled.render_mode);
+        assert_eq!(euclid::Point2D::new(105.56, 200.0), filled.glyphs[0].origin);
+    }
+
+    #[test]
+    fn glyph_origins_follow_the_text_rendering_matrix() {
+        let mut document = lopdf::Document::new();
+        let font_file = document.add_object(lopdf::Stream::new(
+            lopdf::Dictionary::new(),
+            b"not a font".to_vec(),
+        ));
+        let mut helvetica = lopdf::Dictionary::new();
+        helvetica.set("Subtype", name(b"Type1"));
+        helvetica.set("BaseFont", name(b"Helvetica"));
+        let mut fonts = lopdf::Dictionary::new();
+        fonts.set("F1", helvetica);
+        for &(font_name, encoding) in &[(b"F2", b"Identity-H"), (b"F3", b"Identity-V")] {
+            let mut descriptor = lopdf::Dictionary::new();
+            descriptor.set("FontFile2", font_file);
+            let mut descendant = lopdf::Dictionary::new();
+            descendant.set("Subtype", name(b"CIDFontType2"));
+            descendant.set("FontDescriptor", descriptor);
+            descendant.set("DW", lopdf::Object::Integer(500));
+            let mut font_dict = lopdf::Dictionary::new();
+            font_dict.set("Subtype", name(b"Type0"));
+            font_dict.set("Encoding", name(encoding));
+            font_dict.set(
+                "DescendantFonts",
+                lopdf::Object::Array(vec![descendant.into()]),
+            );
+            fonts.set(font_name.to_vec(), font_dict);
+        }
+        let mut page_resources = lopdf::Dictionary::new();
+        page_resources.set("Font", fonts);
+        let document = Arc::new(document);
+
+        // Helvetica's "a" is 556 and its space 278 units wide; the CIDs of the composite
+        // fonts are 500 units wide, with the default vertical metrics
+        type Case = (&'static [u8], &'static [(f32, f32)]);
+        let cases: &[Case] = &[
+            (
+                b"/F1 10 Tf 100 200 Td (aa) Tj",
+                &[(100.0, 200.0), (105.56, 200.0)],
+            ),
+            (
+                b"/F1 10 Tf 2 Tc 100 200 Td (aa) Tj",
+                &[(100.0, 200.0), (107.56, 200.0)],
+            ),
+            (
+                b"/F1 10 Tf 5 Tw 100 200 Td (a a) Tj",
+                &[(100.0, 200.0), (105.56, 200.0), (113.34, 200.0)],
+            ),
+            (
+                b"/F1 10 Tf 50 Tz 2 Tc 100 200 Td (aa) Tj",
+                &[(100.0, 200.0), (103.78, 200.0)],
+            ),
+            (
+                b"/F1 10 Tf 50 Tz 100 200 Td [(a) -1000 (a)] TJ",
+                &[(100.0, 200.0), (107.78, 200.0)],
+            ),
+            (b"/F1 10 Tf 3 Ts 100 200 Td (a) Tj", &[(100.0, 203.0)]),
+            (
+                b"/F1 10 Tf 0 1 -1 0 100 100 Tm (aa) Tj",
+                &[(100.0, 100.0), (100.0, 105.56)],
+            ),
+            (
+                b"/F1 10 Tf 0 1 -1 0 100 100 Tm 50 Tz 4 Ts (a) Tj",
+                &[(96.0, 100.0)],
+            ),
+            (
+                b"/F1 10 Tf 14 TL 100 200 Td (a) Tj T* (a) Tj",
+                &[(100.0, 200.0), (100.0, 186.0)],
+            ),
+            (b"/F1 10 Tf 14.5 TL 100 200 Td (a) '", &[(100.0, 185.5)]),
+            (
+                b"/F1 10 Tf 100 200 Td 0 -12 TD (a) Tj T* (a) Tj",
+                &[(100.0, 188.0), (100.0, 176.0)],
+            ),
+            (
+                b"/F1 10 Tf 12 TL 100 200 Td 5 1 (aa) \"",
+                &[(100.0, 188.0), (106.56, 188.0)],
+            ),
+            (
+                b"/F2 10 Tf 5 Tw 100 200 Td <00200020> Tj",
+                &[(100.0, 200.0), (105.0, 200.0)],
+            ),
+            (
+                b"/F3 10 Tf 100 200 Td <00010001> Tj",
+                &[(97.5, 191.2), (97.5, 181.2)],
+            ),
+            (
+                b"/F3 10 Tf 50 Tz 5 Tw 100 200 Td <0020> Tj",
+                &[(98.75, 191.2)],
+            ),
+        ];
+        for &(text, expected) in cases {
+            let content = [&b"2 0 0 2 10 20 cm BT "[..], text, b" ET"].concat();
+            let objects = decode_page(document.clone(), page_resources.clone(), &content);
+            assert_eq!(1, objects.len());
+            let origins = as_text(&objects[0])
+                .fragments
+                .iter()
+                .flat_map(|fragment| fragment.glyphs.iter().map(|glyph| glyph.origin))
+                .collect::<Vec<_>>();
+            // the CTM doubles text space and moves it by (10, 20)
+            let expected = expected
+                .iter()
+                .map(|&(x, y)| euclid::Point2D::new(2.0 * x + 10.0, 2.0 * y + 20.0))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                expected.len(),
+                origins.len(),
+                "{}",
+                String::from_utf8_lossy(text)
+            );
+            for (expected, origin) in expected.iter().zip(&origins) {
+                assert!(
+                    (*expected - *origin).length() < 1e-3,
+                    "{}: expected {:?}, got {:?}",
+                    String::from_utf8_lossy(text),
+                    expected,
+                    origin
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn text_without_a_loaded_font_is_skipped() {
+        let mut font_dict = lopdf::Dictionary::new();
+        font_dict.set("Subtype", name(b"Type1"));
+        font_dict.set("BaseFont", name(b"Helvetica"));
+        let mut fonts = lopdf::Dictionary::new();
+        fonts.set("F1", font_dict);
+        let mut page_resources = lopdf::Dictionary::new();
+        page_resources.set("Font", fonts);
+
+        let objects = decode_page(
+            Arc::new(lopdf::Document::new()),
+            page_resources,
+            b"BT 100 200 Td (a) Tj /F2 10 Tf (a) Tj /F1 10 Tf (a) Tj ET",
+        );
+
+        assert_eq!(1, objects.len());
+        let text_object = as_text(&objects[0]);
+        assert_eq!(1, text_object.fragments.len());
+        assert_eq!(&b"F1"[..], &text_object.fragments[0].font_name[..]);
+        assert_eq!(
+            euclid::Point2D::new(100.0, 200.0),
+            text_object.fragments[0].glyphs[0].origin
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use failure::Fallible;
+
+use rpdf_lopdf_extra::DocumentExt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineCap {
+    Butt,
+    Round,
+    ProjectingSquare,
+}
+
+impl LineCap {
+    pub fn from_i64(value: i64) -> Option<Self> {
+        match value {
+            0 => Some(LineCap::Butt),
+            1 => Some(LineCap::Round),
+            2 => Some(LineCap::ProjectingSquare),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineJoin {
+    Miter,
+    Round,
+    Bevel,
+}
+
+impl LineJoin {
+    pub fn from_i64(value: i64) -> Option<Self> {
+        match value {
+            0 => Some(LineJoin::Miter),
+            1 => Some(LineJoin::Round),
+            2 => Some(LineJoin::Bevel),
+            _ => None,
+        }
+    }
+}
+
+/// Lengths of alternating dashes and gaps, starting `phase` units into the pattern.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DashPattern {
+    pub array: Vec<f32>,
+    pub phase: f32,
+}
+
+impl DashPattern {
+    /// Returns whether the pattern produces dashes; patterns that are empty or have no
+    /// positive length draw solid lines.
+    pub fn is_dashed(&self) -> bool {
+        self.array.iter().all(|length| *length >= 0.0) && self.array.iter().sum::<f32>() > 0.0
+    }
+}
+
+/// Parameters used when stroking paths, in user space units.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineStyle {
+    /// Line width; zero requests the thinnest line the device can render.
+    pub width: f32,
+    pub cap: LineCap,
+    pub join: LineJoin,
+    pub miter_limit: f32,
+    pub dash: DashPattern,
+}
+
+impl Default for LineStyle {
+    fn default() -> Self {
+        LineStyle {
+            width: 1.0,
+            cap: LineCap::Butt,
+            join: LineJoin::Miter,
+            miter_limit: 10.0,
+            dash: DashPattern::default(),
+        }
+    }
+}
+
+impl LineStyle {
+    pub fn set_cap(&mut self, value: i64) {
+        match LineCap::from_i64(value) {
+            Some(cap) => self.cap = cap,
+            None => log::warn!("invalid line cap style {}", value),
+        }
+    }
+
+    pub fn set_join(&mut self, value: i64) {
+        match LineJoin::from_i64(value) {
+            Some(join) => self.join = join,
+            None => log::warn!("invalid line join style {}", value),
+        }
+    }
+
+    pub fn handle_operation(
+        &mut self,
+        document: &lopdf::Document,
+        op: &lopdf::content::Operation,
+    ) -> Fallible<()> {
+        match op.operator.as_str() {
+            "w" => {
+                let width: f32 = document.deserialize_object(&op.operands[0])?;
+                self.width = width.abs();
+            }
+            "J" => {
+                let cap = document.deserialize_object(&op.operands[0])?;
+                self.set_cap(cap);
+            }
+            "j" => {
+                let join = document.deserialize_object(&op.operands[0])?;
+                self.set_join(join);
+            }
+            "M" => {
+                self.miter_limit = document.deserialize_object(&op.operands[0])?;
+            }
+            "d" => {
+                self.dash = DashPattern {
+                    array: document.deserialize_object(&op.operands[0])?,
+                    phase: document.deserialize_object(&op.operands[1])?,
+                };
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lopdf::content::Operation;
+    use lopdf::Object;
+
+    #[test]
+    fn line_style_operators() {
+        let document = lopdf::Document::new();
+        let mut style = LineStyle::default();
+        let operations = [
+            Operation::new("w", vec![Object::Real(0.5)]),
+            Operation::new("J", vec![Object::Integer(1)]),
+            Operation::new("j", vec![Object::Integer(2)]),
+            Operation::new("M", vec![Object::Integer(4)]),
+            Operation::new(
+                "d",
+                vec![
+                    Object::Array(vec![Object::Integer(3), Object::Integer(1)]),
+                    Object::Integer(2),
+                ],
+            ),
+            // out of range values are ignored
+            Operation::new("J", vec![Object::Integer(7)]),
+        ];
+        for op in operations.iter() {
+            style.handle_operation(&document, op).unwrap();
+        }
+        assert_eq!(
+            LineStyle {
+                width: 0.5,
+                cap: LineCap::Round,
+                join: LineJoin::Bevel,
+                miter_limit: 4.0,
+                dash: DashPattern {
+                    array: vec![3.0, 1.0],
+                    phase: 2.0,
+                },
+            },
+            style
+        );
+        assert!(style.dash.is_dashed());
+        assert!(!DashPattern::default().is_dashed());
+    }
+}
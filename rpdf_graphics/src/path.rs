@@ -0,0 +1,449 @@
+use std::sync::Arc;
+
+use failure::Fallible;
+
+use rpdf_lopdf_extra::DocumentExt;
+
+use crate::clip::{Clip, ClipPath};
+use crate::color::Rgb;
+use crate::line::LineStyle;
+use crate::transparency::TransparencyState;
+
+use super::GraphicsState;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FillRule {
+    NonZero,
+    EvenOdd,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathSegment {
+    LineTo(euclid::Point2D<f32>),
+    CurveTo(
+        euclid::Point2D<f32>,
+        euclid::Point2D<f32>,
+        euclid::Point2D<f32>,
+    ),
+}
+
+impl PathSegment {
+    pub fn end_point(&self) -> euclid::Point2D<f32> {
+        match *self {
+            PathSegment::LineTo(point) => point,
+            PathSegment::CurveTo(_, _, point) => point,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Subpath {
+    pub start: euclid::Point2D<f32>,
+    pub segments: Vec<PathSegment>,
+    pub closed: bool,
+}
+
+impl Subpath {
+    fn new(start: euclid::Point2D<f32>) -> Self {
+        Subpath {
+            start,
+            segments: Vec::new(),
+            closed: false,
+        }
+    }
+
+    pub fn current_point(&self) -> euclid::Point2D<f32> {
+        self.segments
+            .last()
+            .map(PathSegment::end_point)
+            .unwrap_or(self.start)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Path {
+    pub subpaths: Vec<Subpath>,
+}
+
+impl Path {
+    pub fn from_rect(rect: &euclid::Rect<f32>) -> Self {
+        Path {
+            subpaths: vec![Subpath {
+                start: rect.origin,
+                segments: vec![
+                    PathSegment::LineTo(rect.top_right()),
+                    PathSegment::LineTo(rect.bottom_right()),
+                    PathSegment::LineTo(rect.bottom_left()),
+                ],
+                closed: true,
+            }],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subpaths.is_empty()
+    }
+
+    /// Returns the rectangle covered by the path if it consists of a single axis-aligned
+    /// rectangle, such as one created by the `re` operator.
+    pub fn as_rect(&self) -> Option<euclid::Rect<f32>> {
+        if self.subpaths.len() != 1 {
+            return None;
+        }
+        let subpath = &self.subpaths[0];
+        let mut points = vec![subpath.start];
+        for segment in subpath.segments.iter() {
+            match *segment {
+                PathSegment::LineTo(point) => points.push(point),
+                PathSegment::CurveTo(..) => return None,
+            }
+        }
+        if points.len() == 5 && points[4] == points[0] {
+            points.pop();
+        }
+        if points.len() != 4 {
+            return None;
+        }
+
+        let horizontal_first = points[0].y == points[1].y
+            && points[1].x == points[2].x
+            && points[2].y == points[3].y
+            && points[3].x == points[0].x;
+        let vertical_first = points[0].x == points[1].x
+            && points[1].y == points[2].y
+            && points[2].x == points[3].x
+            && points[3].y == points[0].y;
+        if horizontal_first || vertical_first {
+            Some(euclid::Rect::from_points(points.iter()))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct PathObject {
+    pub transform: euclid::Transform2D<f32>,
+    pub path: Path,
+    pub fill: Option<FillRule>,
+    pub stroke: bool,
+    pub fill_color: Rgb,
+    pub stroke_color: Rgb,
+    pub line_style: LineStyle,
+    pub transparency: TransparencyState,
+    pub clip: Clip,
+}
+
+pub struct PathObjectBuilder {
+    document: Arc<lopdf::Document>,
+    subpaths: Vec<Subpath>,
+    current_subpath: Option<Subpath>,
+    current_point: euclid::Point2D<f32>,
+    /// Set by `W` or `W*` to intersect the clip with the path once it is painted.
+    clip: Option<FillRule>,
+}
+
+impl PathObjectBuilder {
+    pub fn new(document: Arc<lopdf::Document>) -> Self {
+        Self {
+            document,
+            subpaths: Vec::new(),
+            current_subpath: None,
+            current_point: euclid::Point2D::zero(),
+            clip: None,
+        }
+    }
+
+    fn read_point(
+        &self,
+        op: &lopdf::content::Operation,
+        index: usize,
+    ) -> Fallible<euclid::Point2D<f32>> {
+        let x = self.document.deserialize_object(&op.operands[index])?;
+        let y = self.document.deserialize_object(&op.operands[index + 1])?;
+        Ok(euclid::Point2D::new(x, y))
+    }
+
+    fn move_to(&mut self, point: euclid::Point2D<f32>) {
+        if let Some(subpath) = self.current_subpath.take() {
+            self.subpaths.push(subpath);
+        }
+        self.current_subpath = Some(Subpath::new(point));
+        self.current_point = point;
+    }
+
+    fn push_segment(&mut self, segment: PathSegment) {
+        let current_point = self.current_point;
+        let subpath = self
+            .current_subpath
+            .get_or_insert_with(|| Subpath::new(current_point));
+        self.current_point = segment.end_point();
+        subpath.segments.push(segment);
+    }
+
+    fn close_path(&mut self) {
+        if let Some(mut subpath) = self.current_subpath.take() {
+            subpath.closed = true;
+            self.current_point = subpath.start;
+            self.subpaths.push(subpath);
+        }
+    }
+
+    pub fn handle_operation(&mut self, op: &lopdf::content::Operation) -> Fallible<()> {
+        match op.operator.as_str() {
+            "m" => {
+                let point = self.read_point(op, 0)?;
+                self.move_to(point);
+            }
+            "l" => {
+                let point = self.read_point(op, 0)?;
+                self.push_segment(PathSegment::LineTo(point));
+            }
+            "c" => {
+                let control_1 = self.read_point(op, 0)?;
+                let control_2 = self.read_point(op, 2)?;
+                let point = self.read_point(op, 4)?;
+                self.push_segment(PathSegment::CurveTo(control_1, control_2, point));
+            }
+            "v" => {
+                let control_1 = self.current_point;
+                let control_2 = self.read_point(op, 0)?;
+                let point = self.read_point(op, 2)?;
+                self.push_segment(PathSegment::CurveTo(control_1, control_2, point));
+            }
+            "y" => {
+                let control_1 = self.read_point(op, 0)?;
+                let point = self.read_point(op, 2)?;
+                self.push_segment(PathSegment::CurveTo(control_1, point, point));
+            }
+            "h" => {
+                self.close_path();
+            }
+            "re" => {
+                let origin = self.read_point(op, 0)?;
+                let width: f32 = self.document.deserialize_object(&op.operands[2])?;
+                let height: f32 = self.document.deserialize_object(&op.operands[3])?;
+                self.move_to(origin);
+                self.push_segment(PathSegment::LineTo(euclid::Point2D::new(
+                    origin.x + width,
+                    origin.y,
+                )));
+                self.push_segment(PathSegment::LineTo(euclid::Point2D::new(
+                    origin.x + width,
+                    origin.y + height,
+                )));
+                self.push_segment(PathSegment::LineTo(euclid::Point2D::new(
+                    origin.x,
+                    origin.y + height,
+                )));
+                self.close_path();
+            }
+            "W" => {
+                self.clip = Some(FillRule::NonZero);
+            }
+            "W*" => {
+                self.clip = Some(FillRule::EvenOdd);
+            }
+            _ => failure::bail!("unknown operation {:?}", op),
+        }
+        Ok(())
+    }
+
+    /// Finishes the path using the given painting operator.
+    ///
+    /// Returns `None` for the `n` operator, which ends the path without painting it.
+    /// A pending `W` or `W*` intersects the clip of `state` with the path, which only
+    /// affects objects painted afterwards.
+    pub fn build(
+        mut self,
+        state: &mut GraphicsState,
+        op: &lopdf::content::Operation,
+    ) -> Fallible<Option<PathObject>> {
+        let (close, fill, stroke) = match op.operator.as_str() {
+            "S" => (false, None, true),
+            "s" => (true, None, true),
+            "f" | "F" => (false, Some(FillRule::NonZero), false),
+            "f*" => (false, Some(FillRule::EvenOdd), false),
+            "B" => (false, Some(FillRule::NonZero), true),
+            "B*" => (false, Some(FillRule::EvenOdd), true),
+            "b" => (true, Some(FillRule::NonZero), true),
+            "b*" => (true, Some(FillRule::EvenOdd), true),
+            "n" => (false, None, false),
+            _ => failure::bail!("unknown operation {:?}", op),
+        };
+
+        if close {
+            self.close_path();
+        } else if let Some(subpath) = self.current_subpath.take() {
+            self.subpaths.push(subpath);
+        }
+        let path = Path {
+            subpaths: self.subpaths,
+        };
+
+        let clip = state.clip.clone();
+        if let Some(fill_rule) = self.clip {
+            state.clip.intersect(ClipPath {
+                transform: state.transform,
+                path: path.clone(),
+                fill_rule,
+            });
+        }
+
+        if fill.is_none() && !stroke {
+            return Ok(None);
+        }
+        Ok(Some(PathObject {
+            transform: state.transform,
+            path,
+            fill,
+            stroke,
+            fill_color: state.color_state.fill.to_rgb(),
+            stroke_color: state.color_state.stroke.to_rgb(),
+            line_style: state.line_style.clone(),
+            transparency: state.transparency.clone(),
+            clip,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lopdf::content::Operation;
+
+    fn build_path_with_state(
+        state: &mut GraphicsState,
+        ops: Vec<Operation>,
+        paint: Operation,
+    ) -> Option<PathObject> {
+        let mut builder = PathObjectBuilder::new(Arc::new(lopdf::Document::new()));
+        for op in ops {
+            builder.handle_operation(&op).unwrap();
+        }
+        builder.build(state, &paint).unwrap()
+    }
+
+    fn build_path(ops: Vec<Operation>, paint: Operation) -> Option<PathObject> {
+        build_path_with_state(&mut GraphicsState::default(), ops, paint)
+    }
+
+    fn operands(values: &[i64]) -> Vec<lopdf::Object> {
+        values.iter().map(|v| lopdf::Object::Integer(*v)).collect()
+    }
+
+    #[test]
+    fn rectangle_is_closed_subpath() {
+        let object = build_path(
+            vec![Operation::new("re", operands(&[10, 20, 30, 40]))],
+            Operation::new("f", vec![]),
+        )
+        .unwrap();
+        assert_eq!(Some(FillRule::NonZero), object.fill);
+        assert!(!object.stroke);
+        assert_eq!(
+            vec![Subpath {
+                start: euclid::Point2D::new(10.0, 20.0),
+                segments: vec![
+                    PathSegment::LineTo(euclid::Point2D::new(40.0, 20.0)),
+                    PathSegment::LineTo(euclid::Point2D::new(40.0, 60.0)),
+                    PathSegment::LineTo(euclid::Point2D::new(10.0, 60.0)),
+                ],
+                closed: true,
+            }],
+            object.path.subpaths
+        );
+    }
+
+    #[test]
+    fn close_and_stroke_with_shorthand_curves() {
+        let object = build_path(
+            vec![
+                Operation::new("m", operands(&[0, 0])),
+                Operation::new("v", operands(&[1, 1, 2, 0])),
+                Operation::new("y", operands(&[3, 1, 4, 0])),
+            ],
+            Operation::new("s", vec![]),
+        )
+        .unwrap();
+        assert_eq!(None, object.fill);
+        assert!(object.stroke);
+        assert_eq!(
+            vec![Subpath {
+                start: euclid::Point2D::new(0.0, 0.0),
+                segments: vec![
+                    PathSegment::CurveTo(
+                        euclid::Point2D::new(0.0, 0.0),
+                        euclid::Point2D::new(1.0, 1.0),
+                        euclid::Point2D::new(2.0, 0.0),
+                    ),
+                    PathSegment::CurveTo(
+                        euclid::Point2D::new(3.0, 1.0),
+                        euclid::Point2D::new(4.0, 0.0),
+                        euclid::Point2D::new(4.0, 0.0),
+                    ),
+                ],
+                closed: true,
+            }],
+            object.path.subpaths
+        );
+    }
+
+    #[test]
+    fn end_path_without_painting() {
+        let object = build_path(
+            vec![Operation::new("re", operands(&[0, 0, 1, 1]))],
+            Operation::new("n", vec![]),
+        );
+        assert!(object.is_none());
+    }
+
+    #[test]
+    fn rectangle_path_as_rect() {
+        let rect = euclid::Rect::new(
+            euclid::Point2D::new(10.0, 20.0),
+            euclid::Size2D::new(30.0, 40.0),
+        );
+        assert_eq!(Some(rect), Path::from_rect(&rect).as_rect());
+
+        let object = build_path(
+            vec![
+                Operation::new("m", operands(&[0, 0])),
+                Operation::new("l", operands(&[10, 5])),
+                Operation::new("l", operands(&[0, 10])),
+            ],
+            Operation::new("f", vec![]),
+        )
+        .unwrap();
+        assert_eq!(None, object.path.as_rect());
+    }
+
+    #[test]
+    fn clip_applies_after_painting() {
+        let mut state = GraphicsState::default();
+        let object = build_path_with_state(
+            &mut state,
+            vec![
+                Operation::new("re", operands(&[0, 0, 10, 10])),
+                Operation::new("W*", vec![]),
+            ],
+            Operation::new("f", vec![]),
+        )
+        .unwrap();
+        assert!(object.clip.is_empty());
+        assert_eq!(1, state.clip.paths().len());
+        assert_eq!(FillRule::EvenOdd, state.clip.paths()[0].fill_rule);
+
+        let object = build_path_with_state(
+            &mut state,
+            vec![
+                Operation::new("re", operands(&[5, 5, 10, 10])),
+                Operation::new("W", vec![]),
+            ],
+            Operation::new("n", vec![]),
+        );
+        assert!(object.is_none());
+        assert_eq!(2, state.clip.paths().len());
+        assert_eq!(FillRule::NonZero, state.clip.paths()[1].fill_rule);
+    }
+}
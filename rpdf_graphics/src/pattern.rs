@@ -0,0 +1,125 @@
+use std::sync::Arc;
+
+use failure::Fallible;
+use serde_derive::Deserialize;
+
+use rpdf_lopdf_extra::*;
+
+use crate::clip::Clip;
+use crate::filter;
+use crate::shading::Shading;
+use crate::transparency::TransparencyState;
+use crate::GraphicsObject;
+
+#[derive(Deserialize)]
+struct PatternDictionary {
+    #[serde(rename = "PatternType")]
+    pattern_type: u32,
+    #[serde(rename = "Matrix")]
+    matrix: Option<[f32; 6]>,
+}
+
+#[derive(Deserialize)]
+struct TilingPatternDictionary {
+    #[serde(rename = "PaintType")]
+    paint_type: u32,
+    #[serde(rename = "BBox")]
+    bbox: [f32; 4],
+    #[serde(rename = "XStep")]
+    x_step: f32,
+    #[serde(rename = "YStep")]
+    y_step: f32,
+}
+
+/// A pattern whose cell, described by a content stream, is repeated at fixed intervals.
+pub struct TilingPattern {
+    /// Whether the cell specifies its own colours. Uncoloured cells are painted in the
+    /// colour the pattern is used with.
+    pub colored: bool,
+    /// Bounds of the cell in pattern space, to which its contents are clipped.
+    pub bbox: euclid::Rect<f32>,
+    pub x_step: f32,
+    pub y_step: f32,
+    pub content: Vec<u8>,
+    /// The `Resources` entry of the pattern, if any.
+    pub resources: Option<lopdf::Object>,
+}
+
+pub enum PatternKind {
+    Shading(Arc<Shading>),
+    Tiling(TilingPattern),
+}
+
+/// A pattern used as the colour of the `Pattern` colour space.
+pub struct Pattern {
+    pub kind: PatternKind,
+    /// Transform from pattern space to the default coordinate space of the content
+    /// stream using the pattern.
+    pub matrix: euclid::Transform2D<f32>,
+}
+
+impl Pattern {
+    pub fn try_from_object(doc: &lopdf::Document, obj: &lopdf::Object) -> Fallible<Self> {
+        let dict: PatternDictionary = doc.deserialize_object(obj)?;
+        let [a, b, c, d, e, f] = dict.matrix.unwrap_or([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+        let pattern_dict = match doc.resolve_object(obj)? {
+            lopdf::Object::Dictionary(ref dict) => dict,
+            lopdf::Object::Stream(ref stream) => &stream.dict,
+            _ => failure::bail!("pattern is neither a dictionary nor a stream"),
+        };
+
+        let kind = match dict.pattern_type {
+            1 => {
+                let tiling_dict: TilingPatternDictionary = doc.deserialize_object(obj)?;
+                let colored = match tiling_dict.paint_type {
+                    1 => true,
+                    2 => false,
+                    paint_type => failure::bail!("invalid pattern paint type {}", paint_type),
+                };
+                if tiling_dict.x_step == 0.0 || tiling_dict.y_step == 0.0 {
+                    failure::bail!("tiling pattern has a zero step");
+                }
+                let [x1, y1, x2, y2] = tiling_dict.bbox;
+                let stream = doc.resolve_object(obj)?.try_as_stream()?;
+                PatternKind::Tiling(TilingPattern {
+                    colored,
+                    bbox: euclid::Rect::from_points([
+                        euclid::Point2D::new(x1, y1),
+                        euclid::Point2D::new(x2, y2),
+                    ]),
+                    x_step: tiling_dict.x_step,
+                    y_step: tiling_dict.y_step,
+                    content: filter::decode(doc, &stream.dict, &stream.content)?,
+                    resources: stream.dict.get(b"Resources").cloned(),
+                })
+            }
+            2 => {
+                if pattern_dict.get(b"ExtGState").is_some() {
+                    log::warn!("ignoring graphics state parameters of shading pattern");
+                }
+                let shading = Shading::try_from_object(doc, pattern_dict.try_get(b"Shading")?)?;
+                PatternKind::Shading(Arc::new(shading))
+            }
+            pattern_type => failure::bail!("unsupported pattern type {}", pattern_type),
+        };
+
+        Ok(Pattern {
+            kind,
+            matrix: euclid::Transform2D::row_major(a, b, c, d, e, f),
+        })
+    }
+}
+
+/// A path filled with a tiling pattern.
+pub struct TilingObject {
+    /// The objects painted by one cell of the pattern, in pattern space.
+    pub objects: Vec<GraphicsObject>,
+    /// Transform from pattern space to the page.
+    pub transform: euclid::Transform2D<f32>,
+    pub bbox: euclid::Rect<f32>,
+    pub x_step: f32,
+    pub y_step: f32,
+    pub transparency: TransparencyState,
+    /// The clip of the object, including the filled path.
+    pub clip: Clip,
+}
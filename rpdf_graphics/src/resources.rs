@@ -0,0 +1,149 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use failure::Fallible;
+
+use rpdf_lopdf_extra::*;
+
+use crate::colorspace::ColorSpace;
+use crate::pattern::Pattern;
+use crate::shading::Shading;
+
+/// The named resources (colour spaces, XObjects, fonts, ...) available to a content stream.
+pub struct Resources {
+    document: Arc<lopdf::Document>,
+    dictionaries: Vec<lopdf::Dictionary>,
+    color_spaces: RefCell<HashMap<Vec<u8>, Arc<ColorSpace>>>,
+    patterns: RefCell<HashMap<Vec<u8>, Arc<Pattern>>>,
+    shadings: RefCell<HashMap<Vec<u8>, Arc<Shading>>>,
+}
+
+impl Resources {
+    pub fn new(document: Arc<lopdf::Document>, dictionaries: Vec<lopdf::Dictionary>) -> Self {
+        Resources {
+            document,
+            dictionaries,
+            color_spaces: RefCell::new(HashMap::new()),
+            patterns: RefCell::new(HashMap::new()),
+            shadings: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_page(document: Arc<lopdf::Document>, page_id: lopdf::ObjectId) -> Self {
+        let mut dictionaries = Vec::new();
+        {
+            let (resource_dict, resource_ids) = document.get_page_resources(page_id);
+            if let Some(dict) = resource_dict {
+                dictionaries.push(dict.clone());
+            }
+            for resource_id in resource_ids {
+                if let Some(dict) = document.get_dictionary(resource_id) {
+                    dictionaries.push(dict.clone());
+                }
+            }
+        }
+        Self::new(document, dictionaries)
+    }
+
+    fn category<'a>(
+        &'a self,
+        dict: &'a lopdf::Dictionary,
+        category: &[u8],
+    ) -> Option<&'a lopdf::Dictionary> {
+        dict.get(category)
+            .and_then(|obj| self.document.resolve_object(obj).ok())
+            .and_then(lopdf::Object::as_dict)
+    }
+
+    pub fn document(&self) -> &lopdf::Document {
+        &self.document
+    }
+
+    /// Looks up a resource by category (e.g. `ColorSpace`) and name, resolving references.
+    pub fn get(&self, category: &[u8], name: &[u8]) -> Option<&lopdf::Object> {
+        self.get_unresolved(category, name)
+            .and_then(|obj| self.document.resolve_object(obj).ok())
+    }
+
+    fn get_unresolved(&self, category: &[u8], name: &[u8]) -> Option<&lopdf::Object> {
+        self.dictionaries
+            .iter()
+            .filter_map(|dict| self.category(dict, category)?.get(name))
+            .next()
+    }
+
+    /// Returns the object id of a resource, if it is an indirect object.
+    pub fn get_id(&self, category: &[u8], name: &[u8]) -> Option<lopdf::ObjectId> {
+        match self.get_unresolved(category, name) {
+            Some(lopdf::Object::Reference(id)) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// Returns all resources of a category by name, resolving references.
+    pub fn entries(&self, category: &[u8]) -> BTreeMap<Vec<u8>, &lopdf::Object> {
+        let mut entries = BTreeMap::new();
+        for dict in self.dictionaries.iter() {
+            if let Some(category_dict) = self.category(dict, category) {
+                for (name, obj) in category_dict.iter() {
+                    if let Ok(obj) = self.document.resolve_object(obj) {
+                        entries.entry(name.clone()).or_insert(obj);
+                    }
+                }
+            }
+        }
+        entries
+    }
+
+    pub fn try_get(&self, category: &[u8], name: &[u8]) -> Fallible<&lopdf::Object> {
+        self.get(category, name).ok_or_else(|| {
+            failure::format_err!(
+                "resource {} /{} not found",
+                String::from_utf8_lossy(category),
+                String::from_utf8_lossy(name)
+            )
+        })
+    }
+
+    /// Resolves a colour space operand, which is either a family name or the name of a
+    /// `ColorSpace` resource.
+    pub fn color_space(&self, name: &[u8]) -> Fallible<Arc<ColorSpace>> {
+        if let Some(space) = ColorSpace::from_name(name) {
+            return Ok(Arc::new(space));
+        }
+        if let Some(space) = self.color_spaces.borrow().get(name) {
+            return Ok(space.clone());
+        }
+        let obj = self.try_get(b"ColorSpace", name)?;
+        let space = Arc::new(ColorSpace::try_from_object(&self.document, obj)?);
+        self.color_spaces
+            .borrow_mut()
+            .insert(name.to_owned(), space.clone());
+        Ok(space)
+    }
+
+    pub fn pattern(&self, name: &[u8]) -> Fallible<Arc<Pattern>> {
+        if let Some(pattern) = self.patterns.borrow().get(name) {
+            return Ok(pattern.clone());
+        }
+        let obj = self.try_get(b"Pattern", name)?;
+        let pattern = Arc::new(Pattern::try_from_object(&self.document, obj)?);
+        self.patterns
+            .borrow_mut()
+            .insert(name.to_owned(), pattern.clone());
+        Ok(pattern)
+    }
+
+    pub fn shading(&self, name: &[u8]) -> Fallible<Arc<Shading>> {
+        if let Some(shading) = self.shadings.borrow().get(name) {
+            return Ok(shading.clone());
+        }
+        let obj = self.try_get(b"Shading", name)?;
+        let shading = Arc::new(Shading::try_from_object(&self.document, obj)?);
+        self.shadings
+            .borrow_mut()
+            .insert(name.to_owned(), shading.clone());
+        Ok(shading)
+    }
+}
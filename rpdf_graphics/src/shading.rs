@@ -0,0 +1,382 @@
+use std::sync::Arc;
+
+use failure::Fallible;
+use serde_derive::Deserialize;
+
+use rpdf_lopdf_extra::*;
+
+use crate::clip::Clip;
+use crate::color::Rgb;
+use crate::colorspace::ColorSpace;
+use crate::function::Function;
+use crate::transparency::TransparencyState;
+
+mod mesh;
+
+pub use self::mesh::{MeshVertex, Triangle};
+
+#[derive(Deserialize)]
+struct ShadingDictionary {
+    #[serde(rename = "ShadingType")]
+    shading_type: u32,
+    #[serde(rename = "Background")]
+    background: Option<Vec<f32>>,
+    #[serde(rename = "BBox")]
+    bbox: Option<[f32; 4]>,
+}
+
+#[derive(Deserialize)]
+struct FunctionShadingDictionary {
+    #[serde(rename = "Domain")]
+    domain: Option<[f32; 4]>,
+    #[serde(rename = "Matrix")]
+    matrix: Option<[f32; 6]>,
+}
+
+#[derive(Deserialize)]
+struct AxialShadingDictionary {
+    #[serde(rename = "Coords")]
+    coords: [f32; 4],
+    #[serde(rename = "Domain")]
+    domain: Option<[f32; 2]>,
+    #[serde(rename = "Extend")]
+    extend: Option<[bool; 2]>,
+}
+
+#[derive(Deserialize)]
+struct RadialShadingDictionary {
+    #[serde(rename = "Coords")]
+    coords: [f32; 6],
+    #[serde(rename = "Domain")]
+    domain: Option<[f32; 2]>,
+    #[serde(rename = "Extend")]
+    extend: Option<[bool; 2]>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Circle {
+    pub center: euclid::Point2D<f32>,
+    pub radius: f32,
+}
+
+#[derive(Debug)]
+pub enum ShadingKind {
+    /// Colours are a function of the coordinates in `domain`, which `matrix` maps to
+    /// shading space.
+    FunctionBased {
+        domain: euclid::Rect<f32>,
+        matrix: euclid::Transform2D<f32>,
+    },
+    /// Colours vary along the axis between two points.
+    Axial {
+        start: euclid::Point2D<f32>,
+        end: euclid::Point2D<f32>,
+        domain: [f32; 2],
+        extend: [bool; 2],
+    },
+    /// Colours vary across a family of circles interpolated between two circles.
+    Radial {
+        start: Circle,
+        end: Circle,
+        domain: [f32; 2],
+        extend: [bool; 2],
+    },
+    /// Colours are interpolated across triangles, which approximate the patches of
+    /// patch meshes.
+    Mesh { triangles: Vec<Triangle> },
+}
+
+/// A shading dictionary, describing a smooth transition between colours.
+#[derive(Debug)]
+pub struct Shading {
+    pub kind: ShadingKind,
+    pub color_space: Arc<ColorSpace>,
+    /// Colour of the areas outside the shading's extent, only used in shading patterns.
+    pub background: Option<Rgb>,
+    /// Bounds of the shading in shading space.
+    pub bbox: Option<euclid::Rect<f32>>,
+    /// Either a single function with one output per colour component, or one function
+    /// per component. Mesh shadings may have no function at all.
+    functions: Vec<Function>,
+}
+
+fn shading_dictionary<'a>(
+    doc: &'a lopdf::Document,
+    obj: &'a lopdf::Object,
+) -> Fallible<&'a lopdf::Dictionary> {
+    match doc.resolve_object(obj)? {
+        lopdf::Object::Dictionary(ref dict) => Ok(dict),
+        lopdf::Object::Stream(ref stream) => Ok(&stream.dict),
+        _ => failure::bail!("shading is neither a dictionary nor a stream"),
+    }
+}
+
+fn read_functions(doc: &lopdf::Document, obj: &lopdf::Object) -> Fallible<Vec<Function>> {
+    match doc.resolve_object(obj)? {
+        lopdf::Object::Array(ref items) => items
+            .iter()
+            .map(|item| Function::try_from_object(doc, item))
+            .collect(),
+        _ => Ok(vec![Function::try_from_object(doc, obj)?]),
+    }
+}
+
+/// Solves `a·s² - 2b·s + c = 0`, returning the larger root first.
+fn solve_quadratic(a: f32, b: f32, c: f32) -> Vec<f32> {
+    if a.abs() < 1e-6 {
+        if b.abs() < 1e-6 {
+            return vec![];
+        }
+        return vec![c / (2.0 * b)];
+    }
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return vec![];
+    }
+    let root = discriminant.sqrt();
+    let (s1, s2) = ((b + root) / a, (b - root) / a);
+    vec![s1.max(s2), s1.min(s2)]
+}
+
+impl Shading {
+    pub fn try_from_object(doc: &lopdf::Document, obj: &lopdf::Object) -> Fallible<Self> {
+        let dict: ShadingDictionary = doc.deserialize_object(obj)?;
+        let shading_dict = shading_dictionary(doc, obj)?;
+        let color_space = Arc::new(ColorSpace::try_from_object(
+            doc,
+            shading_dict.try_get(b"ColorSpace")?,
+        )?);
+        let functions = match shading_dict.get(b"Function") {
+            Some(function_obj) => read_functions(doc, function_obj)?,
+            None => vec![],
+        };
+
+        let kind = match dict.shading_type {
+            1 => {
+                let function_dict: FunctionShadingDictionary = doc.deserialize_object(obj)?;
+                let [x0, x1, y0, y1] = function_dict.domain.unwrap_or([0.0, 1.0, 0.0, 1.0]);
+                let [a, b, c, d, e, f] = function_dict
+                    .matrix
+                    .unwrap_or([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+                ShadingKind::FunctionBased {
+                    domain: euclid::Rect::from_points([
+                        euclid::Point2D::new(x0, y0),
+                        euclid::Point2D::new(x1, y1),
+                    ]),
+                    matrix: euclid::Transform2D::row_major(a, b, c, d, e, f),
+                }
+            }
+            2 => {
+                let axial_dict: AxialShadingDictionary = doc.deserialize_object(obj)?;
+                let [x0, y0, x1, y1] = axial_dict.coords;
+                ShadingKind::Axial {
+                    start: euclid::Point2D::new(x0, y0),
+                    end: euclid::Point2D::new(x1, y1),
+                    domain: axial_dict.domain.unwrap_or([0.0, 1.0]),
+                    extend: axial_dict.extend.unwrap_or([false, false]),
+                }
+            }
+            3 => {
+                let radial_dict: RadialShadingDictionary = doc.deserialize_object(obj)?;
+                let [x0, y0, r0, x1, y1, r1] = radial_dict.coords;
+                if r0 < 0.0 || r1 < 0.0 {
+                    failure::bail!("radial shading has a negative radius");
+                }
+                ShadingKind::Radial {
+                    start: Circle {
+                        center: euclid::Point2D::new(x0, y0),
+                        radius: r0,
+                    },
+                    end: Circle {
+                        center: euclid::Point2D::new(x1, y1),
+                        radius: r1,
+                    },
+                    domain: radial_dict.domain.unwrap_or([0.0, 1.0]),
+                    extend: radial_dict.extend.unwrap_or([false, false]),
+                }
+            }
+            4..=7 => {
+                // with a function, vertices carry its parameter instead of a colour
+                let color_values = if functions.is_empty() {
+                    color_space.components()
+                } else {
+                    1
+                };
+                ShadingKind::Mesh {
+                    triangles: mesh::decode(doc, obj, dict.shading_type, color_values)?,
+                }
+            }
+            shading_type => failure::bail!("unsupported shading type {}", shading_type),
+        };
+        if functions.is_empty() && dict.shading_type < 4 {
+            failure::bail!("shading type {} requires a function", dict.shading_type);
+        }
+
+        let background = dict
+            .background
+            .map(|components| color_space.to_rgb(&components));
+        let bbox = dict.bbox.map(|[x1, y1, x2, y2]| {
+            euclid::Rect::from_points([euclid::Point2D::new(x1, y1), euclid::Point2D::new(x2, y2)])
+        });
+
+        Ok(Shading {
+            kind,
+            color_space,
+            background,
+            bbox,
+            functions,
+        })
+    }
+
+    /// Returns the colour for the given function input, which is the parameter `t` for
+    /// axial and radial shadings and a point in the domain for function-based shadings.
+    /// For mesh shadings, the input are the colour values of the vertices.
+    pub fn color(&self, input: &[f32]) -> Rgb {
+        let components = if self.functions.is_empty() {
+            input.to_owned()
+        } else if self.functions.len() == 1 {
+            self.functions[0].evaluate(input)
+        } else {
+            self.functions
+                .iter()
+                .map(|function| function.evaluate(input).first().cloned().unwrap_or(0.0))
+                .collect()
+        };
+        self.color_space.to_rgb(&components)
+    }
+
+    /// Returns the parameter `t` of an axial or radial shading at a point in shading
+    /// space, or `None` if the shading doesn't paint the point.
+    pub fn parameter_at(&self, point: euclid::Point2D<f32>) -> Option<f32> {
+        let (s, domain, extend) = match self.kind {
+            ShadingKind::FunctionBased { .. } | ShadingKind::Mesh { .. } => return None,
+            ShadingKind::Axial {
+                start,
+                end,
+                domain,
+                extend,
+            } => {
+                let axis = end - start;
+                let length = axis.square_length();
+                if length == 0.0 {
+                    return None;
+                }
+                ((point - start).dot(axis) / length, domain, extend)
+            }
+            ShadingKind::Radial {
+                start,
+                end,
+                domain,
+                extend,
+            } => {
+                // find the largest s for which the point lies on the circle interpolated
+                // between the start and end circles, and that circle is painted
+                let center = end.center - start.center;
+                let radius = end.radius - start.radius;
+                let offset = point - start.center;
+                let roots = solve_quadratic(
+                    center.square_length() - radius * radius,
+                    offset.dot(center) + start.radius * radius,
+                    offset.square_length() - start.radius * start.radius,
+                );
+                let s = roots.into_iter().find(|s| {
+                    start.radius + s * radius >= 0.0
+                        && (*s >= 0.0 || extend[0])
+                        && (*s <= 1.0 || extend[1])
+                })?;
+                (s, domain, extend)
+            }
+        };
+        if (s < 0.0 && !extend[0]) || (s > 1.0 && !extend[1]) {
+            return None;
+        }
+        let s = s.clamp(0.0, 1.0);
+        Some(domain[0] + s * (domain[1] - domain[0]))
+    }
+}
+
+/// A shading painted with the `sh` operator, or as the fill of a path with a shading
+/// pattern.
+pub struct ShadingObject {
+    /// Transform from shading space to the page.
+    pub transform: euclid::Transform2D<f32>,
+    pub shading: Arc<Shading>,
+    /// Painted behind the shading within the clip, for shading patterns.
+    pub background: Option<Rgb>,
+    pub transparency: TransparencyState,
+    pub clip: Clip,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lopdf::Object;
+
+    fn shading(shading_type: i64, coords: &[i64], extend: bool) -> Shading {
+        let mut function = lopdf::Dictionary::new();
+        function.set("FunctionType", Object::Integer(2));
+        function.set(
+            "Domain",
+            Object::Array(vec![Object::Integer(0), Object::Integer(1)]),
+        );
+        function.set("N", Object::Integer(1));
+        let mut dict = lopdf::Dictionary::new();
+        dict.set("ShadingType", Object::Integer(shading_type));
+        dict.set("ColorSpace", Object::Name(b"DeviceGray".to_vec()));
+        dict.set(
+            "Coords",
+            Object::Array(coords.iter().map(|value| Object::Integer(*value)).collect()),
+        );
+        dict.set(
+            "Extend",
+            Object::Array(vec![Object::Boolean(extend), Object::Boolean(extend)]),
+        );
+        dict.set("Function", function);
+        Shading::try_from_object(&lopdf::Document::new(), &Object::Dictionary(dict)).unwrap()
+    }
+
+    #[test]
+    fn axial_parameter() {
+        let axial = shading(2, &[0, 0, 10, 0], false);
+        assert_eq!(
+            Some(0.25),
+            axial.parameter_at(euclid::Point2D::new(2.5, 7.0))
+        );
+        assert_eq!(None, axial.parameter_at(euclid::Point2D::new(-1.0, 0.0)));
+        assert_eq!(Rgb::new(0.25, 0.25, 0.25), axial.color(&[0.25]));
+
+        let extended = shading(2, &[0, 0, 10, 0], true);
+        assert_eq!(
+            Some(0.0),
+            extended.parameter_at(euclid::Point2D::new(-1.0, 0.0))
+        );
+        assert_eq!(
+            Some(1.0),
+            extended.parameter_at(euclid::Point2D::new(11.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn radial_parameter() {
+        // concentric circles with radii 2 and 6
+        let radial = shading(3, &[0, 0, 2, 0, 0, 6], false);
+        assert_eq!(
+            Some(0.5),
+            radial.parameter_at(euclid::Point2D::new(0.0, 4.0))
+        );
+        assert_eq!(None, radial.parameter_at(euclid::Point2D::new(1.0, 0.0)));
+        assert_eq!(None, radial.parameter_at(euclid::Point2D::new(7.0, 0.0)));
+
+        let extended = shading(3, &[0, 0, 2, 0, 0, 6], true);
+        assert_eq!(
+            Some(0.0),
+            extended.parameter_at(euclid::Point2D::new(1.0, 0.0))
+        );
+
+        // a cone from a point to a circle only covers its inside, even when extended
+        let cone = shading(3, &[0, 0, 0, 4, 0, 2], true);
+        assert_eq!(Some(1.0), cone.parameter_at(euclid::Point2D::new(4.0, 2.0)));
+        assert_eq!(None, cone.parameter_at(euclid::Point2D::new(-1.0, 0.0)));
+    }
+}
@@ -0,0 +1,498 @@
+use failure::Fallible;
+use serde_derive::Deserialize;
+
+use rpdf_lopdf_extra::*;
+
+use crate::data::BitReader;
+use crate::filter;
+
+/// Number of intervals in each direction in which patches are divided into triangles.
+const PATCH_DIVISIONS: usize = 16;
+
+/// Limits the number of triangles a mesh is decoded into, each of which holds the
+/// colour values of its three vertices.
+const MAX_TRIANGLES: usize = 1 << 18;
+
+#[derive(Deserialize)]
+struct MeshShadingDictionary {
+    #[serde(rename = "BitsPerCoordinate")]
+    bits_per_coordinate: u32,
+    #[serde(rename = "BitsPerComponent")]
+    bits_per_component: u32,
+    #[serde(rename = "BitsPerFlag")]
+    bits_per_flag: Option<u32>,
+    #[serde(rename = "Decode")]
+    decode: Vec<f32>,
+    #[serde(rename = "VerticesPerRow")]
+    vertices_per_row: Option<usize>,
+}
+
+/// A vertex of a mesh shading. Its colour values are either colour components or, if
+/// the shading has a function, the parameter passed to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshVertex {
+    pub point: euclid::Point2D<f32>,
+    pub color: Vec<f32>,
+}
+
+pub type Triangle = [MeshVertex; 3];
+
+/// Reads the values of a mesh shading's packed vertex data.
+struct MeshReader<'a> {
+    reader: BitReader<'a>,
+    bits_per_coordinate: u32,
+    bits_per_component: u32,
+    bits_per_flag: u32,
+    decode: Vec<f32>,
+    color_values: usize,
+}
+
+fn warn_truncated() {
+    log::warn!("truncating mesh shading at {} triangles", MAX_TRIANGLES);
+}
+
+fn decode_value(raw: u32, bits: u32, min: f32, max: f32) -> f32 {
+    let max_raw = ((1u64 << bits) - 1) as f64;
+    (f64::from(min) + f64::from(raw) * f64::from(max - min) / max_raw) as f32
+}
+
+impl<'a> MeshReader<'a> {
+    fn new(dict: &MeshShadingDictionary, data: &'a [u8], color_values: usize) -> Fallible<Self> {
+        let bits_per_flag = dict.bits_per_flag.unwrap_or(8);
+        if !(1..=32).contains(&dict.bits_per_coordinate)
+            || !(1..=16).contains(&dict.bits_per_component)
+            || !(1..=8).contains(&bits_per_flag)
+        {
+            failure::bail!("mesh shading has an invalid number of bits per value");
+        }
+        if dict.decode.len() < 4 + color_values * 2 {
+            failure::bail!("mesh shading has an incomplete decode array");
+        }
+        Ok(MeshReader {
+            reader: BitReader::new(data),
+            bits_per_coordinate: dict.bits_per_coordinate,
+            bits_per_component: dict.bits_per_component,
+            bits_per_flag,
+            decode: dict.decode.clone(),
+            color_values,
+        })
+    }
+
+    /// Reads the edge flag, which always starts on a byte boundary.
+    fn read_flag(&mut self) -> Option<u32> {
+        self.reader.align();
+        self.reader.read(self.bits_per_flag)
+    }
+
+    fn read_point(&mut self) -> Option<euclid::Point2D<f32>> {
+        let bits = self.bits_per_coordinate;
+        let x = self.reader.read(bits)?;
+        let y = self.reader.read(bits)?;
+        Some(euclid::Point2D::new(
+            decode_value(x, bits, self.decode[0], self.decode[1]),
+            decode_value(y, bits, self.decode[2], self.decode[3]),
+        ))
+    }
+
+    fn read_color(&mut self) -> Option<Vec<f32>> {
+        let bits = self.bits_per_component;
+        (0..self.color_values)
+            .map(|index| {
+                let raw = self.reader.read(bits)?;
+                let range = &self.decode[4 + index * 2..6 + index * 2];
+                Some(decode_value(raw, bits, range[0], range[1]))
+            })
+            .collect()
+    }
+
+    fn read_vertex(&mut self) -> Option<MeshVertex> {
+        let point = self.read_point()?;
+        let color = self.read_color()?;
+        Some(MeshVertex { point, color })
+    }
+}
+
+/// Decodes a free-form (type 4) triangle mesh, where the edge flag of each vertex
+/// tells whether it starts a new triangle or extends the previous one.
+fn decode_free_form(reader: &mut MeshReader) -> Vec<Triangle> {
+    let mut triangles: Vec<Triangle> = Vec::new();
+    let mut pending = Vec::with_capacity(3);
+    while let Some(flag) = reader.read_flag() {
+        if triangles.len() >= MAX_TRIANGLES {
+            warn_truncated();
+            break;
+        }
+        let vertex = match reader.read_vertex() {
+            Some(vertex) => vertex,
+            None => break,
+        };
+        // the flags of the second and third vertex of a new triangle are ignored
+        if !pending.is_empty() || flag == 0 {
+            pending.push(vertex);
+            if pending.len() == 3 {
+                let c = pending.pop().unwrap();
+                let b = pending.pop().unwrap();
+                let a = pending.pop().unwrap();
+                triangles.push([a, b, c]);
+            }
+            continue;
+        }
+        let [a, b, c] = match triangles.last() {
+            Some(triangle) => triangle.clone(),
+            None => {
+                log::warn!("triangle mesh starts with edge flag {}", flag);
+                break;
+            }
+        };
+        match flag {
+            1 => triangles.push([b, c, vertex]),
+            2 => triangles.push([a, c, vertex]),
+            _ => {
+                log::warn!("invalid triangle mesh edge flag {}", flag);
+                break;
+            }
+        }
+    }
+    triangles
+}
+
+/// Decodes a lattice-form (type 5) triangle mesh, made of rows of vertices.
+fn decode_lattice_form(reader: &mut MeshReader, vertices_per_row: usize) -> Vec<Triangle> {
+    let mut triangles = Vec::new();
+    let mut previous_row: Option<Vec<MeshVertex>> = None;
+    loop {
+        let row = (0..vertices_per_row)
+            .map(|_| reader.read_vertex())
+            .collect::<Option<Vec<_>>>();
+        let row = match row {
+            Some(row) => row,
+            None => break,
+        };
+        if let Some(previous_row) = previous_row {
+            if triangles.len() + 2 * (vertices_per_row - 1) > MAX_TRIANGLES {
+                warn_truncated();
+                break;
+            }
+            for column in 0..vertices_per_row - 1 {
+                let (a, b) = (&previous_row[column], &previous_row[column + 1]);
+                let (c, d) = (&row[column], &row[column + 1]);
+                triangles.push([a.clone(), b.clone(), c.clone()]);
+                triangles.push([b.clone(), d.clone(), c.clone()]);
+            }
+        }
+        previous_row = Some(row);
+    }
+    triangles
+}
+
+/// Returns the Bernstein polynomials of degree three at `t`.
+fn bernstein(t: f32) -> [f32; 4] {
+    let s = 1.0 - t;
+    [s * s * s, 3.0 * t * s * s, 3.0 * t * t * s, t * t * t]
+}
+
+/// A tensor-product patch, with control points `points[i][j]` and the colours of the
+/// corners at `(u, v)` = (0, 0), (0, 1), (1, 1) and (1, 0).
+struct Patch {
+    points: [[euclid::Point2D<f32>; 4]; 4],
+    colors: [Vec<f32>; 4],
+}
+
+impl Patch {
+    /// Completes the interior control points of a Coons patch, which is the tensor-
+    /// product patch with the same boundary.
+    fn complete_coons(&mut self) {
+        let p = |(i, j): (usize, usize)| self.points[i][j].to_vector();
+        let interior = |corner, near: [_; 2], far: [_; 2], across: [_; 2], opposite| {
+            let sum = p(corner) * -4.0 + (p(near[0]) + p(near[1])) * 6.0
+                - (p(far[0]) + p(far[1])) * 2.0
+                + (p(across[0]) + p(across[1])) * 3.0
+                - p(opposite);
+            (sum / 9.0).to_point()
+        };
+        let p11 = interior(
+            (0, 0),
+            [(0, 1), (1, 0)],
+            [(0, 3), (3, 0)],
+            [(3, 1), (1, 3)],
+            (3, 3),
+        );
+        let p12 = interior(
+            (0, 3),
+            [(0, 2), (1, 3)],
+            [(0, 0), (3, 3)],
+            [(3, 2), (1, 0)],
+            (3, 0),
+        );
+        let p21 = interior(
+            (3, 0),
+            [(3, 1), (2, 0)],
+            [(3, 3), (0, 0)],
+            [(0, 1), (2, 3)],
+            (0, 3),
+        );
+        let p22 = interior(
+            (3, 3),
+            [(3, 2), (2, 3)],
+            [(3, 0), (0, 3)],
+            [(2, 0), (0, 2)],
+            (0, 0),
+        );
+        self.points[1][1] = p11;
+        self.points[1][2] = p12;
+        self.points[2][1] = p21;
+        self.points[2][2] = p22;
+    }
+
+    fn vertex_at(&self, u: f32, v: f32) -> MeshVertex {
+        let (bu, bv) = (bernstein(u), bernstein(v));
+        let mut point = euclid::Vector2D::zero();
+        for (i, row) in self.points.iter().enumerate() {
+            for (j, control) in row.iter().enumerate() {
+                point += control.to_vector() * (bu[i] * bv[j]);
+            }
+        }
+        let weights = [(1.0 - u) * (1.0 - v), (1.0 - u) * v, u * v, u * (1.0 - v)];
+        let color = (0..self.colors[0].len())
+            .map(|index| {
+                self.colors
+                    .iter()
+                    .zip(weights.iter())
+                    .map(|(color, weight)| color[index] * weight)
+                    .sum()
+            })
+            .collect();
+        MeshVertex {
+            point: point.to_point(),
+            color,
+        }
+    }
+
+    fn triangulate(&self, triangles: &mut Vec<Triangle>) {
+        let step = 1.0 / PATCH_DIVISIONS as f32;
+        let grid = (0..=PATCH_DIVISIONS)
+            .map(|i| {
+                (0..=PATCH_DIVISIONS)
+                    .map(|j| self.vertex_at(i as f32 * step, j as f32 * step))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        for i in 0..PATCH_DIVISIONS {
+            for j in 0..PATCH_DIVISIONS {
+                let (a, b) = (&grid[i][j], &grid[i][j + 1]);
+                let (c, d) = (&grid[i + 1][j], &grid[i + 1][j + 1]);
+                triangles.push([a.clone(), b.clone(), c.clone()]);
+                triangles.push([b.clone(), d.clone(), c.clone()]);
+            }
+        }
+    }
+}
+
+/// Positions of the control points in the order they appear in the data: first the
+/// boundary starting at `p00`, then the interior points of tensor-product patches.
+const PATCH_POINT_ORDER: [(usize, usize); 16] = [
+    (0, 0),
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (1, 3),
+    (2, 3),
+    (3, 3),
+    (3, 2),
+    (3, 1),
+    (3, 0),
+    (2, 0),
+    (1, 0),
+    (1, 1),
+    (1, 2),
+    (2, 2),
+    (2, 1),
+];
+
+/// Decodes Coons (type 6) or tensor-product (type 7) patch meshes into triangles.
+/// Patches with a non-zero edge flag share an edge with the previous patch.
+fn decode_patches(reader: &mut MeshReader, tensor_product: bool) -> Vec<Triangle> {
+    let point_count = if tensor_product { 16 } else { 12 };
+    let mut triangles = Vec::new();
+    let mut previous: Option<Patch> = None;
+    while let Some(flag) = reader.read_flag() {
+        if triangles.len() + PATCH_DIVISIONS * PATCH_DIVISIONS * 2 > MAX_TRIANGLES {
+            warn_truncated();
+            break;
+        }
+        let mut patch = Patch {
+            points: [[euclid::Point2D::zero(); 4]; 4],
+            colors: [vec![], vec![], vec![], vec![]],
+        };
+        // indices of the shared boundary points and corner colours of the previous patch
+        let shared: (&[usize], &[usize]) = match flag {
+            0 => (&[], &[]),
+            1 => (&[3, 4, 5, 6], &[1, 2]),
+            2 => (&[6, 7, 8, 9], &[2, 3]),
+            3 => (&[9, 10, 11, 0], &[3, 0]),
+            _ => {
+                log::warn!("invalid patch mesh edge flag {}", flag);
+                break;
+            }
+        };
+        if !shared.0.is_empty() {
+            let previous = match previous {
+                Some(ref previous) => previous,
+                None => {
+                    log::warn!("patch mesh starts with edge flag {}", flag);
+                    break;
+                }
+            };
+            for (index, shared_index) in shared.0.iter().enumerate() {
+                let (i, j) = PATCH_POINT_ORDER[index];
+                let (si, sj) = PATCH_POINT_ORDER[*shared_index];
+                patch.points[i][j] = previous.points[si][sj];
+            }
+            for (index, shared_index) in shared.1.iter().enumerate() {
+                patch.colors[index] = previous.colors[*shared_index].clone();
+            }
+        }
+
+        let points = (shared.0.len()..point_count)
+            .map(|index| Some((PATCH_POINT_ORDER[index], reader.read_point()?)))
+            .collect::<Option<Vec<_>>>();
+        let colors = (shared.1.len()..4)
+            .map(|index| Some((index, reader.read_color()?)))
+            .collect::<Option<Vec<_>>>();
+        let (points, colors) = match (points, colors) {
+            (Some(points), Some(colors)) => (points, colors),
+            _ => break,
+        };
+        for ((i, j), point) in points {
+            patch.points[i][j] = point;
+        }
+        for (index, color) in colors {
+            patch.colors[index] = color;
+        }
+        if !tensor_product {
+            patch.complete_coons();
+        }
+
+        patch.triangulate(&mut triangles);
+        previous = Some(patch);
+    }
+    triangles
+}
+
+/// Decodes the vertex data of a mesh shading (types 4 to 7) into triangles.
+pub fn decode(
+    doc: &lopdf::Document,
+    obj: &lopdf::Object,
+    shading_type: u32,
+    color_values: usize,
+) -> Fallible<Vec<Triangle>> {
+    let dict: MeshShadingDictionary = doc.deserialize_object(obj)?;
+    let stream = doc.resolve_object(obj)?.try_as_stream()?;
+    let data = filter::decode(doc, &stream.dict, &stream.content)?;
+    let mut reader = MeshReader::new(&dict, &data, color_values)?;
+    let triangles = match shading_type {
+        4 => decode_free_form(&mut reader),
+        5 => match dict.vertices_per_row {
+            Some(vertices_per_row) if vertices_per_row >= 2 => {
+                decode_lattice_form(&mut reader, vertices_per_row)
+            }
+            _ => failure::bail!("lattice-form mesh has an invalid number of vertices per row"),
+        },
+        6 => decode_patches(&mut reader, false),
+        7 => decode_patches(&mut reader, true),
+        _ => failure::bail!("shading type {} is not a mesh", shading_type),
+    };
+    Ok(triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader(data: &[u8], color_values: usize) -> MeshReader<'_> {
+        let dict = MeshShadingDictionary {
+            bits_per_coordinate: 8,
+            bits_per_component: 8,
+            bits_per_flag: Some(8),
+            decode: vec![0.0, 255.0, 0.0, 255.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0],
+            vertices_per_row: None,
+        };
+        MeshReader::new(&dict, data, color_values).unwrap()
+    }
+
+    #[test]
+    fn free_form_triangles_share_edges() {
+        #[rustfmt::skip]
+        let data = [
+            0, 0, 0, 255,
+            0, 10, 0, 0,
+            0, 0, 10, 0,
+            1, 10, 10, 255,
+            2, 20, 20, 0,
+        ];
+        let triangles = decode_free_form(&mut reader(&data, 1));
+        assert_eq!(3, triangles.len());
+        let points = |triangle: &Triangle| {
+            triangle
+                .iter()
+                .map(|vertex| (vertex.point.x, vertex.point.y))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![(10.0, 0.0), (0.0, 10.0), (10.0, 10.0)],
+            points(&triangles[1])
+        );
+        assert_eq!(
+            vec![(10.0, 0.0), (10.0, 10.0), (20.0, 20.0)],
+            points(&triangles[2])
+        );
+        assert_eq!(vec![1.0], triangles[1][2].color);
+    }
+
+    #[test]
+    fn free_form_is_truncated() {
+        let mut data = vec![0, 0, 0, 0, 0, 10, 0, 0, 0, 0, 10, 0];
+        for _ in 0..MAX_TRIANGLES {
+            data.extend_from_slice(&[1, 10, 10, 0]);
+        }
+        let triangles = decode_free_form(&mut reader(&data, 1));
+        assert_eq!(MAX_TRIANGLES, triangles.len());
+    }
+
+    #[test]
+    fn lattice_form_rows() {
+        #[rustfmt::skip]
+        let data = [
+            0, 0, 0, 10, 0, 0, 20, 0, 0,
+            0, 10, 0, 10, 10, 0, 20, 10, 0,
+        ];
+        let triangles = decode_lattice_form(&mut reader(&data, 1), 3);
+        assert_eq!(4, triangles.len());
+    }
+
+    #[test]
+    fn coons_patch_with_straight_edges_is_bilinear() {
+        // a square with straight edges and alternating black and white corners
+        #[rustfmt::skip]
+        let data = [
+            0,
+            0, 0, 0, 10, 0, 20, 0, 30,
+            10, 30, 20, 30, 30, 30,
+            30, 20, 30, 10, 30, 0,
+            20, 0, 10, 0,
+            0, 0, 0, 255, 255, 255, 0, 0, 0, 255, 255, 255,
+        ];
+        let mut reader = reader(&data, 3);
+        let triangles = decode_patches(&mut reader, false);
+        assert_eq!(PATCH_DIVISIONS * PATCH_DIVISIONS * 2, triangles.len());
+        // the middle of the patch has the average colour and position
+        let vertex = triangles
+            .iter()
+            .flat_map(|triangle| triangle.iter())
+            .find(|vertex| (vertex.point - euclid::Point2D::new(15.0, 15.0)).length() < 1e-4)
+            .unwrap();
+        for value in vertex.color.iter() {
+            assert!((value - 0.5).abs() < 1e-6);
+        }
+    }
+}
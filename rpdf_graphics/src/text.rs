@@ -1,15 +1,57 @@
 use std::collections::HashMap;
+use std::rc::Rc;
 use std::sync::Arc;
 
 use failure::Fallible;
 
 use rpdf_lopdf_extra::DocumentExt;
 
+use crate::clip::Clip;
+use crate::color::Rgb;
 use crate::data::Name;
-use crate::font::{FontMap, LoadedFont};
+use crate::font::{Font, FontMap, LoadedFont};
+use crate::transparency::TransparencyState;
 
 use super::GraphicsState;
 
+/// How the glyphs of text are painted. Clipping to text isn't supported, so the modes
+/// that add glyphs to the clipping path (4 to 7) only paint them as the corresponding
+/// modes 0 to 3 do.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderMode {
+    Fill,
+    Stroke,
+    FillStroke,
+    Invisible,
+}
+
+impl RenderMode {
+    fn from_operand(mode: i64) -> Fallible<Self> {
+        Ok(match mode {
+            0 | 4 => RenderMode::Fill,
+            1 | 5 => RenderMode::Stroke,
+            2 | 6 => RenderMode::FillStroke,
+            3 | 7 => RenderMode::Invisible,
+            _ => failure::bail!("invalid text rendering mode {}", mode),
+        })
+    }
+
+    pub fn fills(self) -> bool {
+        matches!(self, RenderMode::Fill | RenderMode::FillStroke)
+    }
+
+    pub fn strokes(self) -> bool {
+        matches!(self, RenderMode::Stroke | RenderMode::FillStroke)
+    }
+
+    /// Whether glyphs are painted at all. Text that isn't, such as the recognised text
+    /// over a scanned page, is still kept for extraction.
+    pub fn is_visible(self) -> bool {
+        self.fills() || self.strokes()
+    }
+}
+
+#[derive(Clone)]
 pub struct TextState {
     char_spacing: f32,
     word_spacing: f32,
@@ -17,6 +59,9 @@ pub struct TextState {
     text_font: Vec<u8>,
     text_font_size: f32,
     text_leading: f32,
+    render_mode: RenderMode,
+    /// The distance that the baseline is moved up by, in unscaled text space units.
+    rise: f32,
 }
 
 impl Default for TextState {
@@ -28,11 +73,22 @@ impl Default for TextState {
             text_font: Vec::new(),
             text_font_size: 0.0,
             text_leading: 0.0,
+            render_mode: RenderMode::Fill,
+            rise: 0.0,
         }
     }
 }
 
 impl TextState {
+    pub fn font_name(&self) -> &[u8] {
+        &self.text_font
+    }
+
+    pub fn set_font(&mut self, name: Vec<u8>, size: f32) {
+        self.text_font = name;
+        self.text_font_size = size;
+    }
+
     pub fn handle_operation(
         &mut self,
         document: &lopdf::Document,
@@ -51,38 +107,60 @@ impl TextState {
                 let scale: f32 = document.deserialize_object(&op.operands[0])?;
                 self.horizontal_scaling = scale / 100.0;
             }
-            "TL" => match op.operands[0] {
-                lopdf::Object::Real(leading) => {
-                    self.text_leading = leading as f32;
-                }
-                lopdf::Object::Integer(leading) => {
-                    self.text_leading -= leading as f32;
-                }
-                _ => failure::bail!("unexpected operand {:?}", op),
-            },
+            "TL" => {
+                let leading = document.deserialize_object(&op.operands[0])?;
+                self.text_leading = leading;
+            }
             "Tf" => {
                 let Name(font_name) = document.deserialize_object(&op.operands[0])?;
                 let font_size = document.deserialize_object(&op.operands[1])?;
-                self.text_font = font_name;
-                self.text_font_size = font_size;
+                self.set_font(font_name, font_size);
+            }
+            "Tr" => {
+                let mode = document.deserialize_object(&op.operands[0])?;
+                if (4..=7).contains(&mode) {
+                    log::debug!("clipping to text is not supported");
+                }
+                match RenderMode::from_operand(mode) {
+                    Ok(render_mode) => self.render_mode = render_mode,
+                    Err(err) => log::warn!("keeping the text rendering mode: {}", err),
+                }
+            }
+            "Ts" => {
+                let rise = document.deserialize_object(&op.operands[0])?;
+                self.rise = rise;
             }
             _ => {}
         }
         Ok(())
     }
+
+    /// Returns the matrix that maps glyph positions, in units of font size relative to
+    /// the text position, to text space. Followed by the text matrix and the CTM, it
+    /// makes up the text rendering matrix.
+    fn font_matrix(&self) -> euclid::Transform2D<f32> {
+        euclid::Transform2D::row_major(
+            self.text_font_size * self.horizontal_scaling,
+            0.0,
+            0.0,
+            self.text_font_size,
+            0.0,
+            self.rise,
+        )
+    }
 }
 
-pub struct TextObjectBuilder<'a> {
+pub struct TextObjectBuilder {
     document: Arc<lopdf::Document>,
-    font_map: &'a FontMap,
-    loaded_fonts: HashMap<Vec<u8>, LoadedFont>,
+    font_map: Rc<FontMap>,
+    loaded_fonts: HashMap<Vec<u8>, Option<LoadedFont>>,
     text_matrix: euclid::Transform2D<f32>,
     text_line_matrix: euclid::Transform2D<f32>,
     fragments: Vec<TextFragment>,
 }
 
-impl<'a> TextObjectBuilder<'a> {
-    pub fn new(document: Arc<lopdf::Document>, font_map: &'a FontMap) -> Self {
+impl TextObjectBuilder {
+    pub fn new(document: Arc<lopdf::Document>, font_map: Rc<FontMap>) -> Self {
         Self {
             document,
             font_map,
@@ -93,43 +171,89 @@ impl<'a> TextObjectBuilder<'a> {
         }
     }
 
-    fn flush_segment(&mut self, text_state: &TextState, chars: &[u8]) {
-        let font = self.font_map.get(&text_state.text_font).unwrap();
+    fn flush_segment(&mut self, state: &GraphicsState, chars: &[u8]) {
+        let text_state = &state.text_state;
+        // text shown before a font is set, or with one that failed to load, can't be
+        // positioned
+        let font = match self.font_map.get(&text_state.text_font) {
+            Some(font) => font.clone(),
+            None => {
+                log::warn!(
+                    "skipping text shown with font /{}, which is not loaded",
+                    String::from_utf8_lossy(&text_state.text_font)
+                );
+                return;
+            }
+        };
         let loaded_font = self
             .loaded_fonts
             .entry(text_state.text_font.clone())
-            .or_insert_with(|| font.load().unwrap());
+            .or_insert_with(|| {
+                if !font.has_program() {
+                    return None;
+                }
+                font.load()
+                    .map_err(|err| log::warn!("failed to load font program: {}", err))
+                    .ok()
+            })
+            .as_ref();
 
+        // the text rise moves glyphs away from the baseline without moving the text
+        // position
+        let rise = euclid::Transform2D::create_translation(0.0, text_state.rise);
         let mut fragment = TextFragment {
-            transform: self.text_matrix,
+            transform: rise.post_mul(&self.text_matrix).post_mul(&state.transform),
             font_name: text_state.text_font.clone(),
+            font: font.clone(),
             font_size: text_state.text_font_size,
+            color: state.color_state.fill.to_rgb(),
+            stroke_color: state.color_state.stroke.to_rgb(),
+            render_mode: text_state.render_mode,
+            rise: text_state.rise,
             line_height: text_state.text_leading,
             glyphs: Vec::with_capacity(chars.len()),
         };
 
-        for c in chars {
-            let index;
-            if let Some(glyph_name) = font.decode_char(*c) {
-                index = loaded_font.glyph_index_for_name(glyph_name.as_bytes());
+        let font_size = text_state.text_font_size;
+        let font_matrix = text_state.font_matrix();
+        for code in font.char_codes(chars) {
+            let index = font.glyph_index(loaded_font, code);
+            // word spacing applies to the single-byte code 32 only, not to multi-byte
+            // codes with the same value
+            let word_spacing = if code.length == 1 && code.code == 32 {
+                text_state.word_spacing
             } else {
-                index = loaded_font.glyph_index_for_char(*c as char);
-            }
+                0.0
+            };
 
-            let origin = euclid::Point2D::zero();
-            let w0 = font.width_for_char(*c) as f32;
-            let tx = (w0 * text_state.text_font_size
-                + text_state.char_spacing
-                + text_state.word_spacing)
-                * text_state.horizontal_scaling;
+            let (origin, advance, translation) = match font.vertical_metrics(code) {
+                Some(metrics) => {
+                    // in vertical writing the text position is the glyph's vertical origin
+                    let origin =
+                        euclid::Point2D::new(-metrics.origin.0 as f32, -metrics.origin.1 as f32);
+                    let ty =
+                        metrics.advance as f32 * font_size + text_state.char_spacing + word_spacing;
+                    (origin, ty, euclid::Transform2D::create_translation(0.0, ty))
+                }
+                None => {
+                    let w0 = font.width(code) as f32;
+                    let tx = (w0 * font_size + text_state.char_spacing + word_spacing)
+                        * text_state.horizontal_scaling;
+                    let translation = euclid::Transform2D::create_translation(tx, 0.0);
+                    (euclid::Point2D::zero(), tx, translation)
+                }
+            };
 
+            let rendering_matrix = font_matrix
+                .post_mul(&self.text_matrix)
+                .post_mul(&state.transform);
             fragment.glyphs.push(TextGlyph {
                 index,
-                origin: self.text_matrix.transform_point(&origin),
-                advance: tx,
+                origin: rendering_matrix.transform_point(&origin),
+                advance,
+                horizontal_scale: font.glyph_scale(code) as f32 * text_state.horizontal_scaling,
             });
 
-            let translation = euclid::Transform2D::create_translation(tx, 0.0);
             self.text_matrix = self.text_matrix.pre_mul(&translation);
         }
 
@@ -144,8 +268,13 @@ impl<'a> TextObjectBuilder<'a> {
     }
 
     fn apply_adjustment(&mut self, text_state: &TextState, adjustment: f32) {
-        let tx = (adjustment / 1000.0) * text_state.text_font_size * text_state.horizontal_scaling;
-        let translation = euclid::Transform2D::create_translation(-tx, 0.0);
+        let amount = (adjustment / 1000.0) * text_state.text_font_size;
+        let font = self.font_map.get(&text_state.text_font);
+        let translation = if font.filter(|font| font.is_vertical()).is_some() {
+            euclid::Transform2D::create_translation(0.0, -amount)
+        } else {
+            euclid::Transform2D::create_translation(-amount * text_state.horizontal_scaling, 0.0)
+        };
         self.text_matrix = self.text_matrix.pre_mul(&translation);
     }
 
@@ -178,7 +307,7 @@ impl<'a> TextObjectBuilder<'a> {
                 self.text_line_matrix = transform;
             }
             "T*" => {
-                self.apply_translation(0.0, state.text_state.text_leading);
+                self.apply_translation(0.0, -state.text_state.text_leading);
             }
             _ => {}
         }
@@ -193,14 +322,14 @@ impl<'a> TextObjectBuilder<'a> {
         match op.operator.as_str() {
             "Tj" => match op.operands[0] {
                 lopdf::Object::String(ref s, _) => {
-                    self.flush_segment(&state.text_state, s);
+                    self.flush_segment(state, s);
                 }
                 _ => failure::bail!("unexpected operand {:?}", op),
             },
             "'" => match op.operands[0] {
                 lopdf::Object::String(ref s, _) => {
-                    self.apply_translation(0.0, state.text_state.text_leading);
-                    self.flush_segment(&state.text_state, s);
+                    self.apply_translation(0.0, -state.text_state.text_leading);
+                    self.flush_segment(state, s);
                 }
                 _ => failure::bail!("unexpected operand {:?}", op),
             },
@@ -211,8 +340,8 @@ impl<'a> TextObjectBuilder<'a> {
                     lopdf::Object::String(ref s, _) => {
                         state.text_state.word_spacing = word_spacing;
                         state.text_state.char_spacing = char_spacing;
-                        self.apply_translation(0.0, state.text_state.text_leading);
-                        self.flush_segment(&state.text_state, s);
+                        self.apply_translation(0.0, -state.text_state.text_leading);
+                        self.flush_segment(state, s);
                     }
                     _ => failure::bail!("unexpected operand {:?}", op),
                 }
@@ -222,7 +351,7 @@ impl<'a> TextObjectBuilder<'a> {
                     for part in parts {
                         match part {
                             lopdf::Object::String(ref s, _) => {
-                                self.flush_segment(&state.text_state, s);
+                                self.flush_segment(state, s);
                             }
                             lopdf::Object::Real(amount) => {
                                 self.apply_adjustment(&state.text_state, *amount as f32);
@@ -253,21 +382,35 @@ impl<'a> TextObjectBuilder<'a> {
         }
     }
 
-    pub fn build(mut self) -> TextObject {
+    pub fn fragments(&self) -> &[TextFragment] {
+        &self.fragments
+    }
+
+    pub fn build(mut self, state: &GraphicsState) -> TextObject {
         TextObject {
             fragments: self.fragments.split_off(0),
+            transparency: state.transparency.clone(),
+            clip: state.clip.clone(),
         }
     }
 }
 
 pub struct TextObject {
     pub fragments: Vec<TextFragment>,
+    pub transparency: TransparencyState,
+    pub clip: Clip,
 }
 
 pub struct TextFragment {
     pub transform: euclid::Transform2D<f32>,
     pub font_name: Vec<u8>,
+    pub font: Arc<Font>,
     pub font_size: f32,
+    pub color: Rgb,
+    pub stroke_color: Rgb,
+    pub render_mode: RenderMode,
+    /// The text rise, which `transform` and the glyph origins already include.
+    pub rise: f32,
     pub line_height: f32,
     pub glyphs: Vec<TextGlyph>,
 }
@@ -276,4 +419,8 @@ pub struct TextGlyph {
     pub index: u32,
     pub origin: euclid::Point2D<f32>,
     pub advance: f32,
+    /// Scales the glyph horizontally, by the horizontal scaling of the text and so that
+    /// glyphs of a substitute font program are as wide as those of the font it stands in
+    /// for.
+    pub horizontal_scale: f32,
 }
@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use crate::clip::Clip;
+use crate::color::Rgb;
+use crate::function::Function;
+use crate::GraphicsObject;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+}
+
+impl BlendMode {
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        match name {
+            // `Compatible` is a deprecated synonym of `Normal`
+            b"Normal" | b"Compatible" => Some(BlendMode::Normal),
+            b"Multiply" => Some(BlendMode::Multiply),
+            b"Screen" => Some(BlendMode::Screen),
+            b"Overlay" => Some(BlendMode::Overlay),
+            b"Darken" => Some(BlendMode::Darken),
+            b"Lighten" => Some(BlendMode::Lighten),
+            b"ColorDodge" => Some(BlendMode::ColorDodge),
+            b"ColorBurn" => Some(BlendMode::ColorBurn),
+            b"HardLight" => Some(BlendMode::HardLight),
+            b"SoftLight" => Some(BlendMode::SoftLight),
+            b"Difference" => Some(BlendMode::Difference),
+            b"Exclusion" => Some(BlendMode::Exclusion),
+            b"Hue" => Some(BlendMode::Hue),
+            b"Saturation" => Some(BlendMode::Saturation),
+            b"Color" => Some(BlendMode::Color),
+            b"Luminosity" => Some(BlendMode::Luminosity),
+            _ => None,
+        }
+    }
+}
+
+/// The transparency parameters of the graphics state, usually set through `gs`.
+#[derive(Clone)]
+pub struct TransparencyState {
+    pub stroke_alpha: f32,
+    pub fill_alpha: f32,
+    pub blend_mode: BlendMode,
+    pub soft_mask: Option<Arc<SoftMask>>,
+    /// Whether the alpha values are shape rather than opacity values (`AIS`).
+    pub alpha_is_shape: bool,
+}
+
+impl Default for TransparencyState {
+    fn default() -> Self {
+        TransparencyState {
+            stroke_alpha: 1.0,
+            fill_alpha: 1.0,
+            blend_mode: BlendMode::Normal,
+            soft_mask: None,
+            alpha_is_shape: false,
+        }
+    }
+}
+
+impl TransparencyState {
+    /// Returns whether objects painted with this state are simply drawn over the page.
+    pub fn is_opaque(&self) -> bool {
+        self.stroke_alpha >= 1.0
+            && self.fill_alpha >= 1.0
+            && self.blend_mode == BlendMode::Normal
+            && self.soft_mask.is_none()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SoftMaskType {
+    Alpha,
+    Luminosity,
+}
+
+/// A soft mask set through the `SMask` entry of an ExtGState, whose values are derived
+/// from painting a transparency group.
+pub struct SoftMask {
+    pub subtype: SoftMaskType,
+    pub group: GroupObject,
+    /// The colour the group is composited over for luminosity masks, also used outside
+    /// of the group's bounding box.
+    pub backdrop: Rgb,
+    /// Transfer function applied to the mask values, if not the identity.
+    pub transfer: Option<Function>,
+}
+
+/// The contents of a transparency group XObject, which are composited with each other
+/// before the result is painted onto the page.
+pub struct GroupObject {
+    pub objects: Vec<GraphicsObject>,
+    /// Transform of the group's form space, in which `bbox` is given.
+    pub transform: euclid::Transform2D<f32>,
+    pub bbox: euclid::Rect<f32>,
+    pub isolated: bool,
+    pub knockout: bool,
+    /// The transparency parameters used to paint the group as a whole.
+    pub transparency: TransparencyState,
+    pub clip: Clip,
+}
@@ -0,0 +1,439 @@
+use rpdf_graphics::clip::Clip;
+use rpdf_graphics::color::Rgb;
+use rpdf_graphics::pattern::TilingObject;
+use rpdf_graphics::transparency::{GroupObject, SoftMask, SoftMaskType};
+use rpdf_graphics::GraphicsObject;
+
+use super::raster::{self, Bitmap, CoverageMask};
+use super::shading;
+
+/// Maximum width or height of the rasterized cell of a tiling pattern, in pixels.
+const MAX_CELL_SIZE: f32 = 1024.0;
+
+/// Maximum number of overlapping tiles of a tiling pattern painted in each direction.
+const MAX_OVERLAPPING_TILES: i32 = 16;
+
+/// A software canvas with premultiplied RGBA pixels, used to paint the groups of soft
+/// masks and the cells of tiling patterns, whose results are needed as pixels rather
+/// than as webrender primitives.
+pub struct Canvas {
+    pub origin: euclid::Point2D<i32>,
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<[f32; 4]>,
+}
+
+impl Canvas {
+    /// Creates a transparent canvas covering `bounds`, rounded out to whole pixels.
+    pub fn new(bounds: &euclid::Rect<f32>) -> Self {
+        let min_x = bounds.min_x().floor() as i32;
+        let min_y = bounds.min_y().floor() as i32;
+        let width = (bounds.max_x().ceil() as i32 - min_x).max(0) as usize;
+        let height = (bounds.max_y().ceil() as i32 - min_y).max(0) as usize;
+        Canvas {
+            origin: euclid::Point2D::new(min_x, min_y),
+            width,
+            height,
+            pixels: vec![[0.0; 4]; width * height],
+        }
+    }
+
+    pub fn bounds(&self) -> euclid::Rect<f32> {
+        euclid::Rect::new(
+            euclid::Point2D::new(self.origin.x as f32, self.origin.y as f32),
+            euclid::Size2D::new(self.width as f32, self.height as f32),
+        )
+    }
+
+    /// Composites a premultiplied colour over the pixel at the given canvas coordinates.
+    fn blend(&mut self, x: i32, y: i32, source: [f32; 4]) {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return;
+        }
+        let pixel = &mut self.pixels[y as usize * self.width + x as usize];
+        let remaining = 1.0 - source[3];
+        for (value, source) in pixel.iter_mut().zip(source.iter()) {
+            *value = source + *value * remaining;
+        }
+    }
+
+    /// Returns the premultiplied colour of the pixel at the given canvas coordinates.
+    fn pixel(&self, x: i32, y: i32) -> Option<[f32; 4]> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+        Some(self.pixels[y as usize * self.width + x as usize])
+    }
+
+    fn to_bitmap(&self) -> Bitmap {
+        let mut data = Vec::with_capacity(self.pixels.len() * 4);
+        for pixel in &self.pixels {
+            for value in [pixel[2], pixel[1], pixel[0], pixel[3]].iter() {
+                data.push((value.clamp(0.0, 1.0) * 255.0).round() as u8);
+            }
+        }
+        Bitmap {
+            origin: self.origin,
+            width: self.width,
+            height: self.height,
+            data,
+        }
+    }
+
+    fn clip_value(&self, clip: &Option<Vec<f32>>, x: i32, y: i32) -> f32 {
+        match *clip {
+            Some(ref coverage) if x >= 0 && y >= 0 => {
+                let (x, y) = (x as usize, y as usize);
+                if x < self.width && y < self.height {
+                    coverage[y * self.width + x]
+                } else {
+                    0.0
+                }
+            }
+            Some(_) => 0.0,
+            None => 1.0,
+        }
+    }
+
+    /// Returns the coverage of a clip for every pixel of the canvas, or `None` if the
+    /// clip doesn't restrict painting.
+    fn clip_coverage(
+        &self,
+        clip: &Clip,
+        page_transform: &euclid::Transform2D<f32>,
+    ) -> Option<Vec<f32>> {
+        if clip.is_empty() {
+            return None;
+        }
+        let mut coverage = vec![1.0; self.width * self.height];
+        for clip_path in clip.paths() {
+            let mut path_coverage = vec![0.0; self.width * self.height];
+            if let Some(mask) =
+                raster::rasterize_clip_path(clip_path, page_transform, &self.bounds())
+            {
+                for index in 0..mask.coverage.len() {
+                    let x = mask.origin.x - self.origin.x + (index % mask.width) as i32;
+                    let y = mask.origin.y - self.origin.y + (index / mask.width) as i32;
+                    if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+                        path_coverage[y as usize * self.width + x as usize] = mask.value(index);
+                    }
+                }
+            }
+            for (value, path_value) in coverage.iter_mut().zip(path_coverage) {
+                *value *= path_value;
+            }
+        }
+        Some(coverage)
+    }
+
+    fn fill(&mut self, mask: &CoverageMask, rgb: Rgb, alpha: f32, clip: &Option<Vec<f32>>) {
+        for index in 0..mask.coverage.len() {
+            let x = mask.origin.x - self.origin.x + (index % mask.width) as i32;
+            let y = mask.origin.y - self.origin.y + (index / mask.width) as i32;
+            let a = mask.value(index) * alpha * self.clip_value(clip, x, y);
+            if a > 0.0 {
+                self.blend(x, y, [rgb.red * a, rgb.green * a, rgb.blue * a, a]);
+            }
+        }
+    }
+
+    fn draw_bitmap(&mut self, bitmap: &Bitmap, alpha: f32, clip: &Option<Vec<f32>>) {
+        for (index, pixel) in bitmap.data.chunks(4).enumerate() {
+            let x = bitmap.origin.x - self.origin.x + (index % bitmap.width) as i32;
+            let y = bitmap.origin.y - self.origin.y + (index / bitmap.width) as i32;
+            let factor = alpha * self.clip_value(clip, x, y) / 255.0;
+            if pixel[3] > 0 && factor > 0.0 {
+                let (b, g, r, a) = (pixel[0], pixel[1], pixel[2], pixel[3]);
+                self.blend(
+                    x,
+                    y,
+                    [
+                        f32::from(r) * factor,
+                        f32::from(g) * factor,
+                        f32::from(b) * factor,
+                        f32::from(a) * factor,
+                    ],
+                );
+            }
+        }
+    }
+
+    /// Paints a group into its own canvas and composites the result with the group's
+    /// constant alpha. Blend modes and nested soft masks are not evaluated here.
+    fn draw_group(&mut self, group: &GroupObject, page_transform: &euclid::Transform2D<f32>) {
+        let mut canvas = Canvas::new(&self.bounds());
+        canvas.draw_objects(&group.objects, page_transform);
+        let clip = self.clip_coverage(&group.clip, page_transform);
+        let alpha = group.transparency.fill_alpha;
+        for (index, pixel) in canvas.pixels.iter().enumerate() {
+            let x = (index % self.width) as i32;
+            let y = (index / self.width) as i32;
+            let factor = alpha * self.clip_value(&clip, x, y);
+            if pixel[3] > 0.0 && factor > 0.0 {
+                self.blend(
+                    x,
+                    y,
+                    [
+                        pixel[0] * factor,
+                        pixel[1] * factor,
+                        pixel[2] * factor,
+                        pixel[3] * factor,
+                    ],
+                );
+            }
+        }
+    }
+
+    /// Paints graphics objects onto the canvas, given the transform from page space to
+    /// layout space. Text is not rasterized in software and is skipped.
+    pub fn draw_objects(
+        &mut self,
+        objects: &[GraphicsObject],
+        page_transform: &euclid::Transform2D<f32>,
+    ) {
+        let bounds = self.bounds();
+        for graphics_object in objects {
+            match graphics_object {
+                GraphicsObject::Path(path_object) => {
+                    let transform = path_object.transform.post_mul(page_transform);
+                    let clip = self.clip_coverage(&path_object.clip, page_transform);
+                    let (fill_mask, stroke_mask) =
+                        raster::rasterize_path(path_object, &transform, &bounds);
+                    if let Some(mask) = fill_mask {
+                        let alpha = path_object.transparency.fill_alpha;
+                        self.fill(&mask, path_object.fill_color, alpha, &clip);
+                    }
+                    if let Some(mask) = stroke_mask {
+                        let alpha = path_object.transparency.stroke_alpha;
+                        self.fill(&mask, path_object.stroke_color, alpha, &clip);
+                    }
+                }
+                GraphicsObject::Image(image_object) => {
+                    let transform = image_object.transform.post_mul(page_transform);
+                    let clip = self.clip_coverage(&image_object.clip, page_transform);
+                    if let Some(bitmap) = raster::transform_image(image_object, &transform, &bounds)
+                    {
+                        self.draw_bitmap(&bitmap, image_object.transparency.fill_alpha, &clip);
+                    }
+                }
+                GraphicsObject::Shading(shading_object) => {
+                    let transform = shading_object.transform.post_mul(page_transform);
+                    let clip = self.clip_coverage(&shading_object.clip, page_transform);
+                    let alpha = shading_object.transparency.fill_alpha;
+                    if let Some(background) = shading_object.background {
+                        let mask = CoverageMask {
+                            origin: self.origin,
+                            width: self.width,
+                            height: self.height,
+                            coverage: vec![255; self.width * self.height],
+                        };
+                        self.fill(&mask, background, alpha, &clip);
+                    }
+                    if let Some(bitmap) =
+                        shading::rasterize_shading(&shading_object.shading, &transform, &bounds)
+                    {
+                        self.draw_bitmap(&bitmap, alpha, &clip);
+                    }
+                }
+                GraphicsObject::Tiling(tiling_object) => {
+                    let clip = self.clip_coverage(&tiling_object.clip, page_transform);
+                    if let Some(bitmap) = rasterize_tiling(tiling_object, page_transform, &bounds) {
+                        self.draw_bitmap(&bitmap, tiling_object.transparency.fill_alpha, &clip);
+                    }
+                }
+                GraphicsObject::Group(group) => self.draw_group(group, page_transform),
+                GraphicsObject::Text(_) => {
+                    log::debug!("text in soft masks is not supported");
+                }
+            }
+        }
+    }
+
+    /// Computes the values of a soft mask whose group has been painted onto the canvas.
+    pub fn soft_mask_values(&self, soft_mask: &SoftMask) -> Vec<u8> {
+        let backdrop = soft_mask.backdrop;
+        let transfer = soft_mask.transfer.as_ref().map(|function| {
+            (0..256)
+                .map(|value| {
+                    let output = function.evaluate(&[value as f32 / 255.0]);
+                    output.first().cloned().unwrap_or(0.0).clamp(0.0, 1.0)
+                })
+                .collect::<Vec<_>>()
+        });
+        self.pixels
+            .iter()
+            .map(|pixel| {
+                let value = match soft_mask.subtype {
+                    SoftMaskType::Alpha => pixel[3],
+                    SoftMaskType::Luminosity => {
+                        // the group is composited over the backdrop colour
+                        let remaining = 1.0 - pixel[3];
+                        let red = pixel[0] + backdrop.red * remaining;
+                        let green = pixel[1] + backdrop.green * remaining;
+                        let blue = pixel[2] + backdrop.blue * remaining;
+                        0.3 * red + 0.59 * green + 0.11 * blue
+                    }
+                };
+                let value = (value.clamp(0.0, 1.0) * 255.0).round() as usize;
+                match transfer {
+                    Some(ref transfer) => (transfer[value] * 255.0).round() as u8,
+                    None => value as u8,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Returns the range of tile indices along one axis whose cells, spanning `min..max`
+/// from their origin at multiples of `step`, contain the coordinate `value`.
+fn tile_range(value: f32, min: f32, max: f32, step: f32) -> std::ops::RangeInclusive<i32> {
+    let (a, b) = ((value - max) / step, (value - min) / step);
+    let first = a.min(b).ceil() as i32;
+    let last = (a.max(b).floor() as i32).min(first + MAX_OVERLAPPING_TILES - 1);
+    first..=last
+}
+
+/// Paints the cell of a tiling pattern once and repeats it over `bounds`, given the
+/// transform from page space to layout space.
+pub fn rasterize_tiling(
+    tiling: &TilingObject,
+    page_transform: &euclid::Transform2D<f32>,
+    bounds: &euclid::Rect<f32>,
+) -> Option<Bitmap> {
+    let bbox = tiling.bbox;
+    if bbox.size.width <= 0.0 || bbox.size.height <= 0.0 {
+        return None;
+    }
+    let transform = tiling.transform.post_mul(page_transform);
+    let inverse = transform.inverse()?;
+
+    // the cell is rendered at the resolution the pattern is displayed with, within limits
+    let cell_scale = raster::transform_scale(&transform)
+        .min(MAX_CELL_SIZE / bbox.size.width.max(bbox.size.height));
+    let cell_transform = euclid::Transform2D::create_translation(-bbox.origin.x, -bbox.origin.y)
+        .post_scale(cell_scale, cell_scale);
+    let mut cell = Canvas::new(&euclid::Rect::new(
+        euclid::Point2D::zero(),
+        bbox.size * cell_scale,
+    ));
+    cell.draw_objects(&tiling.objects, &cell_transform);
+
+    let mut canvas = Canvas::new(bounds);
+    for y in 0..canvas.height as i32 {
+        for x in 0..canvas.width as i32 {
+            let point = inverse.transform_point(&euclid::Point2D::new(
+                (canvas.origin.x + x) as f32 + 0.5,
+                (canvas.origin.y + y) as f32 + 0.5,
+            ));
+            for j in tile_range(point.y, bbox.min_y(), bbox.max_y(), tiling.y_step) {
+                for i in tile_range(point.x, bbox.min_x(), bbox.max_x(), tiling.x_step) {
+                    let cell_point = cell_transform.transform_point(&euclid::Point2D::new(
+                        point.x - i as f32 * tiling.x_step,
+                        point.y - j as f32 * tiling.y_step,
+                    ));
+                    let cell_x = cell_point.x.floor() as i32;
+                    let cell_y = cell_point.y.floor() as i32;
+                    if let Some(pixel) = cell.pixel(cell_x, cell_y) {
+                        canvas.blend(x, y, pixel);
+                    }
+                }
+            }
+        }
+    }
+    Some(canvas.to_bitmap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rpdf_graphics::path::{FillRule, Path, PathObject};
+    use rpdf_graphics::transparency::TransparencyState;
+
+    fn square_group(color: Rgb) -> GroupObject {
+        let rect = euclid::Rect::new(
+            euclid::Point2D::new(1.0, 1.0),
+            euclid::Size2D::new(2.0, 2.0),
+        );
+        let path_object = PathObject {
+            transform: euclid::Transform2D::identity(),
+            path: Path::from_rect(&rect),
+            fill: Some(FillRule::NonZero),
+            stroke: false,
+            fill_color: color,
+            stroke_color: color,
+            line_style: Default::default(),
+            transparency: TransparencyState {
+                fill_alpha: 0.5,
+                ..Default::default()
+            },
+            clip: Clip::default(),
+        };
+        GroupObject {
+            objects: vec![GraphicsObject::Path(path_object)],
+            transform: euclid::Transform2D::identity(),
+            bbox: rect,
+            isolated: true,
+            knockout: false,
+            transparency: TransparencyState::default(),
+            clip: Clip::default(),
+        }
+    }
+
+    #[test]
+    fn luminosity_and_alpha_masks() {
+        let bounds = euclid::Rect::new(euclid::Point2D::zero(), euclid::Size2D::new(4.0, 1.0));
+        let identity = euclid::Transform2D::identity();
+
+        let mut canvas = Canvas::new(&bounds.translate(&euclid::Vector2D::new(0.0, 1.0)));
+        let group = square_group(Rgb::new(1.0, 1.0, 1.0));
+        canvas.draw_objects(&group.objects, &identity);
+        let soft_mask = SoftMask {
+            subtype: SoftMaskType::Luminosity,
+            group,
+            backdrop: Rgb::BLACK,
+            transfer: None,
+        };
+        assert_eq!(vec![0, 128, 128, 0], canvas.soft_mask_values(&soft_mask));
+
+        let mut canvas = Canvas::new(&bounds.translate(&euclid::Vector2D::new(0.0, 1.0)));
+        let group = square_group(Rgb::BLACK);
+        canvas.draw_objects(&group.objects, &identity);
+        let soft_mask = SoftMask {
+            subtype: SoftMaskType::Alpha,
+            group,
+            backdrop: Rgb::BLACK,
+            transfer: None,
+        };
+        assert_eq!(vec![0, 128, 128, 0], canvas.soft_mask_values(&soft_mask));
+    }
+
+    #[test]
+    fn tiling_pattern_repeats_cell() {
+        let tiling = TilingObject {
+            objects: square_group(Rgb::new(1.0, 0.0, 0.0)).objects,
+            transform: euclid::Transform2D::create_scale(2.0, 2.0),
+            bbox: euclid::Rect::new(euclid::Point2D::zero(), euclid::Size2D::new(4.0, 4.0)),
+            x_step: 4.0,
+            y_step: 4.0,
+            transparency: TransparencyState::default(),
+            clip: Clip::default(),
+        };
+        let bounds = euclid::Rect::new(
+            euclid::Point2D::new(0.0, 3.0),
+            euclid::Size2D::new(16.0, 1.0),
+        );
+        let bitmap = rasterize_tiling(&tiling, &euclid::Transform2D::identity(), &bounds).unwrap();
+
+        let alpha = bitmap
+            .data
+            .chunks(4)
+            .map(|pixel| pixel[3])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![0, 0, 128, 128, 128, 128, 0, 0, 0, 0, 128, 128, 128, 128, 0, 0],
+            alpha
+        );
+        assert_eq!(&[0, 0, 128, 128], &bitmap.data[8..12]);
+    }
+}
@@ -5,13 +5,16 @@ use rpdf_document::Document;
 
 mod text;
 use self::text::FontRenderContext;
+mod composite;
 mod page;
 use self::page::PageRenderer;
+mod raster;
+mod shading;
 
 pub struct DocumentRenderer<'a> {
     document: &'a Document,
     page_renderers: Vec<PageRenderer<'a>>,
-    font_context: FontRenderContext<'a>,
+    font_context: FontRenderContext,
 }
 
 impl<'a> DocumentRenderer<'a> {
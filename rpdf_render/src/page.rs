@@ -1,18 +1,487 @@
-use webrender::api::units::LayoutPixel;
+use std::collections::HashMap;
+
+use webrender::api::units::{LayoutPixel, LayoutPoint, LayoutRect, LayoutSize, LayoutTransform};
 use webrender::api::*;
 
 use rpdf_document::Page;
-use rpdf_graphics::{text, GraphicsObject};
+use rpdf_graphics::clip::{Clip, ClipPath};
+use rpdf_graphics::color::Rgb;
+use rpdf_graphics::pattern::TilingObject;
+use rpdf_graphics::shading::ShadingObject;
+use rpdf_graphics::transparency::{BlendMode, GroupObject, SoftMask, SoftMaskType};
+use rpdf_graphics::{image, path, text, GraphicsObject};
 
+use super::composite::{self, Canvas};
+use super::raster;
+use super::shading::{self, Gradient};
 use super::text::FontRenderContext;
 
+/// Images larger than this (in either dimension) are split into tiles by webrender.
+const MAX_UNTILED_IMAGE_SIZE: usize = 2048;
+
+const IMAGE_TILE_SIZE: TileSize = 512;
+
+fn color(rgb: Rgb, alpha: f32) -> ColorF {
+    ColorF::new(rgb.red, rgb.green, rgb.blue, alpha)
+}
+
+/// Pushes a run of glyphs, scaled horizontally about the page origin.
+fn push_glyphs(
+    builder: &mut DisplayListBuilder,
+    space_and_clip: &SpaceAndClipInfo,
+    glyph_instances: &[GlyphInstance],
+    font_instance_key: FontInstanceKey,
+    rect: LayoutRect,
+    horizontal_scale: f32,
+    color: ColorF,
+) {
+    log::trace!("push text {:?} {:?}", glyph_instances, rect);
+    if horizontal_scale == 1.0 {
+        builder.push_text(
+            &LayoutPrimitiveInfo::new(rect),
+            space_and_clip,
+            glyph_instances,
+            font_instance_key,
+            color,
+            None,
+        );
+        return;
+    }
+
+    let spatial_id = builder.push_reference_frame(
+        LayoutPoint::zero(),
+        space_and_clip.spatial_id,
+        TransformStyle::Flat,
+        PropertyBinding::Value(LayoutTransform::create_scale(horizontal_scale, 1.0, 1.0)),
+        ReferenceFrameKind::Transform,
+    );
+    let scaled_rect = LayoutRect::new(
+        LayoutPoint::new(rect.origin.x / horizontal_scale, rect.origin.y),
+        LayoutSize::new(rect.size.width / horizontal_scale, rect.size.height),
+    );
+    builder.push_text(
+        &LayoutPrimitiveInfo::new(scaled_rect),
+        &SpaceAndClipInfo {
+            spatial_id,
+            clip_id: space_and_clip.clip_id,
+        },
+        glyph_instances,
+        font_instance_key,
+        color,
+        None,
+    );
+    builder.pop_reference_frame();
+}
+
+fn mix_blend_mode(blend_mode: BlendMode) -> MixBlendMode {
+    match blend_mode {
+        BlendMode::Normal => MixBlendMode::Normal,
+        BlendMode::Multiply => MixBlendMode::Multiply,
+        BlendMode::Screen => MixBlendMode::Screen,
+        BlendMode::Overlay => MixBlendMode::Overlay,
+        BlendMode::Darken => MixBlendMode::Darken,
+        BlendMode::Lighten => MixBlendMode::Lighten,
+        BlendMode::ColorDodge => MixBlendMode::ColorDodge,
+        BlendMode::ColorBurn => MixBlendMode::ColorBurn,
+        BlendMode::HardLight => MixBlendMode::HardLight,
+        BlendMode::SoftLight => MixBlendMode::SoftLight,
+        BlendMode::Difference => MixBlendMode::Difference,
+        BlendMode::Exclusion => MixBlendMode::Exclusion,
+        BlendMode::Hue => MixBlendMode::Hue,
+        BlendMode::Saturation => MixBlendMode::Saturation,
+        BlendMode::Color => MixBlendMode::Color,
+        BlendMode::Luminosity => MixBlendMode::Luminosity,
+    }
+}
+
 pub struct PageRenderer<'a> {
     page: &'a Page,
+    image_keys: Vec<ImageKey>,
+    /// Clips defined in the current display list, by the address of their clipping path.
+    clip_ids: HashMap<*const ClipPath, ClipId>,
+    /// Image mask clips of the soft masks in the current display list, by address.
+    soft_mask_ids: HashMap<*const SoftMask, ClipId>,
 }
 
 impl<'a> PageRenderer<'a> {
     pub fn new(page: &'a Page) -> Self {
-        Self { page }
+        Self {
+            page,
+            image_keys: Vec::new(),
+            clip_ids: HashMap::new(),
+            soft_mask_ids: HashMap::new(),
+        }
+    }
+
+    /// Transform from PDF page space into the (unscaled) layout space of the page.
+    fn page_transform(
+        &self,
+        scale: euclid::TypedScale<f32, LayoutPixel, LayoutPixel>,
+    ) -> euclid::Transform2D<f32> {
+        euclid::Transform2D::row_major(1.0, 0.0, 0.0, -1.0, 0.0, self.page.height() as f32)
+            .post_scale(scale.get(), scale.get())
+    }
+
+    fn page_bounds(
+        &self,
+        scale: euclid::TypedScale<f32, LayoutPixel, LayoutPixel>,
+    ) -> euclid::Rect<f32> {
+        euclid::Rect::new(
+            euclid::Point2D::zero(),
+            euclid::Size2D::new(self.page.width() as f32, self.page.height() as f32) * scale.get(),
+        )
+    }
+
+    fn push_bitmap(
+        &mut self,
+        api: &RenderApi,
+        builder: &mut DisplayListBuilder,
+        txn: &mut Transaction,
+        space_and_clip: &SpaceAndClipInfo,
+        bitmap: raster::Bitmap,
+        rect: LayoutRect,
+    ) {
+        let (width, height) = (bitmap.width, bitmap.height);
+        let key = api.generate_image_key();
+        let tiling = if width > MAX_UNTILED_IMAGE_SIZE || height > MAX_UNTILED_IMAGE_SIZE {
+            Some(IMAGE_TILE_SIZE)
+        } else {
+            None
+        };
+        txn.add_image(
+            key,
+            ImageDescriptor::new(
+                width as i32,
+                height as i32,
+                ImageFormat::BGRA8,
+                false,
+                false,
+            ),
+            ImageData::new(bitmap.data),
+            tiling,
+        );
+        self.image_keys.push(key);
+
+        builder.push_image(
+            &LayoutPrimitiveInfo::new(rect),
+            space_and_clip,
+            rect.size,
+            LayoutSize::zero(),
+            ImageRendering::Auto,
+            AlphaType::PremultipliedAlpha,
+            key,
+            ColorF::WHITE,
+        );
+    }
+
+    fn push_coverage_mask(
+        &mut self,
+        api: &RenderApi,
+        builder: &mut DisplayListBuilder,
+        txn: &mut Transaction,
+        space_and_clip: &SpaceAndClipInfo,
+        mask: raster::CoverageMask,
+        rgb: Rgb,
+        opacity: f32,
+    ) {
+        let mut bytes = Vec::with_capacity(mask.coverage.len() * 4);
+        for index in 0..mask.coverage.len() {
+            let alpha = mask.value(index) * opacity;
+            bytes.push((rgb.blue * alpha * 255.0).round() as u8);
+            bytes.push((rgb.green * alpha * 255.0).round() as u8);
+            bytes.push((rgb.red * alpha * 255.0).round() as u8);
+            bytes.push((alpha * 255.0).round() as u8);
+        }
+
+        let bitmap = raster::Bitmap {
+            origin: mask.origin,
+            width: mask.width,
+            height: mask.height,
+            data: bytes,
+        };
+        let rect = LayoutRect::from_untyped(&bitmap.rect());
+        self.push_bitmap(api, builder, txn, space_and_clip, bitmap, rect);
+    }
+
+    fn define_clip_path(
+        &mut self,
+        scale: euclid::TypedScale<f32, LayoutPixel, LayoutPixel>,
+        api: &RenderApi,
+        builder: &mut DisplayListBuilder,
+        txn: &mut Transaction,
+        space_and_clip: &SpaceAndClipInfo,
+        clip_path: &ClipPath,
+    ) -> ClipId {
+        let transform = clip_path.transform.post_mul(&self.page_transform(scale));
+
+        // rectangles that stay axis-aligned can be clipped exactly by webrender
+        if transform.m12 == 0.0 && transform.m21 == 0.0 {
+            if let Some(rect) = clip_path.path.as_rect() {
+                let clip_rect = LayoutRect::from_untyped(&transform.transform_rect(&rect));
+                return builder.define_clip(space_and_clip, clip_rect, vec![], None);
+            }
+        }
+
+        let page_bounds = self.page_bounds(scale);
+        match raster::rasterize_clip_path(clip_path, &self.page_transform(scale), &page_bounds) {
+            Some(mask) => {
+                let rect = euclid::Rect::new(
+                    euclid::Point2D::new(mask.origin.x as f32, mask.origin.y as f32),
+                    euclid::Size2D::new(mask.width as f32, mask.height as f32),
+                );
+                self.define_image_mask_clip(api, builder, txn, space_and_clip, mask.coverage, rect)
+            }
+            None => builder.define_clip(space_and_clip, LayoutRect::zero(), vec![], None),
+        }
+    }
+
+    /// Defines a clip from an 8-bit mask covering `rect`, which clips everything outside.
+    fn define_image_mask_clip(
+        &mut self,
+        api: &RenderApi,
+        builder: &mut DisplayListBuilder,
+        txn: &mut Transaction,
+        space_and_clip: &SpaceAndClipInfo,
+        bytes: Vec<u8>,
+        rect: euclid::Rect<f32>,
+    ) -> ClipId {
+        let key = api.generate_image_key();
+        txn.add_image(
+            key,
+            ImageDescriptor::new(
+                rect.size.width as i32,
+                rect.size.height as i32,
+                ImageFormat::R8,
+                false,
+                false,
+            ),
+            ImageData::new(bytes),
+            None,
+        );
+        self.image_keys.push(key);
+
+        let clip_rect = LayoutRect::from_untyped(&rect);
+        let image_mask = ImageMask {
+            image: key,
+            rect: clip_rect,
+            repeat: false,
+        };
+        builder.define_clip(space_and_clip, clip_rect, vec![], Some(image_mask))
+    }
+
+    /// Paints the group of a soft mask in software and turns the mask values into an
+    /// image mask clip.
+    fn define_soft_mask(
+        &mut self,
+        scale: euclid::TypedScale<f32, LayoutPixel, LayoutPixel>,
+        api: &RenderApi,
+        builder: &mut DisplayListBuilder,
+        txn: &mut Transaction,
+        space_and_clip: &SpaceAndClipInfo,
+        soft_mask: &SoftMask,
+    ) -> ClipId {
+        let page_transform = self.page_transform(scale);
+        let page_bounds = self.page_bounds(scale);
+        // outside of the group, alpha masks are zero, but luminosity masks take the
+        // value of the backdrop, so they need to cover the whole page
+        let bounds = match soft_mask.subtype {
+            SoftMaskType::Alpha => {
+                let group = &soft_mask.group;
+                let transform = group.transform.post_mul(&page_transform);
+                transform
+                    .transform_rect(&group.bbox)
+                    .intersection(&page_bounds)
+                    .unwrap_or_else(euclid::Rect::zero)
+            }
+            SoftMaskType::Luminosity => page_bounds,
+        };
+        let mut canvas = Canvas::new(&bounds);
+        if canvas.width == 0 || canvas.height == 0 {
+            return builder.define_clip(space_and_clip, LayoutRect::zero(), vec![], None);
+        }
+        canvas.draw_objects(&soft_mask.group.objects, &page_transform);
+        let bytes = canvas.soft_mask_values(soft_mask);
+        let rect = canvas.bounds();
+        self.define_image_mask_clip(api, builder, txn, space_and_clip, bytes, rect)
+    }
+
+    /// Returns the space and clip to draw a graphics object with the given clip and
+    /// soft mask in.
+    fn define_clip(
+        &mut self,
+        scale: euclid::TypedScale<f32, LayoutPixel, LayoutPixel>,
+        api: &RenderApi,
+        builder: &mut DisplayListBuilder,
+        txn: &mut Transaction,
+        space_and_clip: &SpaceAndClipInfo,
+        clip: &Clip,
+        soft_mask: Option<&SoftMask>,
+    ) -> SpaceAndClipInfo {
+        if clip.is_empty() && soft_mask.is_none() {
+            return *space_and_clip;
+        }
+
+        let mut clip_ids = Vec::with_capacity(clip.paths().len() + 1);
+        for clip_path in clip.paths() {
+            let address: *const ClipPath = &**clip_path;
+            let clip_id = match self.clip_ids.get(&address) {
+                Some(clip_id) => *clip_id,
+                None => {
+                    let clip_id =
+                        self.define_clip_path(scale, api, builder, txn, space_and_clip, clip_path);
+                    self.clip_ids.insert(address, clip_id);
+                    clip_id
+                }
+            };
+            clip_ids.push(clip_id);
+        }
+        if let Some(soft_mask) = soft_mask {
+            let address: *const SoftMask = soft_mask;
+            let clip_id = match self.soft_mask_ids.get(&address) {
+                Some(clip_id) => *clip_id,
+                None => {
+                    let clip_id =
+                        self.define_soft_mask(scale, api, builder, txn, space_and_clip, soft_mask);
+                    self.soft_mask_ids.insert(address, clip_id);
+                    clip_id
+                }
+            };
+            clip_ids.push(clip_id);
+        }
+
+        SpaceAndClipInfo {
+            spatial_id: space_and_clip.spatial_id,
+            clip_id: ClipId::ClipChain(builder.define_clip_chain(None, clip_ids)),
+        }
+    }
+
+    fn render_path(
+        &mut self,
+        scale: euclid::TypedScale<f32, LayoutPixel, LayoutPixel>,
+        api: &RenderApi,
+        builder: &mut DisplayListBuilder,
+        txn: &mut Transaction,
+        space_and_clip: &SpaceAndClipInfo,
+        path_object: &path::PathObject,
+    ) {
+        let transform = path_object.transform.post_mul(&self.page_transform(scale));
+        let transparency = &path_object.transparency;
+
+        // filled rectangles that stay axis-aligned, such as backgrounds and table rules,
+        // are drawn by webrender instead of being rasterized and uploaded as images
+        let fill_rect = match path_object.path.as_rect() {
+            Some(rect) if transform.m12 == 0.0 && transform.m21 == 0.0 => {
+                path_object.fill.map(|_| rect)
+            }
+            _ => None,
+        };
+        if let Some(rect) = fill_rect {
+            let rect = LayoutRect::from_untyped(&transform.transform_rect(&rect));
+            builder.push_rect(
+                &LayoutPrimitiveInfo::new(rect),
+                space_and_clip,
+                color(path_object.fill_color, transparency.fill_alpha),
+            );
+            if path_object.stroke {
+                let polylines = raster::flatten(&path_object.path, &transform);
+                let bounds = self.page_bounds(scale);
+                if let Some(mask) =
+                    raster::rasterize_stroke(path_object, &polylines, &transform, &bounds)
+                {
+                    self.push_coverage_mask(
+                        api,
+                        builder,
+                        txn,
+                        space_and_clip,
+                        mask,
+                        path_object.stroke_color,
+                        transparency.stroke_alpha,
+                    );
+                }
+            }
+            return;
+        }
+
+        let (fill_mask, stroke_mask) =
+            raster::rasterize_path(path_object, &transform, &self.page_bounds(scale));
+        if let Some(mask) = fill_mask {
+            self.push_coverage_mask(
+                api,
+                builder,
+                txn,
+                space_and_clip,
+                mask,
+                path_object.fill_color,
+                transparency.fill_alpha,
+            );
+        }
+        if let Some(mask) = stroke_mask {
+            self.push_coverage_mask(
+                api,
+                builder,
+                txn,
+                space_and_clip,
+                mask,
+                path_object.stroke_color,
+                transparency.stroke_alpha,
+            );
+        }
+    }
+
+    fn render_image(
+        &mut self,
+        scale: euclid::TypedScale<f32, LayoutPixel, LayoutPixel>,
+        api: &RenderApi,
+        builder: &mut DisplayListBuilder,
+        txn: &mut Transaction,
+        space_and_clip: &SpaceAndClipInfo,
+        image_object: &image::ImageObject,
+    ) {
+        let transform = image_object.transform.post_mul(&self.page_transform(scale));
+        if image_object.width == 0 || image_object.height == 0 {
+            return;
+        }
+
+        if transform.m12 == 0.0 && transform.m21 == 0.0 {
+            // without rotation or skew, webrender can scale the image itself; we only need
+            // to mirror the samples so that the first row ends up where the transform puts it
+            let (width, height) = (image_object.width as usize, image_object.height as usize);
+            let flip_x = transform.m11 < 0.0;
+            let flip_y = transform.m22 > 0.0;
+            let mut pixels = Vec::with_capacity(image_object.pixels.len());
+            for row in 0..height {
+                let row = if flip_y { height - 1 - row } else { row };
+                let line = &image_object.pixels[row * width * 4..(row + 1) * width * 4];
+                if flip_x {
+                    for pixel in line.chunks(4).rev() {
+                        pixels.extend_from_slice(pixel);
+                    }
+                } else {
+                    pixels.extend_from_slice(line);
+                }
+            }
+
+            let origin = transform.transform_point(&euclid::Point2D::new(0.0, 0.0));
+            let corner = transform.transform_point(&euclid::Point2D::new(1.0, 1.0));
+            let rect = LayoutRect::new(
+                LayoutPoint::new(origin.x.min(corner.x), origin.y.min(corner.y)),
+                LayoutSize::new((corner.x - origin.x).abs(), (corner.y - origin.y).abs()),
+            );
+            let mut bitmap = raster::Bitmap {
+                origin: euclid::Point2D::zero(),
+                width,
+                height,
+                data: raster::premultiply(&pixels),
+            };
+            bitmap.apply_alpha(image_object.transparency.fill_alpha);
+            self.push_bitmap(api, builder, txn, space_and_clip, bitmap, rect);
+        } else if let Some(mut bitmap) =
+            raster::transform_image(image_object, &transform, &self.page_bounds(scale))
+        {
+            bitmap.apply_alpha(image_object.transparency.fill_alpha);
+            let rect = LayoutRect::from_untyped(&bitmap.rect());
+            self.push_bitmap(api, builder, txn, space_and_clip, bitmap, rect);
+        }
     }
 
     fn render_text(
@@ -22,34 +491,36 @@ impl<'a> PageRenderer<'a> {
         builder: &mut DisplayListBuilder,
         txn: &mut Transaction,
         space_and_clip: &SpaceAndClipInfo,
-        font_context: &mut FontRenderContext<'a>,
-        text_object: &'a text::TextObject,
+        font_context: &mut FontRenderContext,
+        text_object: &text::TextObject,
     ) {
         for text_fragment in text_object.fragments.iter() {
+            // Type 3 glyphs are painted by the objects of their glyph descriptions, and
+            // invisible text and standard fonts without a substitute can only be extracted
+            if !text_fragment.font.has_program() || !text_fragment.render_mode.is_visible() {
+                continue;
+            }
+            // glyph outlines can't be stroked, so text that is only stroked is filled with
+            // the stroke colour instead
+            let glyph_color = if text_fragment.render_mode.fills() {
+                color(text_fragment.color, text_object.transparency.fill_alpha)
+            } else {
+                color(
+                    text_fragment.stroke_color,
+                    text_object.transparency.stroke_alpha,
+                )
+            };
             let mut transform = euclid::TypedTransform2D::from_untyped(&text_fragment.transform);
             transform.m32 = self.page.height() as f32 - transform.m32;
 
-            if let Some(font) = self.page.font(&text_fragment.font_name) {
-                font_context.load_font(api, txn, &text_fragment.font_name, font);
-            } else {
-                // skip text fragments that don't have font data
-                continue;
-            };
+            font_context.load_font(api, txn, &text_fragment.font);
 
-            let font_size = text_fragment.font_size * scale.get();
+            // the fragment transform combines the text matrix with the CTM, so the
+            // effective font size depends on how it scales the vertical axis
+            let vertical_scale = transform.m21.hypot(transform.m22);
+            let font_size = text_fragment.font_size * vertical_scale * scale.get();
             let font_instance_key =
-                font_context.load_font_instance(api, txn, &text_fragment.font_name, font_size);
-
-            let mut glyph_instances = Vec::with_capacity(text_fragment.glyphs.len());
-
-            for text_glyph in text_fragment.glyphs.iter() {
-                let mut point = euclid::TypedPoint2D::from_untyped(&text_glyph.origin);
-                point.y = self.page.height() as f32 - point.y;
-                glyph_instances.push(GlyphInstance {
-                    index: text_glyph.index,
-                    point: scale.transform_point(&point),
-                });
-            }
+                font_context.load_font_instance(api, txn, &text_fragment.font, font_size);
 
             let size = euclid::TypedSize2D::<f32, LayoutPixel>::new(self.page.width() as f32, 60.0);
             let rect = euclid::TypedRect::<f32, LayoutPixel>::new(
@@ -58,40 +529,353 @@ impl<'a> PageRenderer<'a> {
             );
             let transformed_rect = scale.transform_rect(&transform.transform_rect(&rect));
 
-            log::trace!("push text {:?} {:?}", glyph_instances, transformed_rect);
+            // glyphs are drawn in runs that share a horizontal scale, which is applied
+            // by a reference frame unless it is 1
+            let mut glyphs = text_fragment.glyphs.iter().peekable();
+            while let Some(first_glyph) = glyphs.peek() {
+                let horizontal_scale = first_glyph.horizontal_scale;
+                let mut glyph_instances = Vec::new();
+                while let Some(text_glyph) =
+                    glyphs.next_if(|text_glyph| text_glyph.horizontal_scale == horizontal_scale)
+                {
+                    let mut point = euclid::TypedPoint2D::from_untyped(&text_glyph.origin);
+                    point.y = self.page.height() as f32 - point.y;
+                    let mut point = scale.transform_point(&point);
+                    point.x /= horizontal_scale;
+                    glyph_instances.push(GlyphInstance {
+                        index: text_glyph.index,
+                        point,
+                    });
+                }
+                push_glyphs(
+                    builder,
+                    space_and_clip,
+                    &glyph_instances,
+                    font_instance_key,
+                    transformed_rect,
+                    horizontal_scale,
+                    glyph_color,
+                );
+            }
+        }
+    }
 
-            builder.push_text(
-                &LayoutPrimitiveInfo::new(transformed_rect),
+    fn render_shading(
+        &mut self,
+        scale: euclid::TypedScale<f32, LayoutPixel, LayoutPixel>,
+        api: &RenderApi,
+        builder: &mut DisplayListBuilder,
+        txn: &mut Transaction,
+        space_and_clip: &SpaceAndClipInfo,
+        shading_object: &ShadingObject,
+    ) {
+        let page_transform = self.page_transform(scale);
+        // shadings extend indefinitely, but only the clipped part needs to be drawn
+        let bounds = match raster::clip_bounds(
+            &shading_object.clip,
+            &page_transform,
+            &self.page_bounds(scale),
+        ) {
+            Some(bounds) => bounds.round_out(),
+            None => return,
+        };
+        let info = LayoutPrimitiveInfo::new(LayoutRect::from_untyped(&bounds));
+        let alpha = shading_object.transparency.fill_alpha;
+        if let Some(background) = shading_object.background {
+            builder.push_rect(&info, space_and_clip, color(background, alpha));
+        }
+
+        let transform = shading_object.transform.post_mul(&page_transform);
+        // gradients are positioned relative to the primitive they fill
+        let gradient_transform = transform.post_translate(-bounds.origin.to_vector());
+        match shading::gradient(&shading_object.shading, &gradient_transform, alpha) {
+            Some(Gradient::Linear { start, end, stops }) => {
+                let gradient = builder.create_gradient(start, end, stops, ExtendMode::Clamp);
+                builder.push_gradient(
+                    &info,
+                    space_and_clip,
+                    gradient,
+                    info.rect.size,
+                    LayoutSize::zero(),
+                );
+            }
+            Some(Gradient::Radial {
+                center,
+                radius,
+                stops,
+            }) => {
+                let gradient =
+                    builder.create_radial_gradient(center, radius, stops, ExtendMode::Clamp);
+                builder.push_radial_gradient(
+                    &info,
+                    space_and_clip,
+                    gradient,
+                    info.rect.size,
+                    LayoutSize::zero(),
+                );
+            }
+            None => {
+                if let Some(mut bitmap) =
+                    shading::rasterize_shading(&shading_object.shading, &transform, &bounds)
+                {
+                    bitmap.apply_alpha(alpha);
+                    let rect = LayoutRect::from_untyped(&bitmap.rect());
+                    self.push_bitmap(api, builder, txn, space_and_clip, bitmap, rect);
+                }
+            }
+        }
+    }
+
+    fn render_tiling(
+        &mut self,
+        scale: euclid::TypedScale<f32, LayoutPixel, LayoutPixel>,
+        api: &RenderApi,
+        builder: &mut DisplayListBuilder,
+        txn: &mut Transaction,
+        space_and_clip: &SpaceAndClipInfo,
+        tiling_object: &TilingObject,
+    ) {
+        let page_transform = self.page_transform(scale);
+        let bounds = match raster::clip_bounds(
+            &tiling_object.clip,
+            &page_transform,
+            &self.page_bounds(scale),
+        ) {
+            Some(bounds) => bounds.round_out(),
+            None => return,
+        };
+        if let Some(mut bitmap) =
+            composite::rasterize_tiling(tiling_object, &page_transform, &bounds)
+        {
+            bitmap.apply_alpha(tiling_object.transparency.fill_alpha);
+            let rect = LayoutRect::from_untyped(&bitmap.rect());
+            self.push_bitmap(api, builder, txn, space_and_clip, bitmap, rect);
+        }
+    }
+
+    fn push_stacking_context(
+        &self,
+        scale: euclid::TypedScale<f32, LayoutPixel, LayoutPixel>,
+        builder: &mut DisplayListBuilder,
+        space_and_clip: &SpaceAndClipInfo,
+        clip_id: Option<ClipId>,
+        blend_mode: BlendMode,
+        opacity: f32,
+    ) {
+        let filters = if opacity < 1.0 {
+            vec![FilterOp::Opacity(PropertyBinding::Value(opacity), opacity)]
+        } else {
+            vec![]
+        };
+        builder.push_stacking_context(
+            &LayoutPrimitiveInfo::new(LayoutRect::from_untyped(&self.page_bounds(scale))),
+            space_and_clip.spatial_id,
+            clip_id,
+            TransformStyle::Flat,
+            mix_blend_mode(blend_mode),
+            &filters,
+            RasterSpace::Screen,
+        );
+    }
+
+    /// Renders the objects of a transparency group, which are composited in a stacking
+    /// context of their own unless that wouldn't make a difference.
+    fn render_group(
+        &mut self,
+        scale: euclid::TypedScale<f32, LayoutPixel, LayoutPixel>,
+        api: &RenderApi,
+        builder: &mut DisplayListBuilder,
+        txn: &mut Transaction,
+        space_and_clip: &SpaceAndClipInfo,
+        font_context: &mut FontRenderContext,
+        group: &GroupObject,
+    ) {
+        if group.knockout {
+            log::debug!("rendering knockout group as a regular group");
+        }
+        let transparency = &group.transparency;
+        if !group.isolated && transparency.is_opaque() {
+            self.render_objects(
+                scale,
+                api,
+                builder,
+                txn,
                 space_and_clip,
-                &glyph_instances,
-                font_instance_key,
-                ColorF::BLACK,
-                None,
+                font_context,
+                &group.objects,
             );
+            return;
         }
+
+        // the group's clip and soft mask apply to the composited group as a whole
+        let group_space_and_clip = self.define_clip(
+            scale,
+            api,
+            builder,
+            txn,
+            space_and_clip,
+            &group.clip,
+            transparency.soft_mask.as_deref(),
+        );
+        let clip_id = if group_space_and_clip.clip_id == space_and_clip.clip_id {
+            None
+        } else {
+            Some(group_space_and_clip.clip_id)
+        };
+        self.push_stacking_context(
+            scale,
+            builder,
+            space_and_clip,
+            clip_id,
+            transparency.blend_mode,
+            transparency.fill_alpha,
+        );
+        self.render_objects(
+            scale,
+            api,
+            builder,
+            txn,
+            space_and_clip,
+            font_context,
+            &group.objects,
+        );
+        builder.pop_stacking_context();
     }
 
-    pub fn render(
+    fn render_objects(
         &mut self,
         scale: euclid::TypedScale<f32, LayoutPixel, LayoutPixel>,
         api: &RenderApi,
         builder: &mut DisplayListBuilder,
         txn: &mut Transaction,
         space_and_clip: &SpaceAndClipInfo,
-        font_context: &mut FontRenderContext<'a>,
+        font_context: &mut FontRenderContext,
+        graphics_objects: &[GraphicsObject],
     ) {
-        for graphics_object in self.page.graphics_objects() {
+        for graphics_object in graphics_objects {
+            let (clip, transparency) = match graphics_object {
+                GraphicsObject::Text(text_object) => (&text_object.clip, &text_object.transparency),
+                GraphicsObject::Path(path_object) => (&path_object.clip, &path_object.transparency),
+                GraphicsObject::Image(image_object) => {
+                    (&image_object.clip, &image_object.transparency)
+                }
+                GraphicsObject::Shading(shading_object) => {
+                    (&shading_object.clip, &shading_object.transparency)
+                }
+                GraphicsObject::Tiling(tiling_object) => {
+                    (&tiling_object.clip, &tiling_object.transparency)
+                }
+                GraphicsObject::Group(group) => {
+                    self.render_group(
+                        scale,
+                        api,
+                        builder,
+                        txn,
+                        space_and_clip,
+                        font_context,
+                        group,
+                    );
+                    continue;
+                }
+            };
+            let object_space_and_clip = self.define_clip(
+                scale,
+                api,
+                builder,
+                txn,
+                space_and_clip,
+                clip,
+                transparency.soft_mask.as_deref(),
+            );
+
+            // blending happens with whatever was painted before, in a stacking context
+            let blend = transparency.blend_mode != BlendMode::Normal;
+            if blend {
+                self.push_stacking_context(
+                    scale,
+                    builder,
+                    space_and_clip,
+                    None,
+                    transparency.blend_mode,
+                    1.0,
+                );
+            }
+
             match graphics_object {
                 GraphicsObject::Text(text_object) => self.render_text(
                     scale,
                     api,
                     builder,
                     txn,
-                    space_and_clip,
+                    &object_space_and_clip,
                     font_context,
                     text_object,
                 ),
+                GraphicsObject::Path(path_object) => self.render_path(
+                    scale,
+                    api,
+                    builder,
+                    txn,
+                    &object_space_and_clip,
+                    path_object,
+                ),
+                GraphicsObject::Image(image_object) => self.render_image(
+                    scale,
+                    api,
+                    builder,
+                    txn,
+                    &object_space_and_clip,
+                    image_object,
+                ),
+                GraphicsObject::Shading(shading_object) => self.render_shading(
+                    scale,
+                    api,
+                    builder,
+                    txn,
+                    &object_space_and_clip,
+                    shading_object,
+                ),
+                GraphicsObject::Tiling(tiling_object) => self.render_tiling(
+                    scale,
+                    api,
+                    builder,
+                    txn,
+                    &object_space_and_clip,
+                    tiling_object,
+                ),
+                GraphicsObject::Group(_) => unreachable!(),
+            }
+
+            if blend {
+                builder.pop_stacking_context();
             }
         }
     }
+
+    pub fn render(
+        &mut self,
+        scale: euclid::TypedScale<f32, LayoutPixel, LayoutPixel>,
+        api: &RenderApi,
+        builder: &mut DisplayListBuilder,
+        txn: &mut Transaction,
+        space_and_clip: &SpaceAndClipInfo,
+        font_context: &mut FontRenderContext,
+    ) {
+        for key in self.image_keys.drain(..) {
+            txn.delete_image(key);
+        }
+
+        self.clip_ids.clear();
+        self.soft_mask_ids.clear();
+
+        self.render_objects(
+            scale,
+            api,
+            builder,
+            txn,
+            space_and_clip,
+            font_context,
+            self.page.graphics_objects(),
+        );
+    }
 }
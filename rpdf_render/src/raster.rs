@@ -0,0 +1,846 @@
+use rpdf_graphics::clip::{Clip, ClipPath};
+use rpdf_graphics::image::ImageObject;
+use rpdf_graphics::line::{DashPattern, LineCap, LineJoin, LineStyle};
+use rpdf_graphics::path::{FillRule, Path, PathObject, PathSegment};
+
+/// Maximum distance (in layout pixels) between a curve and its flattened approximation.
+const FLATTEN_TOLERANCE: f32 = 0.2;
+
+/// Number of sample rows per pixel row used for anti-aliasing.
+const SUBSAMPLES: usize = 4;
+
+/// Dash patterns that repeat within less than this many layout pixels are drawn as
+/// solid lines, since their dashes can't be told apart.
+const MIN_DASH_CYCLE: f32 = 0.5;
+
+/// Maximum number of dashes a subpath is split into; the rest of it is drawn solid.
+const MAX_DASHES: usize = 10_000;
+
+pub struct Polyline {
+    pub points: Vec<euclid::Point2D<f32>>,
+    pub closed: bool,
+}
+
+/// Returns the factor by which `transform` scales areas, as a length.
+pub fn transform_scale(transform: &euclid::Transform2D<f32>) -> f32 {
+    transform.determinant().abs().sqrt()
+}
+
+fn flatten_cubic(
+    points: &mut Vec<euclid::Point2D<f32>>,
+    from: euclid::Point2D<f32>,
+    control_1: euclid::Point2D<f32>,
+    control_2: euclid::Point2D<f32>,
+    to: euclid::Point2D<f32>,
+    tolerance: f32,
+) {
+    // Wang's formula for the number of segments needed to stay within the tolerance
+    let dd1 = (from - control_1 * 2.0 + control_2.to_vector()).length();
+    let dd2 = (control_1 - control_2 * 2.0 + to.to_vector()).length();
+    let segments = ((0.75 * dd1.max(dd2) / tolerance).sqrt().ceil() as usize).clamp(1, 256);
+
+    for i in 1..=segments {
+        let t = i as f32 / segments as f32;
+        let mt = 1.0 - t;
+        let a = mt * mt * mt;
+        let b = 3.0 * mt * mt * t;
+        let c = 3.0 * mt * t * t;
+        let d = t * t * t;
+        points.push(euclid::Point2D::new(
+            a * from.x + b * control_1.x + c * control_2.x + d * to.x,
+            a * from.y + b * control_1.y + c * control_2.y + d * to.y,
+        ));
+    }
+}
+
+/// Approximates the path with polylines in user space, using a tolerance suitable for
+/// drawing it with the given user-to-layout transform.
+pub fn flatten(path: &Path, transform: &euclid::Transform2D<f32>) -> Vec<Polyline> {
+    let scale = transform_scale(transform);
+    let tolerance = if scale > 0.0 {
+        FLATTEN_TOLERANCE / scale
+    } else {
+        FLATTEN_TOLERANCE
+    };
+
+    path.subpaths
+        .iter()
+        .map(|subpath| {
+            let mut points = vec![subpath.start];
+            let mut current = subpath.start;
+            for segment in subpath.segments.iter() {
+                match *segment {
+                    PathSegment::LineTo(to) => points.push(to),
+                    PathSegment::CurveTo(control_1, control_2, to) => {
+                        flatten_cubic(&mut points, current, control_1, control_2, to, tolerance)
+                    }
+                }
+                current = segment.end_point();
+            }
+            Polyline {
+                points,
+                closed: subpath.closed,
+            }
+        })
+        .collect()
+}
+
+/// Splits the polylines into the dashes described by `pattern`, restarting the pattern
+/// for every subpath.
+pub fn dash(polylines: &[Polyline], pattern: &DashPattern) -> Vec<Polyline> {
+    let array = &pattern.array;
+    let cycle = dash_cycle(pattern);
+
+    let mut dashes = Vec::new();
+    'polylines: for polyline in polylines {
+        let first_dash = dashes.len();
+        let mut index = 0;
+        let mut on = true;
+        let mut remaining = array[0];
+        let mut phase = pattern.phase.rem_euclid(cycle);
+        while phase > 0.0 {
+            if phase < remaining {
+                remaining -= phase;
+                break;
+            }
+            phase -= remaining;
+            index = (index + 1) % array.len();
+            on = !on;
+            remaining = array[index];
+        }
+
+        let mut points = polyline.points.clone();
+        if polyline.closed && points.len() > 1 {
+            points.push(points[0]);
+        }
+        let mut current = if on {
+            points.first().map(|point| vec![*point])
+        } else {
+            None
+        };
+        for (segment, pair) in points.windows(2).enumerate() {
+            let length = (pair[1] - pair[0]).length();
+            if length == 0.0 {
+                continue;
+            }
+            let mut position = 0.0;
+            loop {
+                // dashes too short to advance the position would never end the segment
+                if dashes.len() - first_dash >= MAX_DASHES {
+                    log::warn!("drawing the rest of a subpath with too many dashes solid");
+                    let mut rest = current.take().unwrap_or_default();
+                    rest.push(pair[0].lerp(pair[1], position / length));
+                    rest.extend_from_slice(&points[segment + 1..]);
+                    dashes.push(Polyline {
+                        points: rest,
+                        closed: false,
+                    });
+                    continue 'polylines;
+                }
+                if remaining > length - position {
+                    remaining -= length - position;
+                    if let Some(ref mut dash) = current {
+                        dash.push(pair[1]);
+                    }
+                    break;
+                }
+                position += remaining;
+                let point = pair[0].lerp(pair[1], position / length);
+                match current.take() {
+                    Some(mut dash) => {
+                        dash.push(point);
+                        dashes.push(Polyline {
+                            points: dash,
+                            closed: false,
+                        });
+                    }
+                    None => current = Some(vec![point]),
+                }
+                index = (index + 1) % array.len();
+                on = !on;
+                remaining = array[index];
+            }
+        }
+        if let Some(dash) = current {
+            if dash.len() > 1 {
+                dashes.push(Polyline {
+                    points: dash,
+                    closed: false,
+                });
+            }
+        }
+    }
+    dashes
+}
+
+/// Returns the length after which a dash pattern repeats.
+fn dash_cycle(pattern: &DashPattern) -> f32 {
+    let cycle: f32 = pattern.array.iter().sum();
+    if pattern.array.len() % 2 == 1 {
+        // odd arrays swap dashes and gaps on every repetition
+        cycle * 2.0
+    } else {
+        cycle
+    }
+}
+
+struct Stroker<'a> {
+    style: &'a LineStyle,
+    half_width: f32,
+    /// Maximum error of round caps and joins, in user space.
+    tolerance: f32,
+    polygons: Vec<Vec<euclid::Point2D<f32>>>,
+}
+
+impl<'a> Stroker<'a> {
+    fn push_circle(&mut self, center: euclid::Point2D<f32>) {
+        let ratio = (1.0 - self.tolerance / self.half_width).max(-1.0);
+        let segments = ((std::f32::consts::PI / ratio.acos()).ceil() as usize).clamp(8, 128);
+        let polygon = (0..segments)
+            .map(|i| {
+                let angle = i as f32 * 2.0 * std::f32::consts::PI / segments as f32;
+                center + euclid::Vector2D::new(angle.cos(), angle.sin()) * self.half_width
+            })
+            .collect();
+        self.polygons.push(polygon);
+    }
+
+    /// Adds the square cap of a zero-length subpath, whose direction is undefined.
+    fn push_square(&mut self, center: euclid::Point2D<f32>) {
+        let half_width = self.half_width;
+        self.polygons.push(vec![
+            center + euclid::Vector2D::new(-half_width, -half_width),
+            center + euclid::Vector2D::new(half_width, -half_width),
+            center + euclid::Vector2D::new(half_width, half_width),
+            center + euclid::Vector2D::new(-half_width, half_width),
+        ]);
+    }
+
+    /// Fills the gap on the outside of the corner between two segments, given their
+    /// directions and their normals scaled to half the line width.
+    fn push_join(
+        &mut self,
+        point: euclid::Point2D<f32>,
+        directions: (euclid::Vector2D<f32>, euclid::Vector2D<f32>),
+        normals: (euclid::Vector2D<f32>, euclid::Vector2D<f32>),
+    ) {
+        let cross = directions.0.cross(directions.1);
+        if cross == 0.0 && directions.0.dot(directions.1) > 0.0 {
+            return;
+        }
+        if self.style.join == LineJoin::Round {
+            self.push_circle(point);
+            return;
+        }
+        if cross == 0.0 {
+            // the path reverses its direction, which bevel and miter joins leave open
+            return;
+        }
+
+        // normals point to the left, so a left turn has its outside on the right
+        let side = if cross > 0.0 { -1.0 } else { 1.0 };
+        let (outer_0, outer_1) = (normals.0 * side, normals.1 * side);
+        if self.style.join == LineJoin::Miter {
+            let cos_angle = normals.0.dot(normals.1) / (self.half_width * self.half_width);
+            let cos_half_angle = ((1.0 + cos_angle) / 2.0).max(0.0).sqrt();
+            if cos_half_angle > 0.0 && 1.0 / cos_half_angle <= self.style.miter_limit {
+                let bisector = outer_0 + outer_1;
+                let tip = point + bisector * (self.half_width / cos_half_angle / bisector.length());
+                self.polygons
+                    .push(vec![point, point + outer_0, tip, point + outer_1]);
+                return;
+            }
+        }
+        self.polygons
+            .push(vec![point, point + outer_0, point + outer_1]);
+    }
+
+    fn stroke_polyline(&mut self, polyline: &Polyline) {
+        let mut points = polyline.points.clone();
+        points.dedup();
+        if points.len() == 1 {
+            match self.style.cap {
+                LineCap::Round => self.push_circle(points[0]),
+                LineCap::ProjectingSquare => self.push_square(points[0]),
+                LineCap::Butt => {}
+            }
+            return;
+        }
+        let closed = polyline.closed && points.len() > 2;
+        if closed && points.first() == points.last() {
+            points.pop();
+        }
+        if closed {
+            points.push(points[0]);
+        }
+
+        let segments = points
+            .windows(2)
+            .map(|pair| {
+                let direction = pair[1] - pair[0];
+                let normal = euclid::Vector2D::new(-direction.y, direction.x)
+                    * (self.half_width / direction.length());
+                (pair[0], pair[1], direction, normal)
+            })
+            .collect::<Vec<_>>();
+
+        let last = segments.len() - 1;
+        for (index, &(mut from, mut to, direction, normal)) in segments.iter().enumerate() {
+            if !closed && self.style.cap == LineCap::ProjectingSquare {
+                let extension = direction * (self.half_width / direction.length());
+                if index == 0 {
+                    from -= extension;
+                }
+                if index == last {
+                    to += extension;
+                }
+            }
+            self.polygons
+                .push(vec![from + normal, to + normal, to - normal, from - normal]);
+        }
+
+        for pair in segments.windows(2) {
+            self.push_join(pair[1].0, (pair[0].2, pair[1].2), (pair[0].3, pair[1].3));
+        }
+        if closed {
+            let (first, last) = (segments[0], segments[last]);
+            self.push_join(first.0, (last.2, first.2), (last.3, first.3));
+        } else if self.style.cap == LineCap::Round {
+            self.push_circle(points[0]);
+            self.push_circle(points[points.len() - 1]);
+        }
+    }
+}
+
+/// Outlines the polylines with the given line style as a set of polygons, which
+/// together cover the stroke under the non-zero rule once their winding is normalized.
+///
+/// `scale` is the factor from user space to layout pixels, which determines the width
+/// of hairlines and the precision of round caps and joins.
+pub fn stroke(
+    polylines: &[Polyline],
+    style: &LineStyle,
+    scale: f32,
+) -> Vec<Vec<euclid::Point2D<f32>>> {
+    let scale = if scale > 0.0 { scale } else { 1.0 };
+    // a width of zero asks for the thinnest line that can be rendered, a pixel wide
+    let width = if style.width > 0.0 {
+        style.width
+    } else {
+        1.0 / scale
+    };
+    let mut stroker = Stroker {
+        style,
+        half_width: width / 2.0,
+        tolerance: FLATTEN_TOLERANCE / scale,
+        polygons: Vec::new(),
+    };
+
+    if style.dash.is_dashed() && dash_cycle(&style.dash) * scale >= MIN_DASH_CYCLE {
+        for polyline in dash(polylines, &style.dash).iter() {
+            stroker.stroke_polyline(polyline);
+        }
+    } else {
+        for polyline in polylines {
+            stroker.stroke_polyline(polyline);
+        }
+    }
+    stroker.polygons
+}
+
+/// Rasterizes the fill and the stroke of a path object, where it has them.
+pub fn rasterize_path(
+    path_object: &PathObject,
+    transform: &euclid::Transform2D<f32>,
+    bounds: &euclid::Rect<f32>,
+) -> (Option<CoverageMask>, Option<CoverageMask>) {
+    let polylines = flatten(&path_object.path, transform);
+
+    let fill_mask = path_object.fill.and_then(|fill_rule| {
+        let mut polygons = polylines
+            .iter()
+            .map(|polyline| polyline.points.clone())
+            .collect::<Vec<_>>();
+        transform_polygons(&mut polygons, transform);
+        rasterize(&polygons, fill_rule, bounds)
+    });
+
+    let stroke_mask = if path_object.stroke {
+        rasterize_stroke(path_object, &polylines, transform, bounds)
+    } else {
+        None
+    };
+
+    (fill_mask, stroke_mask)
+}
+
+/// Rasterizes the stroke of a path object, given its flattened path.
+pub fn rasterize_stroke(
+    path_object: &PathObject,
+    polylines: &[Polyline],
+    transform: &euclid::Transform2D<f32>,
+    bounds: &euclid::Rect<f32>,
+) -> Option<CoverageMask> {
+    let mut polygons = stroke(
+        polylines,
+        &path_object.line_style,
+        transform_scale(transform),
+    );
+    transform_polygons(&mut polygons, transform);
+    normalize_winding(&mut polygons);
+    rasterize(&polygons, FillRule::NonZero, bounds)
+}
+
+/// Rasterizes a clipping path, given the transform from page to layout space.
+pub fn rasterize_clip_path(
+    clip_path: &ClipPath,
+    page_transform: &euclid::Transform2D<f32>,
+    bounds: &euclid::Rect<f32>,
+) -> Option<CoverageMask> {
+    let transform = clip_path.transform.post_mul(page_transform);
+    let mut polygons = flatten(&clip_path.path, &transform)
+        .into_iter()
+        .map(|polyline| polyline.points)
+        .collect::<Vec<_>>();
+    transform_polygons(&mut polygons, &transform);
+    rasterize(&polygons, clip_path.fill_rule, bounds)
+}
+
+/// Returns the part of `bounds` that isn't clipped away by the bounding boxes of the
+/// clipping paths, or `None` if nothing remains.
+pub fn clip_bounds(
+    clip: &Clip,
+    page_transform: &euclid::Transform2D<f32>,
+    bounds: &euclid::Rect<f32>,
+) -> Option<euclid::Rect<f32>> {
+    let mut clip_bounds = *bounds;
+    for clip_path in clip.paths() {
+        let transform = clip_path.transform.post_mul(page_transform);
+        let points = flatten(&clip_path.path, &transform)
+            .into_iter()
+            .flat_map(|polyline| polyline.points)
+            .map(|point| transform.transform_point(&point))
+            .collect::<Vec<_>>();
+        clip_bounds = clip_bounds.intersection(&euclid::Rect::from_points(points))?;
+    }
+    Some(clip_bounds)
+}
+
+pub fn transform_polygons(
+    polygons: &mut [Vec<euclid::Point2D<f32>>],
+    transform: &euclid::Transform2D<f32>,
+) {
+    for polygon in polygons.iter_mut() {
+        for point in polygon.iter_mut() {
+            *point = transform.transform_point(point);
+        }
+    }
+}
+
+/// Makes sure every polygon winds the same way, so that overlapping outlines produced
+/// by the stroker add up instead of cancelling each other out under the non-zero rule.
+pub fn normalize_winding(polygons: &mut [Vec<euclid::Point2D<f32>>]) {
+    for polygon in polygons.iter_mut() {
+        let mut area = 0.0;
+        for (i, point) in polygon.iter().enumerate() {
+            let next = polygon[(i + 1) % polygon.len()];
+            area += point.x * next.y - next.x * point.y;
+        }
+        if area < 0.0 {
+            polygon.reverse();
+        }
+    }
+}
+
+/// Anti-aliased coverage of a set of polygons, covering a rectangle of whole pixels.
+pub struct CoverageMask {
+    pub origin: euclid::Point2D<i32>,
+    pub width: usize,
+    pub height: usize,
+    /// Coverage of every pixel, from 0 to 255.
+    pub coverage: Vec<u8>,
+}
+
+impl CoverageMask {
+    /// Returns the coverage of the pixel at `index` as a fraction.
+    pub fn value(&self, index: usize) -> f32 {
+        f32::from(self.coverage[index]) / 255.0
+    }
+}
+
+struct Edge {
+    top: euclid::Point2D<f32>,
+    bottom: euclid::Point2D<f32>,
+    winding: i32,
+}
+
+impl Edge {
+    fn x_at(&self, y: f32) -> f32 {
+        let t = (y - self.top.y) / (self.bottom.y - self.top.y);
+        self.top.x + t * (self.bottom.x - self.top.x)
+    }
+}
+
+fn accumulate_span(row: &mut [f32], start: f32, end: f32, weight: f32) {
+    let start = start.max(0.0);
+    let end = end.min(row.len() as f32);
+    if start >= end {
+        return;
+    }
+    let first = start.floor() as usize;
+    let last = end.floor() as usize;
+    if first == last {
+        row[first] += (end - start) * weight;
+        return;
+    }
+    row[first] += (first as f32 + 1.0 - start) * weight;
+    for value in row[first + 1..last].iter_mut() {
+        *value += weight;
+    }
+    if last < row.len() {
+        row[last] += (end - last as f32) * weight;
+    }
+}
+
+/// Computes the coverage of the polygons (in layout pixels) within the given bounds.
+pub fn rasterize(
+    polygons: &[Vec<euclid::Point2D<f32>>],
+    fill_rule: FillRule,
+    bounds: &euclid::Rect<f32>,
+) -> Option<CoverageMask> {
+    let mut edges = Vec::new();
+    let mut min = euclid::Point2D::new(f32::MAX, f32::MAX);
+    let mut max = euclid::Point2D::new(f32::MIN, f32::MIN);
+
+    for polygon in polygons {
+        for (i, &point) in polygon.iter().enumerate() {
+            min = min.min(point);
+            max = max.max(point);
+            let next = polygon[(i + 1) % polygon.len()];
+            if point.y < next.y {
+                edges.push(Edge {
+                    top: point,
+                    bottom: next,
+                    winding: 1,
+                });
+            } else if point.y > next.y {
+                edges.push(Edge {
+                    top: next,
+                    bottom: point,
+                    winding: -1,
+                });
+            }
+        }
+    }
+
+    let min_x = min.x.max(bounds.min_x()).floor() as i32;
+    let min_y = min.y.max(bounds.min_y()).floor() as i32;
+    let max_x = max.x.min(bounds.max_x()).ceil() as i32;
+    let max_y = max.y.min(bounds.max_y()).ceil() as i32;
+    if edges.is_empty() || max_x <= min_x || max_y <= min_y {
+        return None;
+    }
+
+    let width = (max_x - min_x) as usize;
+    let height = (max_y - min_y) as usize;
+    // coverage is accumulated a row at a time and stored with 8 bits per pixel
+    let mut coverage = vec![0; width * height];
+    let mut row = vec![0.0; width];
+    let mut crossings = Vec::new();
+    let weight = 1.0 / SUBSAMPLES as f32;
+
+    for (row_index, output) in coverage.chunks_mut(width).enumerate() {
+        for value in row.iter_mut() {
+            *value = 0.0;
+        }
+        for sample in 0..SUBSAMPLES {
+            let y = min_y as f32 + row_index as f32 + (sample as f32 + 0.5) * weight;
+
+            crossings.clear();
+            for edge in edges.iter() {
+                if edge.top.y <= y && y < edge.bottom.y {
+                    crossings.push((edge.x_at(y) - min_x as f32, edge.winding));
+                }
+            }
+            crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+            let mut winding = 0;
+            let mut span_start = 0.0;
+            for &(x, direction) in crossings.iter() {
+                let was_inside = is_inside(winding, fill_rule);
+                winding += direction;
+                let inside = is_inside(winding, fill_rule);
+                if !was_inside && inside {
+                    span_start = x;
+                } else if was_inside && !inside {
+                    accumulate_span(&mut row, span_start, x, weight);
+                }
+            }
+        }
+        for (output, value) in output.iter_mut().zip(row.iter()) {
+            *output = (value.min(1.0) * 255.0).round() as u8;
+        }
+    }
+
+    Some(CoverageMask {
+        origin: euclid::Point2D::new(min_x, min_y),
+        width,
+        height,
+        coverage,
+    })
+}
+
+fn is_inside(winding: i32, fill_rule: FillRule) -> bool {
+    match fill_rule {
+        FillRule::NonZero => winding != 0,
+        FillRule::EvenOdd => winding % 2 != 0,
+    }
+}
+
+/// Premultiplied BGRA pixels covering a rectangle of whole layout pixels.
+pub struct Bitmap {
+    pub origin: euclid::Point2D<i32>,
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+impl Bitmap {
+    pub fn rect(&self) -> euclid::Rect<f32> {
+        euclid::Rect::new(
+            euclid::Point2D::new(self.origin.x as f32, self.origin.y as f32),
+            euclid::Size2D::new(self.width as f32, self.height as f32),
+        )
+    }
+
+    /// Multiplies the (premultiplied) pixels with a constant opacity.
+    pub fn apply_alpha(&mut self, alpha: f32) {
+        if alpha < 1.0 {
+            for value in self.data.iter_mut() {
+                *value = (f32::from(*value) * alpha).round() as u8;
+            }
+        }
+    }
+}
+
+/// Converts non-premultiplied RGBA pixels to premultiplied BGRA.
+pub fn premultiply(pixels: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(pixels.len());
+    for pixel in pixels.chunks(4) {
+        let alpha = u32::from(pixel[3]);
+        for channel in [pixel[2], pixel[1], pixel[0]].iter() {
+            data.push(((u32::from(*channel) * alpha + 127) / 255) as u8);
+        }
+        data.push(pixel[3]);
+    }
+    data
+}
+
+/// Resamples an image drawn through an arbitrary transform from its unit square into
+/// layout pixels within the given bounds, using nearest-neighbour sampling.
+pub fn transform_image(
+    image: &ImageObject,
+    transform: &euclid::Transform2D<f32>,
+    bounds: &euclid::Rect<f32>,
+) -> Option<Bitmap> {
+    let inverse = transform.inverse()?;
+    let corners = [
+        euclid::Point2D::new(0.0, 0.0),
+        euclid::Point2D::new(1.0, 0.0),
+        euclid::Point2D::new(1.0, 1.0),
+        euclid::Point2D::new(0.0, 1.0),
+    ];
+    let mut min = euclid::Point2D::new(f32::MAX, f32::MAX);
+    let mut max = euclid::Point2D::new(f32::MIN, f32::MIN);
+    for corner in corners.iter() {
+        let point = transform.transform_point(corner);
+        min = min.min(point);
+        max = max.max(point);
+    }
+
+    let min_x = min.x.max(bounds.min_x()).floor() as i32;
+    let min_y = min.y.max(bounds.min_y()).floor() as i32;
+    let max_x = max.x.min(bounds.max_x()).ceil() as i32;
+    let max_y = max.y.min(bounds.max_y()).ceil() as i32;
+    if image.width == 0 || image.height == 0 || max_x <= min_x || max_y <= min_y {
+        return None;
+    }
+
+    let width = (max_x - min_x) as usize;
+    let height = (max_y - min_y) as usize;
+    let mut pixels = vec![0; width * height * 4];
+    for (row, line) in pixels.chunks_mut(width * 4).enumerate() {
+        for (column, pixel) in line.chunks_mut(4).enumerate() {
+            let center = euclid::Point2D::new(
+                min_x as f32 + column as f32 + 0.5,
+                min_y as f32 + row as f32 + 0.5,
+            );
+            let point = inverse.transform_point(&center);
+            if point.x < 0.0 || point.x >= 1.0 || point.y <= 0.0 || point.y > 1.0 {
+                continue;
+            }
+            // the first row of samples is at the top of the unit square
+            let x = ((point.x * image.width as f32) as usize).min(image.width as usize - 1);
+            let y =
+                (((1.0 - point.y) * image.height as f32) as usize).min(image.height as usize - 1);
+            let index = (y * image.width as usize + x) * 4;
+            pixel.copy_from_slice(&image.pixels[index..index + 4]);
+        }
+    }
+
+    Some(Bitmap {
+        origin: euclid::Point2D::new(min_x, min_y),
+        width,
+        height,
+        data: premultiply(&pixels),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rasterize_square() {
+        let square = vec![
+            euclid::Point2D::new(1.0, 1.0),
+            euclid::Point2D::new(3.0, 1.0),
+            euclid::Point2D::new(3.0, 3.5),
+            euclid::Point2D::new(1.0, 3.5),
+        ];
+        let bounds = euclid::Rect::new(euclid::Point2D::zero(), euclid::Size2D::new(10.0, 10.0));
+        let mask = rasterize(&[square], FillRule::NonZero, &bounds).unwrap();
+        assert_eq!(euclid::Point2D::new(1, 1), mask.origin);
+        assert_eq!((2, 3), (mask.width, mask.height));
+        assert_eq!(vec![255, 255, 255, 255, 128, 128], mask.coverage);
+    }
+
+    #[test]
+    fn rasterize_even_odd_hole() {
+        let outer = vec![
+            euclid::Point2D::new(0.0, 0.0),
+            euclid::Point2D::new(3.0, 0.0),
+            euclid::Point2D::new(3.0, 3.0),
+            euclid::Point2D::new(0.0, 3.0),
+        ];
+        let inner = vec![
+            euclid::Point2D::new(1.0, 1.0),
+            euclid::Point2D::new(2.0, 1.0),
+            euclid::Point2D::new(2.0, 2.0),
+            euclid::Point2D::new(1.0, 2.0),
+        ];
+        let bounds = euclid::Rect::new(euclid::Point2D::zero(), euclid::Size2D::new(10.0, 10.0));
+        let mask = rasterize(&[outer, inner], FillRule::EvenOdd, &bounds).unwrap();
+        assert_eq!(0, mask.coverage[4]);
+        assert_eq!(255, mask.coverage[3]);
+    }
+
+    #[test]
+    fn transform_rotated_image() {
+        let image = ImageObject {
+            transform: euclid::Transform2D::identity(),
+            width: 2,
+            height: 1,
+            pixels: vec![255, 0, 0, 255, 0, 0, 255, 128],
+            transparency: Default::default(),
+            clip: Default::default(),
+        };
+        // rotate by 90 degrees and scale the unit square to 2x2 pixels
+        let transform = euclid::Transform2D::row_major(0.0, 2.0, -2.0, 0.0, 2.0, 0.0);
+        let bounds = euclid::Rect::new(euclid::Point2D::zero(), euclid::Size2D::new(10.0, 10.0));
+        let bitmap = transform_image(&image, &transform, &bounds).unwrap();
+        assert_eq!(euclid::Point2D::new(0, 0), bitmap.origin);
+        assert_eq!((2, 2), (bitmap.width, bitmap.height));
+        assert_eq!(&[0, 0, 255, 255], &bitmap.data[0..4]);
+        assert_eq!(&[128, 0, 0, 128], &bitmap.data[8..12]);
+    }
+
+    fn polyline(points: &[(f32, f32)], closed: bool) -> Polyline {
+        Polyline {
+            points: points
+                .iter()
+                .map(|&(x, y)| euclid::Point2D::new(x, y))
+                .collect(),
+            closed,
+        }
+    }
+
+    #[test]
+    fn dash_with_phase() {
+        let pattern = DashPattern {
+            array: vec![2.0, 1.0],
+            phase: 1.0,
+        };
+        let dashes = dash(
+            &[polyline(&[(0.0, 0.0), (4.0, 0.0), (4.0, 2.0)], false)],
+            &pattern,
+        );
+        let points = dashes
+            .iter()
+            .map(|dash| dash.points.iter().map(|p| (p.x, p.y)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                vec![(0.0, 0.0), (1.0, 0.0)],
+                vec![(2.0, 0.0), (4.0, 0.0)],
+                vec![(4.0, 1.0), (4.0, 2.0)],
+            ],
+            points
+        );
+    }
+
+    #[test]
+    fn tiny_dashes_are_bounded() {
+        let pattern = DashPattern {
+            array: vec![1e-10],
+            phase: 0.0,
+        };
+        let line = [polyline(&[(0.0, 0.0), (10.0, 0.0)], false)];
+        let dashes = dash(&line, &pattern);
+        assert_eq!(MAX_DASHES + 1, dashes.len());
+        let rest = dashes.last().unwrap();
+        assert_eq!((10.0, 0.0), {
+            let end = rest.points.last().unwrap();
+            (end.x, end.y)
+        });
+
+        let style = LineStyle {
+            dash: pattern,
+            ..LineStyle::default()
+        };
+        assert_eq!(
+            stroke(&line, &LineStyle::default(), 1.0),
+            stroke(&line, &style, 1.0)
+        );
+    }
+
+    #[test]
+    fn miter_join_respects_limit() {
+        let corner = [polyline(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)], false)];
+        let mut style = LineStyle {
+            width: 2.0,
+            ..LineStyle::default()
+        };
+        let polygons = stroke(&corner, &style, 1.0);
+        // two segments and the miter, which reaches the outer corner of the square
+        assert_eq!(3, polygons.len());
+        let tip = polygons[2][2];
+        assert!((tip.x - 11.0).abs() < 1e-5 && (tip.y + 1.0).abs() < 1e-5);
+
+        // a right angle needs a miter limit of at least sqrt(2)
+        style.miter_limit = 1.4;
+        assert_eq!(3, stroke(&corner, &style, 1.0)[2].len());
+    }
+
+    #[test]
+    fn hairline_is_one_pixel_wide() {
+        let style = LineStyle {
+            width: 0.0,
+            ..LineStyle::default()
+        };
+        let polygons = stroke(&[polyline(&[(0.0, 0.0), (10.0, 0.0)], false)], &style, 4.0);
+        assert_eq!(1, polygons.len());
+        assert_eq!(0.125, polygons[0][0].y);
+        assert_eq!(-0.125, polygons[0][2].y);
+    }
+}
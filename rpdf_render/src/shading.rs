@@ -0,0 +1,376 @@
+use webrender::api::units::*;
+use webrender::api::{ColorF, GradientStop};
+
+use rpdf_graphics::color::Rgb;
+use rpdf_graphics::shading::{Shading, ShadingKind};
+
+use super::raster::{self, Bitmap};
+
+/// Number of colour stops used to approximate the function of a gradient.
+const GRADIENT_STOPS: usize = 64;
+
+/// Number of intervals in each direction of the grid on which function-based shadings
+/// are evaluated.
+const FUNCTION_GRID_SIZE: usize = 64;
+
+/// Number of colours sampled along the parameter of rasterized axial and radial shadings.
+const COLOR_TABLE_SIZE: usize = 256;
+
+/// A shading that can be drawn as a webrender gradient.
+pub enum Gradient {
+    Linear {
+        start: LayoutPoint,
+        end: LayoutPoint,
+        stops: Vec<GradientStop>,
+    },
+    Radial {
+        center: LayoutPoint,
+        radius: LayoutSize,
+        stops: Vec<GradientStop>,
+    },
+}
+
+/// Samples the colours of an axial or radial shading between the start (`s = 0`) and
+/// end (`s = 1`) of its extent, placing them at `offset(s)`. Sides that aren't extended
+/// get a hard stop to transparent.
+fn gradient_stops(
+    shading: &Shading,
+    domain: [f32; 2],
+    extend: [bool; 2],
+    alpha: f32,
+    offset: impl Fn(f32) -> f32,
+) -> Vec<GradientStop> {
+    let transparent = ColorF::new(0.0, 0.0, 0.0, 0.0);
+    let mut stops = Vec::with_capacity(GRADIENT_STOPS + 3);
+    if !extend[0] {
+        stops.push(GradientStop {
+            offset: offset(0.0),
+            color: transparent,
+        });
+    }
+    for index in 0..=GRADIENT_STOPS {
+        let s = index as f32 / GRADIENT_STOPS as f32;
+        let rgb = shading.color(&[domain[0] + s * (domain[1] - domain[0])]);
+        stops.push(GradientStop {
+            offset: offset(s),
+            color: ColorF::new(rgb.red, rgb.green, rgb.blue, alpha),
+        });
+    }
+    if !extend[1] {
+        stops.push(GradientStop {
+            offset: offset(1.0),
+            color: transparent,
+        });
+    }
+    stops
+}
+
+/// Converts an axial or radial shading to a gradient in layout space, given the
+/// transform from shading space to layout space.
+///
+/// Returns `None` if the shading has no equivalent gradient, as is the case for
+/// function-based and mesh shadings, and radial shadings whose circles aren't concentric.
+pub fn gradient(
+    shading: &Shading,
+    transform: &euclid::Transform2D<f32>,
+    alpha: f32,
+) -> Option<Gradient> {
+    match shading.kind {
+        ShadingKind::FunctionBased { .. } | ShadingKind::Mesh { .. } => None,
+        ShadingKind::Axial {
+            start,
+            end,
+            domain,
+            extend,
+        } => {
+            // the parameter is an affine function of the layout position, whose gradient
+            // is the axis mapped through the inverse transpose of the transform
+            let axis = end - start;
+            let inverse = transform.inverse()?;
+            let direction = euclid::Vector2D::new(
+                inverse.m11 * axis.x + inverse.m12 * axis.y,
+                inverse.m21 * axis.x + inverse.m22 * axis.y,
+            ) / axis.square_length();
+            if !direction.square_length().is_normal() {
+                return None;
+            }
+            let start = transform.transform_point(&start);
+            let end = start + direction / direction.square_length();
+            Some(Gradient::Linear {
+                start: LayoutPoint::from_untyped(&start),
+                end: LayoutPoint::from_untyped(&end),
+                stops: gradient_stops(shading, domain, extend, alpha, |s| s),
+            })
+        }
+        ShadingKind::Radial {
+            start,
+            end,
+            domain,
+            extend,
+        } => {
+            let axis_aligned = transform.m12 == 0.0 && transform.m21 == 0.0;
+            if start.center != end.center || end.radius <= start.radius || !axis_aligned {
+                return None;
+            }
+            let radius = end.radius;
+            let center = transform.transform_point(&start.center);
+            Some(Gradient::Radial {
+                center: LayoutPoint::from_untyped(&center),
+                radius: LayoutSize::new(radius * transform.m11.abs(), radius * transform.m22.abs()),
+                stops: gradient_stops(shading, domain, extend, alpha, |s| {
+                    (start.radius + s * (end.radius - start.radius)) / radius
+                }),
+            })
+        }
+    }
+}
+
+fn pixel(rgb: Rgb) -> [u8; 4] {
+    [
+        (rgb.red * 255.0).round() as u8,
+        (rgb.green * 255.0).round() as u8,
+        (rgb.blue * 255.0).round() as u8,
+        255,
+    ]
+}
+
+/// Maps the centre of a layout pixel to the colour of a shading there.
+type ColorFunction<'a> = Box<dyn Fn(euclid::Point2D<f32>) -> Option<[u8; 4]> + 'a>;
+
+/// Returns a function that maps the centre of a layout pixel to shading space, or to the
+/// domain of a function-based shading, and returns its colour there. Mesh shadings
+/// don't have such a mapping.
+fn color_function<'a>(
+    shading: &'a Shading,
+    transform: &euclid::Transform2D<f32>,
+) -> Option<ColorFunction<'a>> {
+    let color_at: ColorFunction<'a> = match shading.kind {
+        ShadingKind::FunctionBased { domain, matrix } => {
+            let inverse = matrix.post_mul(transform).inverse()?;
+            let mut grid = Vec::with_capacity((FUNCTION_GRID_SIZE + 1) * (FUNCTION_GRID_SIZE + 1));
+            for row in 0..=FUNCTION_GRID_SIZE {
+                for column in 0..=FUNCTION_GRID_SIZE {
+                    let x = domain.min_x()
+                        + domain.size.width * column as f32 / FUNCTION_GRID_SIZE as f32;
+                    let y = domain.min_y()
+                        + domain.size.height * row as f32 / FUNCTION_GRID_SIZE as f32;
+                    grid.push(shading.color(&[x, y]));
+                }
+            }
+            Box::new(move |center| {
+                let point = inverse.transform_point(&center);
+                if point.x < domain.min_x()
+                    || point.x > domain.max_x()
+                    || point.y < domain.min_y()
+                    || point.y > domain.max_y()
+                {
+                    return None;
+                }
+                let scale = FUNCTION_GRID_SIZE as f32;
+                let x = ((point.x - domain.min_x()) / domain.size.width * scale).max(0.0);
+                let y = ((point.y - domain.min_y()) / domain.size.height * scale).max(0.0);
+                let column = (x as usize).min(FUNCTION_GRID_SIZE - 1);
+                let row = (y as usize).min(FUNCTION_GRID_SIZE - 1);
+                let (fx, fy) = (x - column as f32, y - row as f32);
+                let at = |row: usize, column: usize| grid[row * (FUNCTION_GRID_SIZE + 1) + column];
+                let mix = |a: Rgb, b: Rgb, f: f32| {
+                    Rgb::new(
+                        a.red + (b.red - a.red) * f,
+                        a.green + (b.green - a.green) * f,
+                        a.blue + (b.blue - a.blue) * f,
+                    )
+                };
+                let top = mix(at(row, column), at(row, column + 1), fx);
+                let bottom = mix(at(row + 1, column), at(row + 1, column + 1), fx);
+                Some(pixel(mix(top, bottom, fy)))
+            })
+        }
+        ShadingKind::Axial { domain, .. } | ShadingKind::Radial { domain, .. } => {
+            let inverse = transform.inverse()?;
+            let table = (0..COLOR_TABLE_SIZE)
+                .map(|index| {
+                    let s = index as f32 / (COLOR_TABLE_SIZE - 1) as f32;
+                    pixel(shading.color(&[domain[0] + s * (domain[1] - domain[0])]))
+                })
+                .collect::<Vec<_>>();
+            Box::new(move |center| {
+                let t = shading.parameter_at(inverse.transform_point(&center))?;
+                let s = if domain[1] == domain[0] {
+                    0.0
+                } else {
+                    (t - domain[0]) / (domain[1] - domain[0])
+                };
+                let index = (s * (COLOR_TABLE_SIZE - 1) as f32).round() as usize;
+                Some(table[index.min(COLOR_TABLE_SIZE - 1)])
+            })
+        }
+        ShadingKind::Mesh { .. } => return None,
+    };
+    Some(color_at)
+}
+
+/// Draws a triangle of a mesh shading with colours interpolated between its vertices,
+/// given in layout space.
+fn draw_triangle(
+    shading: &Shading,
+    vertices: [(euclid::Point2D<f32>, &[f32]); 3],
+    pixels: &mut [u8],
+    origin: euclid::Point2D<i32>,
+    size: euclid::Size2D<usize>,
+) {
+    let [(p0, c0), (p1, c1), (p2, c2)] = vertices;
+    let area = (p1 - p0).cross(p2 - p0);
+    if area == 0.0 || !area.is_finite() {
+        return;
+    }
+    let rect = euclid::Rect::from_points([p0, p1, p2]);
+    let min_x = (rect.min_x().floor() as i32).max(origin.x);
+    let min_y = (rect.min_y().floor() as i32).max(origin.y);
+    let max_x = (rect.max_x().ceil() as i32).min(origin.x + size.width as i32);
+    let max_y = (rect.max_y().ceil() as i32).min(origin.y + size.height as i32);
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let center = euclid::Point2D::new(x as f32 + 0.5, y as f32 + 0.5);
+            // barycentric coordinates, which are all positive inside the triangle
+            let w0 = (p2 - p1).cross(center - p1) / area;
+            let w1 = (p0 - p2).cross(center - p2) / area;
+            let w2 = 1.0 - w0 - w1;
+            if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                continue;
+            }
+            let values = c0
+                .iter()
+                .zip(c1.iter().zip(c2.iter()))
+                .map(|(v0, (v1, v2))| v0 * w0 + v1 * w1 + v2 * w2)
+                .collect::<Vec<_>>();
+            let index = ((y - origin.y) as usize * size.width + (x - origin.x) as usize) * 4;
+            pixels[index..index + 4].copy_from_slice(&pixel(shading.color(&values)));
+        }
+    }
+}
+
+/// Evaluates a shading for every layout pixel within `bounds`, given the transform from
+/// shading space to layout space.
+///
+/// Function-based shadings are evaluated on a grid spanning their domain, which is then
+/// interpolated bilinearly; axial and radial shadings are looked up in a table of
+/// colours, and the triangles of mesh shadings are drawn one after the other.
+pub fn rasterize_shading(
+    shading: &Shading,
+    transform: &euclid::Transform2D<f32>,
+    bounds: &euclid::Rect<f32>,
+) -> Option<Bitmap> {
+    let bounds = match shading.kind {
+        ShadingKind::FunctionBased { domain, matrix } => matrix
+            .post_mul(transform)
+            .transform_rect(&domain)
+            .intersection(bounds)?,
+        ShadingKind::Mesh { ref triangles } => {
+            let points = triangles
+                .iter()
+                .flat_map(|triangle| triangle.iter())
+                .map(|vertex| transform.transform_point(&vertex.point))
+                .collect::<Vec<_>>();
+            euclid::Rect::from_points(points).intersection(bounds)?
+        }
+        _ => *bounds,
+    };
+    let min_x = bounds.min_x().floor() as i32;
+    let min_y = bounds.min_y().floor() as i32;
+    let max_x = bounds.max_x().ceil() as i32;
+    let max_y = bounds.max_y().ceil() as i32;
+    if max_x <= min_x || max_y <= min_y {
+        return None;
+    }
+    let width = (max_x - min_x) as usize;
+    let height = (max_y - min_y) as usize;
+    let mut pixels = vec![0; width * height * 4];
+
+    match shading.kind {
+        ShadingKind::Mesh { ref triangles } => {
+            let origin = euclid::Point2D::new(min_x, min_y);
+            let size = euclid::Size2D::new(width, height);
+            for triangle in triangles {
+                let vertex = |index: usize| {
+                    let vertex = &triangle[index];
+                    (transform.transform_point(&vertex.point), &vertex.color[..])
+                };
+                let vertices = [vertex(0), vertex(1), vertex(2)];
+                draw_triangle(shading, vertices, &mut pixels, origin, size);
+            }
+        }
+        _ => {
+            let color_at = color_function(shading, transform)?;
+            for (row, line) in pixels.chunks_mut(width * 4).enumerate() {
+                for (column, pixel) in line.chunks_mut(4).enumerate() {
+                    let center = euclid::Point2D::new(
+                        min_x as f32 + column as f32 + 0.5,
+                        min_y as f32 + row as f32 + 0.5,
+                    );
+                    if let Some(color) = color_at(center) {
+                        pixel.copy_from_slice(&color);
+                    }
+                }
+            }
+        }
+    }
+    Some(Bitmap {
+        origin: euclid::Point2D::new(min_x, min_y),
+        width,
+        height,
+        data: raster::premultiply(&pixels),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lopdf::Object;
+
+    #[test]
+    fn axial_gradient_under_skewed_transform() {
+        let mut function = lopdf::Dictionary::new();
+        function.set("FunctionType", Object::Integer(2));
+        function.set(
+            "Domain",
+            Object::Array(vec![Object::Integer(0), Object::Integer(1)]),
+        );
+        function.set("N", Object::Integer(1));
+        let mut dict = lopdf::Dictionary::new();
+        dict.set("ShadingType", Object::Integer(2));
+        dict.set("ColorSpace", Object::Name(b"DeviceGray".to_vec()));
+        dict.set(
+            "Coords",
+            Object::Array(vec![
+                Object::Integer(0),
+                Object::Integer(0),
+                Object::Integer(10),
+                Object::Integer(10),
+            ]),
+        );
+        dict.set("Function", function);
+        let shading =
+            Shading::try_from_object(&lopdf::Document::new(), &Object::Dictionary(dict)).unwrap();
+
+        // stretch horizontally, which tilts the lines of constant colour
+        let transform = euclid::Transform2D::create_scale(2.0, 1.0);
+        match gradient(&shading, &transform, 1.0) {
+            Some(Gradient::Linear { start, end, stops }) => {
+                assert_eq!(LayoutPoint::zero(), start);
+                // the line of the end colour through (20, 10) crosses the gradient here
+                assert!((end.x - 8.0).abs() < 1e-4 && (end.y - 16.0).abs() < 1e-4);
+                assert_eq!(GRADIENT_STOPS + 3, stops.len());
+                assert_eq!(0.0, stops[0].color.a);
+            }
+            _ => panic!("expected a linear gradient"),
+        }
+
+        let bounds = euclid::Rect::new(euclid::Point2D::zero(), euclid::Size2D::new(20.0, 10.0));
+        let bitmap = rasterize_shading(&shading, &transform, &bounds).unwrap();
+        let at = |x: usize, y: usize| bitmap.data[(y * bitmap.width + x) * 4];
+        // the pixel centres (0.5, 0.5) and (9.5, 5.5) have the parameters 0.0375 and 0.5125
+        assert_eq!(10, at(0, 0));
+        assert_eq!(131, at(9, 5));
+    }
+}
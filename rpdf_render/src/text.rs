@@ -4,22 +4,17 @@ use app_units::Au;
 use webrender::api::*;
 
 use rpdf_document::Font;
+use rpdf_graphics::font::FontId;
 
 #[derive(Default)]
-pub struct FontRenderContext<'a> {
-    font_keys: HashMap<&'a [u8], FontKey>,
-    font_instance_keys: HashMap<(&'a [u8], Au), FontInstanceKey>,
+pub struct FontRenderContext {
+    font_keys: HashMap<FontId, FontKey>,
+    font_instance_keys: HashMap<(FontId, Au), FontInstanceKey>,
 }
 
-impl<'a> FontRenderContext<'a> {
-    pub fn load_font(
-        &mut self,
-        api: &RenderApi,
-        txn: &mut Transaction,
-        name: &'a [u8],
-        font: &Font,
-    ) -> FontKey {
-        *self.font_keys.entry(name).or_insert_with(|| {
+impl FontRenderContext {
+    pub fn load_font(&mut self, api: &RenderApi, txn: &mut Transaction, font: &Font) -> FontKey {
+        *self.font_keys.entry(font.id()).or_insert_with(|| {
             let key = api.generate_font_key();
             txn.add_raw_font(key, font.data().to_owned(), 0);
             key
@@ -30,17 +25,17 @@ impl<'a> FontRenderContext<'a> {
         &mut self,
         api: &RenderApi,
         txn: &mut Transaction,
-        name: &'a [u8],
+        font: &Font,
         size: f32,
     ) -> FontInstanceKey {
         let au = Au::from_f32_px(size);
         let font_keys = &self.font_keys;
         *self
             .font_instance_keys
-            .entry((name, au))
+            .entry((font.id(), au))
             .or_insert_with(|| {
                 let key = api.generate_font_instance_key();
-                let font_key = font_keys[name];
+                let font_key = font_keys[&font.id()];
                 txn.add_font_instance(key, font_key, au, None, None, vec![]);
                 key
             })
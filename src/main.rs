@@ -16,6 +16,10 @@ mod render;
 struct Opt {
     #[structopt(parse(from_os_str))]
     input: PathBuf,
+    /// Searches a directory for fonts to draw non-embedded fonts with, before the
+    /// system's font directories
+    #[structopt(long = "font-dir", parse(from_os_str))]
+    font_dirs: Vec<PathBuf>,
 }
 
 fn render<'env>(scope: &thread::Scope<'env>, document: &'env Document, title: &str) -> Fallible<()> {
@@ -191,6 +195,12 @@ fn main() -> Fallible<()> {
 
     let opt = Opt::from_args();
 
+    if !opt.font_dirs.is_empty() {
+        let mut font_dirs = opt.font_dirs.clone();
+        font_dirs.extend(rpdf_document::default_font_directories());
+        rpdf_document::set_font_directories(font_dirs);
+    }
+
     let input_file = File::open(&opt.input)?;
     let document = Document::parse(input_file)?;
 